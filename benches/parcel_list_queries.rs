@@ -0,0 +1,250 @@
+/*
+    Benchmark of the parcel list and the webhook followers queries, the old way (two finds and a join in memory) against the
+    aggregation pipelines in src/database_pipelines.rs
+
+    needs a mongod to run against, the database in BENCH_MONGODB_URI is dropped and filled with made up data:
+        BENCH_MONGODB_URI=mongodb://localhost:27017/teletrack_bench cargo bench --bench parcel_list_queries
+*/
+
+// not every pipeline is benchmarked
+#[allow(dead_code)]
+#[path = "../src/database_pipelines.rs"]
+mod database_pipelines;
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::ClientOptions,
+    Client, Database, IndexModel,
+};
+use std::{
+    collections::HashMap,
+    env,
+    time::{Duration, Instant},
+};
+
+// sizes of the made up data
+const PARCELS_PER_USER: [usize; 3] = [10, 100, 1000];
+const FOLLOWERS_PER_NUMBER: [usize; 3] = [10, 100, 1000];
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let uri = match env::var("BENCH_MONGODB_URI") {
+        Ok(uri) => uri,
+        Err(_) => {
+            println!("BENCH_MONGODB_URI not set, skipping");
+            return;
+        }
+    };
+
+    actix_web::rt::System::new().block_on(async move {
+        let options = ClientOptions::parse(&uri)
+            .await
+            .expect("invalid BENCH_MONGODB_URI");
+        let db_name = options
+            .default_database
+            .clone()
+            .unwrap_or_else(|| "teletrack_bench".to_string());
+        let client = Client::with_options(options).expect("failed to connect");
+        let db = client.database(&db_name);
+
+        for parcels in PARCELS_PER_USER {
+            seed(&db, parcels, 1).await;
+            let user_id_hash = "user-0";
+            let find_and_join = time(|| parcel_list_find_and_join(&db, user_id_hash)).await;
+            let pipeline = time(|| parcel_list_pipeline(&db, user_id_hash, false)).await;
+            let summary_pipeline = time(|| parcel_list_pipeline(&db, user_id_hash, true)).await;
+            println!(
+                "parcel list, {:>5} parcels:      find + join {:>9.2?}   $lookup {:>9.2?}   summary $lookup {:>9.2?}",
+                parcels, find_and_join, pipeline, summary_pipeline
+            );
+        }
+
+        for followers in FOLLOWERS_PER_NUMBER {
+            seed(&db, 1, followers).await;
+            let tracking_number = "NUMBER0";
+            let find_and_join = time(|| followers_find_and_join(&db, tracking_number)).await;
+            let pipeline = time(|| followers_pipeline(&db, tracking_number)).await;
+            println!(
+                "followers,   {:>5} followers:    find + join {:>9.2?}   $lookup {:>9.2?}",
+                followers, find_and_join, pipeline
+            );
+        }
+
+        db.drop(None)
+            .await
+            .expect("failed to drop the bench database");
+    });
+}
+
+/// average time of the query over the iterations
+async fn time<F, Fut>(query: F) -> Duration
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = usize>,
+{
+    // warm up
+    query().await;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        query().await;
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// fill the database with parcels tracked by users, every user tracks the first parcels and every parcel is followed by
+/// the first users
+async fn seed(db: &Database, parcels: usize, users: usize) {
+    db.drop(None)
+        .await
+        .expect("failed to drop the bench database");
+
+    let tracking_data: Vec<Document> = (0..parcels)
+        .map(|n| {
+            doc! {"data": {
+                "number": format!("NUMBER{}", n),
+                "carrier": 0,
+                "tag": null,
+                "track_info": {
+                    "latest_status": {"status": "InTransit"},
+                    "tracking": {"providers": [{"events": vec![doc! {"description": "x".repeat(200)}; 20]}]},
+                },
+            }}
+        })
+        .collect();
+    let users_docs: Vec<Document> = (0..users)
+        .map(|u| doc! {"user_id": u as i64, "user_id_hash": format!("user-{}", u), "user_name": "bench"})
+        .collect();
+    let relations: Vec<Document> = (0..users)
+        .flat_map(|u| {
+            (0..parcels).map(move |n| {
+                doc! {
+                    "tracking_number": format!("NUMBER{}", n),
+                    "user_id_hash": format!("user-{}", u),
+                    "is_subscribed": true,
+                    "label": null,
+                    "chat_id": null,
+                }
+            })
+        })
+        .collect();
+
+    db.collection::<Document>("tracking_data")
+        .insert_many(tracking_data, None)
+        .await
+        .expect("failed to seed");
+    db.collection::<Document>("users")
+        .insert_many(users_docs, None)
+        .await
+        .expect("failed to seed");
+    db.collection::<Document>("tracking_number_user_relation")
+        .insert_many(relations, None)
+        .await
+        .expect("failed to seed");
+
+    // same indexes as the server creates on start
+    for (collection, keys) in [
+        ("tracking_data", doc! {"data.number": 1}),
+        ("users", doc! {"user_id_hash": 1}),
+        ("tracking_number_user_relation", doc! {"user_id_hash": 1}),
+        ("tracking_number_user_relation", doc! {"tracking_number": 1}),
+    ] {
+        db.collection::<Document>(collection)
+            .create_index(IndexModel::builder().keys(keys).build(), None)
+            .await
+            .expect("failed to create index");
+    }
+}
+
+async fn parcel_list_find_and_join(db: &Database, user_id_hash: &str) -> usize {
+    let relations: Vec<Document> = db
+        .collection::<Document>("tracking_number_user_relation")
+        .find(doc! {"user_id_hash": user_id_hash, "chat_id": null}, None)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let numbers: Vec<&str> = relations
+        .iter()
+        .filter_map(|r| r.get_str("tracking_number").ok())
+        .collect();
+    let tracking_data: HashMap<String, Document> = db
+        .collection::<Document>("tracking_data")
+        .find(doc! {"data.number": {"$in": &numbers}}, None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<Document>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter_map(|t| {
+            Some((
+                t.get_document("data")
+                    .ok()?
+                    .get_str("number")
+                    .ok()?
+                    .to_string(),
+                t,
+            ))
+        })
+        .collect();
+    numbers
+        .iter()
+        .filter(|n| tracking_data.contains_key(**n))
+        .count()
+}
+
+async fn parcel_list_pipeline(db: &Database, user_id_hash: &str, summary: bool) -> usize {
+    let filter = doc! {"user_id_hash": user_id_hash, "chat_id": null};
+    let pipeline = match summary {
+        true => database_pipelines::user_tracked_numbers_summary_pipeline(filter),
+        false => database_pipelines::user_tracked_numbers_pipeline(filter),
+    };
+    db.collection::<Document>("tracking_number_user_relation")
+        .aggregate(pipeline, None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<Document>>()
+        .await
+        .unwrap()
+        .len()
+}
+
+async fn followers_find_and_join(db: &Database, tracking_number: &str) -> usize {
+    let relations: Vec<Document> = db
+        .collection::<Document>("tracking_number_user_relation")
+        .find(
+            doc! {"tracking_number": tracking_number, "is_subscribed": true},
+            None,
+        )
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let hashes: Vec<&str> = relations
+        .iter()
+        .filter_map(|r| r.get_str("user_id_hash").ok())
+        .collect();
+    db.collection::<Document>("users")
+        .find(doc! {"user_id_hash": {"$in": &hashes}}, None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<Document>>()
+        .await
+        .unwrap()
+        .len()
+}
+
+async fn followers_pipeline(db: &Database, tracking_number: &str) -> usize {
+    let pipeline = database_pipelines::tracking_number_followers_pipeline(tracking_number);
+    db.collection::<Document>("tracking_number_user_relation")
+        .aggregate(pipeline, None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<Document>>()
+        .await
+        .unwrap()
+        .len()
+}
@@ -0,0 +1,122 @@
+/*
+    Cargo stuff
+*/
+
+use serde::{Deserialize, Serialize};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// bundled list of the carriers with their 17TRACK carrier keys, the carrier list of the 17TRACK API docs (apicarrier.all.json)
+// can be put here as it is, its fields are read as well as the ones of this file
+const CARRIERS_JSON: &str = include_str!("../data/carriers.json");
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// one carrier of the directory, key is the 17TRACK carrier key
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct carrier_entry {
+    pub key: i32,
+    #[serde(alias = "_name")]
+    pub name: String,
+    pub alias: Option<String>,
+    #[serde(alias = "_country_iso")]
+    pub country: Option<String>,
+    #[serde(alias = "_url")]
+    pub homepage: Option<String>,
+    #[serde(alias = "_tel")]
+    pub tel: Option<String>,
+}
+
+/// the carriers the numbers can be registered with, loaded once at startup
+pub struct carrier_directory {
+    carriers: Vec<carrier_entry>,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+impl carrier_directory {
+    /// initializer, parses the bundled list
+    pub fn load() -> Result<Self, serde_json::Error> {
+        Self::parse(CARRIERS_JSON)
+    }
+
+    fn parse(carriers_json: &str) -> Result<Self, serde_json::Error> {
+        let mut carriers: Vec<carrier_entry> = serde_json::from_str(carriers_json)?;
+        carriers.sort_by_key(|carrier| carrier.name.to_lowercase());
+        Ok(carrier_directory { carriers })
+    }
+
+    /// find the carrier by its 17TRACK key
+    pub fn get(&self, key: i32) -> Option<&carrier_entry> {
+        self.carriers.iter().find(|carrier| carrier.key == key)
+    }
+
+    /// search by key, name, alias or country code, case insensitive, the carriers whose name starts with the query come
+    /// first and an empty query gives the whole list
+    pub fn search(&self, query: &str) -> Vec<&carrier_entry> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return self.carriers.iter().collect();
+        }
+        let (mut starts_with, contains): (Vec<&carrier_entry>, Vec<&carrier_entry>) = self
+            .carriers
+            .iter()
+            .filter(|carrier| {
+                carrier.key.to_string() == query
+                    || carrier.name.to_lowercase().contains(&query)
+                    || carrier
+                        .alias
+                        .as_ref()
+                        .is_some_and(|alias| alias.to_lowercase().contains(&query))
+                    || carrier
+                        .country
+                        .as_ref()
+                        .is_some_and(|country| country.to_lowercase() == query)
+            })
+            .partition(|carrier| carrier.name.to_lowercase().starts_with(&query));
+        starts_with.extend(contains);
+        starts_with
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_list_loads() {
+        let carriers = carrier_directory::load().unwrap();
+        assert_eq!(carriers.get(100001).unwrap().name, "DHL Express");
+        assert!(carriers.get(0).is_none());
+    }
+
+    #[test]
+    fn reads_the_17track_carrier_list() {
+        let carriers = carrier_directory::parse(
+            r#"[{"key":1151,"_country":1101,"_country_iso":"AU","_email":null,"_tel":"13 13 18",
+                "_url":"https://auspost.com.au","_name":"Australia Post","_name_zh-cn":null,"_group":null}]"#,
+        )
+        .unwrap();
+        let carrier = carriers.get(1151).unwrap();
+        assert_eq!(carrier.name, "Australia Post");
+        assert_eq!(carrier.country.as_deref(), Some("AU"));
+        assert_eq!(carrier.homepage.as_deref(), Some("https://auspost.com.au"));
+        assert_eq!(carrier.tel.as_deref(), Some("13 13 18"));
+        assert!(carrier.alias.is_none());
+    }
+}
@@ -0,0 +1,194 @@
+/*
+    Cargo stuff
+
+    only bson in here so the benchmarks can use the same pipelines (see benches/parcel_list_queries.rs)
+*/
+
+use mongodb::bson::{doc, Bson, DateTime, Document};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    AGGREGATION PIPELINES
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Pipeline for the tracking_number_user_relation collection, joins the relation records that match the filter with the
+/// tracking data of their numbers, every result is {relation, tracking_data} and relations without tracking data are left out
+pub fn user_tracked_numbers_pipeline(relation_filter: Document) -> Vec<Document> {
+    vec![
+        doc! {"$match": relation_filter},
+        doc! {"$lookup": {
+            "from": "tracking_data",
+            "localField": "tracking_number",
+            "foreignField": "data.number",
+            "as": "tracking_data",
+        }},
+        doc! {"$unwind": "$tracking_data"},
+        doc! {"$replaceWith": {
+            "relation": "$$ROOT",
+            "tracking_data": {"data": "$tracking_data.data", "updated_at": "$tracking_data.updated_at"},
+        }},
+        doc! {"$unset": "relation.tracking_data"},
+    ]
+}
+
+/// Same as @user_tracked_numbers_pipeline without the events of the providers, for the list views that only need the
+/// latest event
+pub fn user_tracked_numbers_summary_pipeline(relation_filter: Document) -> Vec<Document> {
+    let mut pipeline = user_tracked_numbers_pipeline(relation_filter);
+    pipeline.push(doc! {"$unset": "tracking_data.data.track_info.tracking.providers.events"});
+    pipeline
+}
+
+/// Same as @user_tracked_numbers_pipeline but only the numbers where the relation or the tracking data changed since the
+/// time, all of them without a time
+pub fn user_changed_tracked_numbers_pipeline(
+    relation_filter: Document,
+    since: Option<DateTime>,
+) -> Vec<Document> {
+    let mut pipeline = user_tracked_numbers_pipeline(relation_filter);
+    if let Some(since) = since {
+        pipeline.push(doc! {"$match": {"$or": [
+            {"relation.updated_at": {"$gte": since}},
+            {"tracking_data.updated_at": {"$gte": since}},
+        ]}});
+    }
+    pipeline
+}
+
+/// Filter for the results of the user tracked numbers pipelines with the filters of the client's list query, the label
+/// matches when it contains the text in any case
+pub fn tracked_numbers_filter(
+    status: Option<&str>,
+    subscribed: Option<bool>,
+    carrier: Option<i32>,
+    label: Option<&str>,
+) -> Document {
+    let mut filter = doc! {};
+    if let Some(status) = status {
+        filter.insert("tracking_data.data.track_info.latest_status.status", status);
+    }
+    if let Some(subscribed) = subscribed {
+        filter.insert("relation.is_subscribed", subscribed);
+    }
+    if let Some(carrier) = carrier {
+        filter.insert("tracking_data.data.carrier", carrier);
+    }
+    if let Some(label) = label {
+        filter.insert(
+            "relation.label",
+            doc! {"$regex": escape_regex(label), "$options": "i"},
+        );
+    }
+    filter
+}
+
+/// Sort key of the list sorted by the latest event, newest first
+pub fn last_event_sort_key() -> Bson {
+    sort_key_or_last(doc! {"$multiply": [
+        -1,
+        timestamp_seconds("$tracking_data.data.track_info.latest_event.time_utc"),
+    ]})
+}
+
+/// Sort key of the list sorted by when the number was registered, newest first, the relations saved before the time was
+/// kept use the time in their ID
+pub fn registered_sort_key() -> Bson {
+    sort_key_or_last(doc! {"$multiply": [
+        -1,
+        {"$ifNull": [
+            "$relation.registered_at",
+            {"$toLong": {"$divide": [{"$toLong": {"$toDate": "$relation._id"}}, 1000]}},
+        ]},
+    ]})
+}
+
+/// Sort key of the list sorted by the estimated delivery, soonest first
+pub fn eta_sort_key() -> Bson {
+    sort_key_or_last(doc! {"$ifNull": [
+        timestamp_seconds("$tracking_data.data.track_info.time_metrics.estimated_delivery_date.from"),
+        timestamp_seconds("$tracking_data.data.track_info.time_metrics.estimated_delivery_date.to"),
+    ]})
+}
+
+/// Stages to add after a user tracked numbers pipeline for one page of the list, the results that pass the filter get the
+/// sort_key field and are sorted by it and the number so the order is always the same, the page starts after the key and
+/// number of the cursor, every result is returned without a limit
+pub fn tracked_numbers_page_stages(
+    filter: Document,
+    sort_key: Bson,
+    after: Option<(i64, &str)>,
+    limit: Option<i64>,
+) -> Vec<Document> {
+    let mut stages = Vec::new();
+    if !filter.is_empty() {
+        stages.push(doc! {"$match": filter});
+    }
+    stages.push(doc! {"$addFields": {"sort_key": sort_key}});
+    if let Some((key, tracking_number)) = after {
+        stages.push(doc! {"$match": {"$or": [
+            {"sort_key": {"$gt": key}},
+            {"sort_key": key, "relation.tracking_number": {"$gt": tracking_number}},
+        ]}});
+    }
+    stages.push(doc! {"$sort": {"sort_key": 1, "relation.tracking_number": 1}});
+    if let Some(limit) = limit {
+        stages.push(doc! {"$limit": limit});
+    }
+    stages
+}
+
+/// Pipeline for the tracking_number_user_relation collection, finds the chats to notify about an update of the tracking
+/// number, every result is {chat_id, label}, the chat is the group chat for group relations and the user's private chat
+/// otherwise
+pub fn tracking_number_followers_pipeline(tracking_number: &str) -> Vec<Document> {
+    vec![
+        doc! {"$match": {"tracking_number": tracking_number, "is_subscribed": true}},
+        doc! {"$lookup": {
+            "from": "users",
+            "localField": "user_id_hash",
+            "foreignField": "user_id_hash",
+            "as": "user",
+        }},
+        doc! {"$project": {
+            "_id": 0,
+            "label": 1,
+            "chat_id": {"$ifNull": ["$chat_id", {"$first": "$user.user_id"}]},
+        }},
+        // users that were deleted in the meantime
+        doc! {"$match": {"chat_id": {"$ne": null}}},
+    ]
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    HELPER FUNCTIONS
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// the RFC 3339 time in the field as unix seconds, null when it's missing or can't be read
+fn timestamp_seconds(field: &str) -> Document {
+    doc! {"$toLong": {"$divide": [
+        {"$toLong": {"$dateFromString": {"dateString": field, "onError": null, "onNull": null}}},
+        1000,
+    ]}}
+}
+
+// the numbers without a value go last
+fn sort_key_or_last(key: Document) -> Bson {
+    Bson::Document(doc! {"$ifNull": [key, i64::MAX]})
+}
+
+// the text matched as it is in a $regex
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
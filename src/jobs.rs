@@ -0,0 +1,571 @@
+/*
+    Cargo stuff
+*/
+
+use crate::{
+    my_structs::tracking_data_formats::status_values::main_status,
+    my_structs::tracking_data_formats::tracking_number_meta_data::AcceptedPage as registered_number,
+    number_detection::normalize_tracking_number,
+    trackingapi::{tracking_client, tracking_error, tracking_number_carrier},
+};
+use actix_web::rt;
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::FindOptions,
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// how often the archive job runs
+const ARCHIVE_JOB_INTERVAL_SECONDS: u64 = 60 * 60;
+// days after delivery before the relations get archived when ARCHIVE_AFTER_DAYS isn't set
+const DEFAULT_ARCHIVE_AFTER_DAYS: i64 = 14;
+// hours between reconciliations when RECONCILE_INTERVAL_HOURS isn't set
+const DEFAULT_RECONCILE_INTERVAL_HOURS: u64 = 24;
+// numbers registered or followed this recently are skipped, the register handler talks to the API before it saves the
+// relation so a fresh number can look orphaned or missing for a moment
+const RECONCILE_GRACE_MINUTES: i64 = 60;
+// the API has 40 numbers on a page, this stops a broken page_total from looping forever
+const MAX_TRACKLIST_PAGES: i32 = 10_000;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// settings for the archive job, read from the environment
+#[derive(Debug, Clone)]
+pub struct archive_settings {
+    pub archive_after_days: i64,
+    // also delete the number from 17TRACK when nobody has it unarchived anymore, frees the provider quota
+    pub delete_from_provider: bool,
+}
+
+impl archive_settings {
+    /// ARCHIVE_AFTER_DAYS and ARCHIVE_DELETE_FROM_PROVIDER, both optional
+    pub fn from_env() -> Self {
+        archive_settings {
+            archive_after_days: env::var("ARCHIVE_AFTER_DAYS")
+                .ok()
+                .and_then(|days| days.parse().ok())
+                .unwrap_or(DEFAULT_ARCHIVE_AFTER_DAYS),
+            delete_from_provider: env::var("ARCHIVE_DELETE_FROM_PROVIDER")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// settings for the reconciliation job, read from the environment
+#[derive(Debug, Clone)]
+pub struct reconcile_settings {
+    pub interval_hours: u64,
+    // fix what was found instead of only reporting it
+    pub auto_fix: bool,
+}
+
+impl reconcile_settings {
+    /// RECONCILE_INTERVAL_HOURS and RECONCILE_AUTO_FIX, both optional
+    pub fn from_env() -> Self {
+        reconcile_settings {
+            interval_hours: env::var("RECONCILE_INTERVAL_HOURS")
+                .ok()
+                .and_then(|hours| hours.parse().ok())
+                .filter(|hours| *hours > 0)
+                .unwrap_or(DEFAULT_RECONCILE_INTERVAL_HOURS),
+            auto_fix: env::var("RECONCILE_AUTO_FIX")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// what the reconciliation found, saved in the reconciliation_reports collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct reconciliation_report {
+    pub started_at: mongodb::bson::DateTime,
+    pub finished_at: mongodb::bson::DateTime,
+    pub upstream_numbers: usize,
+    pub followed_numbers: usize,
+    // registered on the API without any relation, they use provider quota for nobody
+    pub orphaned_upstream: Vec<String>,
+    // followed but not registered on the API, no updates will come for them
+    pub missing_upstream: Vec<String>,
+    // followed with someone subscribed but stopped on the API before delivery
+    pub stopped_upstream: Vec<String>,
+    // subscribed relations on numbers that are stopped on the API and can't be started again, by number
+    pub subscribed_on_stopped: Vec<String>,
+    // every page of the API was read, the numbers after the page limit look missing or orphaned otherwise
+    #[serde(default = "complete_by_default")]
+    pub complete: bool,
+    pub fixed: bool,
+    pub fix_errors: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum reconciliation_error {
+    #[error("database error: {0}")]
+    DatabaseError(#[from] mongodb::error::Error),
+    #[error("API error: {0}")]
+    TrackingError(#[from] tracking_error),
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    JOBS
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Start the archive job in the background of the actix runtime, it runs once right away and then every hour
+pub fn start_archive_job(
+    client: Client,
+    tracking_client: Arc<tracking_client>,
+    settings: archive_settings,
+) {
+    println!(
+        "@ARCHIVE_JOB: archiving delivered parcels after {} days, delete from provider: {}",
+        settings.archive_after_days, settings.delete_from_provider
+    );
+    rt::spawn(async move {
+        let mut interval =
+            rt::time::interval(std::time::Duration::from_secs(ARCHIVE_JOB_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = archive_delivered_parcels(&client, &tracking_client, &settings).await {
+                println!("@ARCHIVE_JOB: database error: {}", e);
+            }
+        }
+    });
+}
+
+/// time the parcel was delivered, the delivered milestone if the carrier gave one or the latest event otherwise, from the
+/// tracking data with only the fields the archive job projects
+fn delivered_at(tracking_data: &Document) -> Option<DateTime<Utc>> {
+    let track_info = tracking_data
+        .get_document("data")
+        .ok()?
+        .get_document("track_info")
+        .ok()?;
+    track_info
+        .get_array("milestone")
+        .ok()
+        .and_then(|milestones| {
+            milestones
+                .iter()
+                .filter_map(|milestone| milestone.as_document())
+                .find(|milestone| milestone.get_str("key_stage").ok() == Some("Delivered"))
+        })
+        .and_then(|milestone| milestone.get_str("time_utc").ok())
+        .or_else(|| {
+            track_info
+                .get_document("latest_event")
+                .ok()?
+                .get_str("time_utc")
+                .ok()
+        })
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Archive the relations of every parcel that was delivered more than the set number of days ago, relations the user
+/// unarchived themselves are left alone
+async fn archive_delivered_parcels(
+    client: &Client,
+    tracking_client: &tracking_client,
+    settings: &archive_settings,
+) -> Result<(), mongodb::error::Error> {
+    // set database and collections
+    let db = client.database("teletrack");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let collection_relations: mongodb::Collection<Document> =
+        db.collection("tracking_number_user_relation");
+
+    // every delivered parcel that wasn't already deleted from the provider by an earlier run, only the fields the job reads
+    let filter = doc! {
+        "data.track_info.latest_status.status": main_status::Delivered.as_str(),
+        "deleted_from_provider": {"$ne": true},
+    };
+    let options = FindOptions::builder()
+        .projection(doc! {
+            "data.number": 1,
+            "data.track_info.milestone.key_stage": 1,
+            "data.track_info.milestone.time_utc": 1,
+            "data.track_info.latest_event.time_utc": 1,
+            "manual": 1,
+        })
+        .build();
+    let delivered_parcels = collection_tracking_data
+        .find(filter, options)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    //
+
+    let archive_before = Utc::now() - Duration::days(settings.archive_after_days);
+    for tracking_data in delivered_parcels {
+        let tracking_number = match tracking_data
+            .get_document("data")
+            .and_then(|data| data.get_str("number"))
+        {
+            Ok(tracking_number) => tracking_number,
+            Err(_) => continue,
+        };
+        match delivered_at(&tracking_data) {
+            Some(delivered_at) if delivered_at <= archive_before => (),
+            _ => continue,
+        }
+
+        // archive the relations
+        let filter = doc! {
+            "tracking_number": tracking_number,
+            "archived": {"$ne": true},
+            "unarchived": {"$ne": true},
+        };
+        let update = doc! {"$set": {
+            "archived": true,
+            "archived_at": Utc::now().timestamp(),
+            "updated_at": mongodb::bson::DateTime::now(),
+        }};
+        let result = collection_relations
+            .update_many(filter, update, None)
+            .await?;
+        if result.modified_count > 0 {
+            println!(
+                "@ARCHIVE_JOB: archived {} relations of {}",
+                result.modified_count, tracking_number
+            );
+        }
+        //
+
+        // manual shipments were never on the provider
+        if !settings.delete_from_provider || tracking_data.get_bool("manual").unwrap_or(false) {
+            continue;
+        }
+
+        // delete from the provider only when every relation of the number is archived
+        let filter = doc! {"tracking_number": tracking_number, "archived": {"$ne": true}};
+        if collection_relations.count_documents(filter, None).await? > 0 {
+            continue;
+        }
+        match tracking_client.delete_number(tracking_number).await {
+            Ok(_) => {
+                println!(
+                    "@ARCHIVE_JOB: deleted {} from the provider",
+                    tracking_number
+                );
+                // the stored tracking data stays so the archived list can still show it
+                db.collection::<Document>("tracking_data")
+                    .update_one(
+                        doc! {"data.number": tracking_number},
+                        doc! {"$set": {"deleted_from_provider": true}},
+                        None,
+                    )
+                    .await?;
+            }
+            Err(e) => println!(
+                "@ARCHIVE_JOB: error deleting {} from the provider: {}",
+                tracking_number, e
+            ),
+        }
+        //
+    }
+
+    Ok(())
+}
+
+/// Start the reconciliation job in the background of the actix runtime, it runs once right away and then every set number
+/// of hours
+pub fn start_reconciliation_job(
+    client: Client,
+    tracking_client: Arc<tracking_client>,
+    settings: reconcile_settings,
+) {
+    println!(
+        "@RECONCILIATION_JOB: comparing with the provider every {} hours, auto fix: {}",
+        settings.interval_hours, settings.auto_fix
+    );
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(std::time::Duration::from_secs(
+            settings.interval_hours * 60 * 60,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) =
+                reconcile_with_provider(&client, &tracking_client, settings.auto_fix).await
+            {
+                println!("@RECONCILIATION_JOB: {}", e);
+            }
+        }
+    });
+}
+
+// the reports saved before the field was added read every page
+fn complete_by_default() -> bool {
+    true
+}
+
+/// every number registered on the API, page by page, and whether the last page was reached before MAX_TRACKLIST_PAGES
+async fn registered_numbers(
+    tracking_client: &tracking_client,
+) -> Result<(Vec<registered_number>, bool), tracking_error> {
+    let mut numbers = Vec::new();
+    let mut page_no = 1;
+    loop {
+        let page = tracking_client.get_tracklist_page(page_no).await?;
+        numbers.extend(page.data.accepted);
+        if page_no >= page.page.page_total {
+            return Ok((numbers, true));
+        }
+        if page_no >= MAX_TRACKLIST_PAGES {
+            println!(
+                "@RECONCILIATION_JOB: stopped at page {} of {}",
+                page_no, page.page.page_total
+            );
+            return Ok((numbers, false));
+        }
+        page_no += 1;
+    }
+}
+
+/// true for API times in the grace period, so numbers that are being registered right now are left alone
+fn registered_recently(time: Option<&str>) -> bool {
+    let grace_start = Utc::now() - Duration::minutes(RECONCILE_GRACE_MINUTES);
+    time.and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .is_some_and(|time| time.with_timezone(&Utc) > grace_start)
+}
+
+/// Compare the numbers registered on the API with the relations in the database, report what doesn't match and fix it when
+/// asked to and every page of the API was read, the report is saved and returned
+///     orphaned upstream       - deleted from the API
+///     missing upstream        - registered again with the carrier of the relation
+///     stopped upstream        - re-tracked, the API allows that once per number
+///     subscribed on stopped   - the relations are set to unsubscribed
+pub async fn reconcile_with_provider(
+    client: &Client,
+    tracking_client: &tracking_client,
+    auto_fix: bool,
+) -> Result<reconciliation_report, reconciliation_error> {
+    let started_at = mongodb::bson::DateTime::now();
+
+    // set database and collections
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<Document> =
+        db.collection("tracking_number_user_relation");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let collection_reports: mongodb::Collection<reconciliation_report> =
+        db.collection("reconciliation_reports");
+
+    // the API first, a relation saved while paging is then already in the database below
+    // the relations have the normalised numbers but the API can still have them under the spelling they were registered with
+    // (see @MIGRATE_NORMALIZED_TRACKING_NUMBERS), so they're compared normalised and the spellings are kept for the API calls
+    let (registered, complete) = registered_numbers(tracking_client).await?;
+    let mut upstream: HashMap<String, registered_number> = HashMap::new();
+    let mut upstream_spellings: HashMap<String, Vec<String>> = HashMap::new();
+    for registered in registered {
+        let spelling = match &registered.number {
+            Some(spelling) => spelling.clone(),
+            None => continue,
+        };
+        let tracking_number = normalize_tracking_number(&spelling);
+        upstream_spellings
+            .entry(tracking_number.clone())
+            .or_default()
+            .push(spelling);
+        // a spelling that's still tracked counts over a stopped one
+        match upstream.get(&tracking_number) {
+            Some(kept) if !kept.tracking_status.is_stopped() => (),
+            _ => {
+                upstream.insert(tracking_number, registered);
+            }
+        }
+    }
+    //
+
+    // followed numbers, with the carrier to register them again and whether anybody is subscribed
+    let relations = collection_relations
+        .find(doc! {}, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let recent = (Utc::now() - Duration::minutes(RECONCILE_GRACE_MINUTES)).timestamp();
+    let mut followed: HashMap<String, (Option<i32>, bool, bool)> = HashMap::new();
+    for relation in &relations {
+        let tracking_number = match relation.get_str("tracking_number") {
+            Ok(tracking_number) => tracking_number.to_string(),
+            Err(_) => continue,
+        };
+        let is_recent = relation
+            .get_i64("registered_at")
+            .is_ok_and(|registered_at| registered_at > recent);
+        let entry = followed
+            .entry(tracking_number)
+            .or_insert((None, false, false));
+        entry.0 = entry.0.or(relation.get_i32("carrier").ok());
+        entry.1 |= relation.get_bool("is_subscribed").unwrap_or(false);
+        entry.2 |= is_recent;
+    }
+    // the archive job deletes some numbers from the API on purpose and manual shipments are never on it
+    let filter = doc! {"$or": [{"deleted_from_provider": true}, {"manual": true}]};
+    let not_on_provider: HashSet<String> = collection_tracking_data
+        .find(filter, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?
+        .into_iter()
+        .filter_map(|tracking_data| {
+            Some(
+                tracking_data
+                    .get_document("data")
+                    .ok()?
+                    .get_str("number")
+                    .ok()?
+                    .to_string(),
+            )
+        })
+        .collect();
+    //
+
+    let mut report = reconciliation_report {
+        started_at,
+        finished_at: started_at,
+        upstream_numbers: upstream.len(),
+        followed_numbers: followed.len(),
+        orphaned_upstream: Vec::new(),
+        missing_upstream: Vec::new(),
+        stopped_upstream: Vec::new(),
+        subscribed_on_stopped: Vec::new(),
+        complete,
+        // a partial walk would delete or register numbers that are fine on the pages it didn't read
+        fixed: auto_fix && complete,
+        fix_errors: Vec::new(),
+    };
+
+    // compare
+    for (tracking_number, registered) in &upstream {
+        if !followed.contains_key(tracking_number)
+            && !registered_recently(registered.register_time.as_deref())
+        {
+            report.orphaned_upstream.push(tracking_number.clone());
+        }
+    }
+    for (tracking_number, (_, subscribed, is_recent)) in &followed {
+        if *is_recent || not_on_provider.contains(tracking_number) {
+            continue;
+        }
+        match upstream.get(tracking_number) {
+            None => report.missing_upstream.push(tracking_number.clone()),
+            Some(registered) if *subscribed && registered.tracking_status.is_stopped() => {
+                match registered.package_status.is_delivered() {
+                    true => report.subscribed_on_stopped.push(tracking_number.clone()),
+                    false => report.stopped_upstream.push(tracking_number.clone()),
+                }
+            }
+            Some(_) => (),
+        }
+    }
+    report.orphaned_upstream.sort();
+    report.missing_upstream.sort();
+    report.stopped_upstream.sort();
+    //
+
+    if report.fixed {
+        for tracking_number in &report.orphaned_upstream {
+            for spelling in upstream_spellings
+                .get(tracking_number)
+                .into_iter()
+                .flatten()
+            {
+                if let Err(e) = tracking_client.delete_number(spelling).await {
+                    report
+                        .fix_errors
+                        .push(format!("deleting {}: {}", spelling, e));
+                }
+            }
+        }
+        for tracking_number in &report.missing_upstream {
+            let tracking_details = tracking_number_carrier {
+                number: tracking_number.clone(),
+                carrier: followed
+                    .get(tracking_number)
+                    .and_then(|(carrier, _, _)| *carrier),
+                param: None,
+            };
+            match tracking_client.register_tracking(tracking_details).await {
+                Ok(_) | Err(tracking_error::TrackingAlreadyRegistered) => (),
+                Err(e) => report
+                    .fix_errors
+                    .push(format!("registering {}: {}", tracking_number, e)),
+            }
+        }
+        // numbers the API doesn't allow to re-track anymore are treated like the delivered ones
+        for tracking_number in report.stopped_upstream.clone() {
+            let spelling = upstream
+                .get(&tracking_number)
+                .and_then(|registered| registered.number.clone())
+                .unwrap_or_else(|| tracking_number.clone());
+            if let Err(e) = tracking_client.retrack_stopped_number(&spelling).await {
+                report
+                    .fix_errors
+                    .push(format!("re-tracking {}: {}", tracking_number, e));
+                report.subscribed_on_stopped.push(tracking_number);
+            }
+        }
+        if !report.subscribed_on_stopped.is_empty() {
+            collection_relations
+                .update_many(
+                    doc! {"tracking_number": {"$in": &report.subscribed_on_stopped}, "is_subscribed": true},
+                    doc! {"$set": {
+                        "is_subscribed": false,
+                        "updated_at": mongodb::bson::DateTime::now(),
+                    }},
+                    None,
+                )
+                .await?;
+        }
+    }
+    report.subscribed_on_stopped.sort();
+
+    report.finished_at = mongodb::bson::DateTime::now();
+    println!(
+        "@RECONCILIATION_JOB: {} upstream, {} followed, {} orphaned, {} missing, {} stopped, {} subscribed on stopped, {} fix errors",
+        report.upstream_numbers,
+        report.followed_numbers,
+        report.orphaned_upstream.len(),
+        report.missing_upstream.len(),
+        report.stopped_upstream.len(),
+        report.subscribed_on_stopped.len(),
+        report.fix_errors.len()
+    );
+    collection_reports.insert_one(&report, None).await?;
+    Ok(report)
+}
+
+/// the report of the last reconciliation, None before the first one
+pub async fn latest_reconciliation_report(
+    client: &Client,
+) -> Result<Option<reconciliation_report>, mongodb::error::Error> {
+    let collection_reports: mongodb::Collection<reconciliation_report> = client
+        .database("teletrack")
+        .collection("reconciliation_reports");
+    let options = mongodb::options::FindOneOptions::builder()
+        .sort(doc! {"started_at": -1})
+        .build();
+    collection_reports.find_one(doc! {}, options).await
+}
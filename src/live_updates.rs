@@ -0,0 +1,113 @@
+/*
+    Cargo stuff
+*/
+
+use actix_web::rt;
+use bytes::Bytes;
+use futures::channel::mpsc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// proxies close connections that stay quiet for too long, a comment line is sent to every client this often
+const KEEPALIVE_INTERVAL_SECONDS: u64 = 15;
+// open mini apps per user, the oldest connection is dropped after this
+const MAX_CONNECTIONS_PER_USER: usize = 5;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// keeps the server-sent event connections of the open mini apps by user ID hash and sends the events to them
+pub struct live_update_broadcaster {
+    clients: Mutex<HashMap<String, Vec<mpsc::UnboundedSender<Bytes>>>>,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+impl live_update_broadcaster {
+    /// initializer, also starts the keepalive in the background of the actix runtime
+    pub fn new() -> Arc<Self> {
+        let broadcaster = Arc::new(live_update_broadcaster {
+            clients: Mutex::new(HashMap::new()),
+        });
+
+        let keepalive_broadcaster = broadcaster.clone();
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(Duration::from_secs(KEEPALIVE_INTERVAL_SECONDS));
+            loop {
+                interval.tick().await;
+                keepalive_broadcaster.keepalive();
+            }
+        });
+
+        broadcaster
+    }
+
+    /// add a connection for the user, the receiver is the body of the event stream response
+    pub fn subscribe(&self, user_id_hash: String) -> mpsc::UnboundedReceiver<Bytes> {
+        let (sender, receiver) = mpsc::unbounded();
+        // tells the client the stream is open
+        let _ = sender.unbounded_send(Bytes::from_static(b": connected\n\n"));
+
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let connections = clients.entry(user_id_hash).or_default();
+        connections.retain(|connection| !connection.is_closed());
+        if connections.len() >= MAX_CONNECTIONS_PER_USER {
+            connections.remove(0);
+        }
+        connections.push(sender);
+        receiver
+    }
+
+    /// check if anybody is connected before doing database work to find who to send an event to
+    pub fn has_subscribers(&self) -> bool {
+        !self
+            .clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /// send an event to every open connection of the user
+    pub fn publish(&self, user_id_hash: &str, event: &str, data: &serde_json::Value) {
+        let message = Bytes::from(format!("event: {}\ndata: {}\n\n", event, data));
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(connections) = clients.get_mut(user_id_hash) {
+            connections.retain(|connection| connection.unbounded_send(message.clone()).is_ok());
+            if connections.is_empty() {
+                clients.remove(user_id_hash);
+            }
+        }
+    }
+
+    /// send the keepalive comment to every connection and forget the closed ones
+    fn keepalive(&self) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|_, connections| {
+            connections.retain(|connection| {
+                connection
+                    .unbounded_send(Bytes::from_static(b": keepalive\n\n"))
+                    .is_ok()
+            });
+            !connections.is_empty()
+        });
+    }
+}
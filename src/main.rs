@@ -2,21 +2,39 @@
     Cargo stuff
 */
 
+mod carriers;
+mod database_pipelines;
+mod jobs;
+mod live_updates;
+mod manual_shipments;
+mod migrations;
 mod my_structs;
 mod notifications;
+mod number_detection;
+mod number_extraction;
+mod provider_quota;
+mod telegram_webhook;
+mod tracking_alerts;
+mod tracking_history;
 mod trackingapi;
+mod user_identity;
 //TODO: CHANGE THE WEBHOOK.LEMONCARDBOARD.UK ROOT TO SOMETHING BETTER THAN WEBHOOK (LIKE TELETRACK)
 mod webhook;
 
 use crate::{
+    my_structs::tracking_data_formats::change_carrier_response::ChangeCarrierResponse as change_carrier_response,
+    my_structs::tracking_data_formats::change_info_response::ChangeInfoResponse as change_info_response,
     my_structs::tracking_data_formats::delete_tracking_number_response::DeleteTrackingResponseNumber as delete_tracking_number_response,
     my_structs::tracking_data_formats::register_tracking_number_response::RegisterResponse as register_tracking_number_response,
     my_structs::tracking_data_formats::retrack_stopped_number_response::RetrackStoppedNumberResponse as retrack_stopped_number_response,
+    my_structs::tracking_data_formats::status_values::{main_status, tracking_status},
     my_structs::tracking_data_formats::stop_tracking_response::StopTrackingResponse as stop_tracking_response,
     my_structs::tracking_data_formats::tracking_data_database_form::TrackingData_DBF as tracking_data_database_form,
     my_structs::tracking_data_formats::tracking_data_get_info::TrackingResponse as tracking_data_get_info,
     my_structs::tracking_data_formats::tracking_data_html_form::tracking_data_HTML,
+    my_structs::tracking_data_formats::tracking_data_summary_form::tracking_data_summary,
     my_structs::tracking_data_formats::tracking_number_meta_data::NumberStatusCheck as number_status_check,
+    tracking_history::snapshot_source,
 };
 use actix_cors::Cors;
 use actix_web::{
@@ -25,20 +43,25 @@ use actix_web::{
     web::{self, Json},
     App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
+use base64::Engine as _;
+use carriers::carrier_directory;
+use chrono::Utc;
 use dotenv::dotenv;
 use futures::{stream::StreamExt, TryStreamExt};
-use hex::encode;
+use live_updates::live_update_broadcaster;
 use mongodb::{
-    bson::doc,
+    bson::{doc, oid::ObjectId, Bson, Document},
     options::{ClientOptions, FindOptions},
     Client,
 };
-use notifications::{notification_service, notification_service_error};
+use notifications::{notification_priority, notification_service, notification_service_error};
+use provider_quota::provider_quota_guard;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{env, string, sync::Arc};
 use trackingapi::{just_the_tracking_number, tracking_client, tracking_error};
+use user_identity::{user_id_hasher, verify_telegram_init_data};
 
 /*
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
@@ -50,6 +73,22 @@ use trackingapi::{just_the_tracking_number, tracking_client, tracking_error};
 
 // default tracking quota for users
 const DEFAULT_TRACKING_QUOTA: i32 = 4;
+// limits for the user's own details of a tracking number
+const MAX_LABEL_LENGTH: usize = 64;
+const MAX_NOTE_LENGTH: usize = 1000;
+// how long a share invite can be accepted after it's made
+const SHARE_INVITE_TTL_HOURS: i64 = 72;
+// how long a live updates ticket can be used to open the event stream after it's made
+const LIVE_UPDATES_TICKET_TTL_SECONDS: i64 = 60;
+// page size of the user's tracked numbers list
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+// how long the deleted relations are remembered for the delta sync, older sync cursors have to sync from scratch
+const SYNC_TOMBSTONE_TTL_DAYS: i64 = 30;
+// the sync cursor is set back a bit so changes saved while the sync was running are sent again on the next one
+const SYNC_CURSOR_OVERLAP_MILLIS: i64 = 5000;
+// most numbers registered in one bulk register request
+const MAX_BULK_REGISTER: usize = 20;
 
 /*
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
@@ -65,7 +104,16 @@ const DEFAULT_TRACKING_QUOTA: i32 = 4;
 struct AppState {
     notification_service: Arc<Result<notification_service, notification_service_error>>,
     tracking_client: Arc<tracking_client>,
+    user_id_hasher: Arc<user_id_hasher>,
+    live_updates: Arc<live_update_broadcaster>,
+    carrier_directory: Arc<carrier_directory>,
+    provider_quota: Arc<provider_quota_guard>,
     webhook_secret: String,
+    telegram_webhook_secret: String,
+    // for checking the init data the mini app sends was signed by telegram for this bot
+    telegram_bot_token: String,
+    // key for the /admin routes in the X-Admin-Key header, the routes are off without it
+    admin_api_key: Option<String>,
 }
 
 /// User structure for database
@@ -75,6 +123,11 @@ struct UserDatabaseForm {
     user_id_hash: String,
     user_name: String,
     remaining_tracking_quota: i32,
+    // fingerprint of the secret the user_id_hash was made with, None for the legacy unkeyed SHA-256
+    user_id_hash_key_id: Option<String>,
+    // identifiers from before the re-hashes, kept so a client still using one can be told to recover the current one
+    #[serde(default)]
+    previous_user_id_hashes: Vec<String>,
 }
 
 /// ERORRS
@@ -99,15 +152,23 @@ struct TestingDataFormat {
     value: String,
 }
 
-// struct for getting user details from the client
+// struct for getting user details from the client, the user ID comes from the init data telegram signed and not from the
+// client itself
 #[derive(Serialize, Deserialize, Debug)]
 struct UserDetailsFromClient {
-    user_id: i64,
+    init_data: String,
     user_name: String,
 }
 
+// struct for getting the telegram init data from the client, Telegram.WebApp.initData as it is
+#[derive(Serialize, Deserialize, Debug)]
+struct UserRecoveryFromClient {
+    init_data: String,
+}
+
 // struct for saving tracking number + carrier (optional) + user id hash as a relation record in the database
 // this also holds a bool that decides if the user is getting updates for the number or not
+// the label, note, pinned and color are the user's own details for the number, other users tracking it don't see them
 // TODO: redundant with webhook
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TrackingNumberUserRelation {
@@ -115,6 +176,234 @@ pub struct TrackingNumberUserRelation {
     carrier: Option<i32>,
     user_id_hash: String,
     is_subscribed: bool,
+    label: Option<String>,
+    note: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    color: Option<String>,
+    // owners registered the number themselves, viewers got it shared by the owner in shared_by and don't use quota
+    #[serde(default)]
+    role: RelationRole,
+    shared_by: Option<String>,
+    // set when the relation posts the updates in a group chat instead of the user's private chat, the user is the admin
+    // that linked the number to the group
+    chat_id: Option<i64>,
+    // archived by the archive job some days after delivery (see jobs.rs), unarchived is set when the user takes it back out
+    // so the job doesn't archive it again
+    #[serde(default)]
+    archived: bool,
+    archived_at: Option<i64>,
+    #[serde(default)]
+    unarchived: bool,
+    // unix time the relation was made, missing on older records so the time in the ID is used for those
+    registered_at: Option<i64>,
+    // when the relation was last changed, for the delta sync of the clients
+    updated_at: Option<mongodb::bson::DateTime>,
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+}
+
+/// role of the user in a relation record
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum RelationRole {
+    #[default]
+    Owner,
+    Viewer,
+}
+
+/// share invite record, the client only ever sees the signed token with the invite ID and the expiry time in it
+#[derive(Serialize, Deserialize, Debug)]
+struct ShareInvite {
+    invite_id: String,
+    tracking_number: String,
+    owner_user_id_hash: String,
+    expires_at: i64,
+    accepted_by: Option<String>,
+}
+
+// struct for getting a share token from the client
+#[derive(Serialize, Deserialize, Debug)]
+struct ShareTokenFromClient {
+    share_token: String,
+}
+
+// struct for getting a viewer of a tracking number from the client, the viewer ID is the ID of their relation record
+#[derive(Serialize, Deserialize, Debug)]
+struct ViewerFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    viewer_id: String,
+}
+
+// struct for getting the page, filters and sorting of the user's tracked numbers list from the client, everything is
+// optional and the cursor is the next_cursor of the previous page
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct TrackedNumbersQueryFromClient {
+    cursor: Option<String>,
+    limit: Option<usize>,
+    status: Option<main_status>,
+    subscribed: Option<bool>,
+    carrier: Option<i32>,
+    label: Option<String>,
+    #[serde(default)]
+    sort: TrackedNumbersSort,
+}
+
+/// sorting of the user's tracked numbers list, newest event first, newest registered first or soonest delivery first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum TrackedNumbersSort {
+    #[default]
+    LastEvent,
+    Registered,
+    Eta,
+}
+
+/// position in the sorted list after which the next page starts, sent to the client as base64 JSON
+#[derive(Serialize, Deserialize, Debug)]
+struct TrackedNumbersCursor {
+    sort: TrackedNumbersSort,
+    key: i64,
+    tracking_number: String,
+}
+
+/// record of a deleted relation, kept for SYNC_TOMBSTONE_TTL_DAYS so the user's client can drop the number from its cache
+#[derive(Serialize, Deserialize, Debug)]
+struct RelationTombstone {
+    tracking_number: String,
+    user_id_hash: String,
+    deleted_at: mongodb::bson::DateTime,
+}
+
+// struct for getting the live updates ticket from the client, EventSource can't set the X-User-ID-Hash header so it's in
+// the query instead
+#[derive(Serialize, Deserialize, Debug)]
+struct LiveUpdatesTicketFromClient {
+    ticket: String,
+}
+
+// struct for getting the carrier search from the client
+#[derive(Serialize, Deserialize, Debug)]
+struct CarrierSearchFromClient {
+    q: Option<String>,
+}
+
+// struct for getting the sync cursor from the client, no cursor means sync everything
+#[derive(Serialize, Deserialize, Debug)]
+struct SyncQueryFromClient {
+    since: Option<String>,
+}
+
+/// time after which the changes are sent on the next sync, sent to the client as base64 JSON
+#[derive(Serialize, Deserialize, Debug)]
+struct SyncCursor {
+    since: i64,
+}
+
+/// tracked numbers that changed since the sync cursor and the numbers the user doesn't have anymore
+#[derive(Serialize, Debug)]
+struct SyncResponse {
+    changed: Vec<tracking_data_HTML>,
+    deleted: Vec<String>,
+    cursor: String,
+}
+
+/// result of the user tracked numbers aggregation (see database_pipelines.rs)
+#[derive(Deserialize, Debug)]
+struct TrackedNumberJoined {
+    relation: TrackingNumberUserRelation,
+    tracking_data: tracking_data_database_form,
+    // only on the list pages (see database_pipelines::tracked_numbers_page_stages)
+    #[serde(default)]
+    sort_key: Option<i64>,
+}
+
+/// one page of the user's tracked numbers list, the items are the HTML form or the summary form
+#[derive(Serialize, Debug)]
+struct TrackedNumbersPage<T> {
+    items: Vec<T>,
+    next_cursor: Option<String>,
+}
+
+// struct for getting the carrier parameter of a tracking number from the client
+#[derive(Serialize, Deserialize, Debug)]
+struct CarrierParamFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    param: trackingapi::carrier_param,
+}
+
+// struct for the query of the admin reconciliation route, fix=true also fixes what it finds
+#[derive(Serialize, Deserialize, Debug)]
+struct ReconcileQueryFromClient {
+    fix: Option<bool>,
+}
+
+// struct for getting the text or the whole email the user pasted to find tracking numbers in
+#[derive(Serialize, Deserialize, Debug)]
+struct ExtractionFromClient {
+    text: String,
+}
+
+// struct for getting the numbers the user picked from the found ones to register them all at once
+#[derive(Serialize, Deserialize, Debug)]
+struct BulkRegisterFromClient {
+    numbers: Vec<trackingapi::tracking_number_carrier>,
+}
+
+// struct for getting a new manual shipment from the client, the reference is the user's own number for the parcel
+#[derive(Serialize, Deserialize, Debug)]
+struct ManualShipmentFromClient {
+    reference: Option<String>,
+    courier_name: Option<String>,
+    #[serde(default)]
+    events: Vec<manual_shipments::manual_event>,
+}
+
+// struct for getting a new event for a manual shipment from the client
+#[derive(Serialize, Deserialize, Debug)]
+struct ManualEventFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    event: manual_shipments::manual_event,
+}
+
+// struct for getting the changed event of a manual shipment from the client, the index is the place in the events list
+#[derive(Serialize, Deserialize, Debug)]
+struct EditManualEventFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    index: usize,
+    event: manual_shipments::manual_event,
+}
+
+// struct for getting the event of a manual shipment the user wants to remove, the index is the place in the events list
+#[derive(Serialize, Deserialize, Debug)]
+struct ManualEventIndexFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    index: usize,
+}
+
+// struct for getting the carrier the user picked for a tracking number the API detected the wrong carrier for
+#[derive(Serialize, Deserialize, Debug)]
+struct ChangeCarrierFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    carrier: i32,
+}
+
+// struct for getting the user's details for a tracking number from the client, missing values are left as they are and
+// empty strings clear them
+#[derive(Serialize, Deserialize, Debug)]
+struct TrackingNumberDetailsFromClient {
+    #[serde(deserialize_with = "number_detection::deserialize_tracking_number")]
+    number: String,
+    label: Option<String>,
+    note: Option<String>,
+    pinned: Option<bool>,
+    color: Option<String>,
 }
 
 /*
@@ -187,6 +476,40 @@ async fn delete_number_single(
     }
 }
 
+/// Function for calling the API to change the carrier of a single number
+async fn change_carrier_single(
+    data: web::Data<AppState>,
+    tracking_number: String,
+    carrier_old: Option<i32>,
+    carrier_new: i32,
+) -> Result<change_carrier_response, trackingapi::tracking_error> {
+    let tracking_client = data.tracking_client.clone();
+    match tracking_client
+        .change_carrier(&tracking_number, carrier_old, carrier_new)
+        .await
+    {
+        Ok(data) => Ok(data),
+        Err(e) => Err(e),
+    }
+}
+
+/// Function for calling the API to change the carrier parameter of a single number
+async fn change_info_single(
+    data: web::Data<AppState>,
+    tracking_number: String,
+    carrier: Option<i32>,
+    param: trackingapi::carrier_param,
+) -> Result<change_info_response, trackingapi::tracking_error> {
+    let tracking_client = data.tracking_client.clone();
+    match tracking_client
+        .change_info(&tracking_number, carrier, &param)
+        .await
+    {
+        Ok(data) => Ok(data),
+        Err(e) => Err(e),
+    }
+}
+
 /// Function for calling the API to check the status and other information about a number that is registered
 async fn check_number_status_single(
     data: web::Data<AppState>,
@@ -211,7 +534,7 @@ async fn check_number_status_single(
 async fn database_tracking_data_from_number(
     client: web::Data<Client>,
     tracking_number: &str,
-) -> tracking_data_database_form {
+) -> Result<tracking_data_database_form, HttpResponse> {
     // set database, relation and filter for getting tracking data
     let db = client.database("teletrack");
     let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
@@ -219,8 +542,12 @@ async fn database_tracking_data_from_number(
     let filter = doc! {"data.number": &tracking_number};
 
     // get the tracking data from the database
-    let query_result = match collection_tracking_data.find_one(filter, None).await {
-        Ok(query_result) => Ok(query_result),
+    match collection_tracking_data.find_one(filter, None).await {
+        Ok(Some(tracking_data)) => Ok(tracking_data),
+        Ok(None) => {
+            println!("tracking data not found for the client query");
+            Err(HttpResponse::InternalServerError().body("no tracking data found for that number"))
+        }
         Err(e) => {
             println!(
                 "@get_tracking_data_from_database error, getting tracking data from db: {}",
@@ -229,19 +556,34 @@ async fn database_tracking_data_from_number(
             Err(HttpResponse::InternalServerError().body(e.to_string()))
         }
     }
-    .unwrap();
     //
+}
 
-    // open the result
-    match query_result {
-        Some(tracking_data) => Ok(tracking_data),
-        None => {
-            println!("tracking data not found for the client query");
-            Err(HttpResponse::InternalServerError().body("no tracking data found for that number"))
+/// GET the carrier 17TRACK tracks the number with from its tracking data, the relation records only have the carrier when
+/// the user changed it, none when the number has no tracking data yet or 17TRACK didn't detect the carrier
+async fn database_carrier_from_number(
+    client: web::Data<Client>,
+    tracking_number: &str,
+) -> Result<Option<i32>, HttpResponse> {
+    let db = client.database("teletrack");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let options = mongodb::options::FindOneOptions::builder()
+        .projection(doc! {"data.carrier": 1})
+        .build();
+    match collection_tracking_data
+        .find_one(doc! {"data.number": tracking_number}, options)
+        .await
+    {
+        Ok(tracking_data) => Ok(tracking_data
+            .as_ref()
+            .and_then(|tracking_data| tracking_data.get_document("data").ok())
+            .and_then(|data| data.get_i32("carrier").ok())
+            .filter(|carrier| *carrier != 0)),
+        Err(e) => {
+            println!("@DATABASE_CARRIER_FROM_NUMBER: {}", e);
+            Err(HttpResponse::InternalServerError().body(e.to_string()))
         }
     }
-    .unwrap()
-    //
 }
 
 /// GET user ID form user ID hash
@@ -259,62 +601,13 @@ async fn database_user_id_from_hash(client: web::Data<Client>, user_id_hash: &st
     //
 }
 
-/// GET delivered bool from tracking number
-async fn database_delivered_status(client: web::Data<Client>, tracking_number: &str) -> bool {
-    // set database, relation and filter for getting tracking data
-    let db = client.database("teletrack");
-    let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
-        db.collection("tracking_data");
-    let filter = doc! {"data.number": &tracking_number};
-
-    // get the tracking data from the database
-    let query_result = match collection_tracking_data.find_one(filter, None).await {
-        Ok(query_result) => Ok(query_result),
-        Err(e) => {
-            println!(
-                "@DATABASE_DELIVERED_STATUS: error getting tracking data from db: {}",
-                e
-            );
-            Err(HttpResponse::InternalServerError().body(e.to_string()))
-        }
-    }
-    .unwrap();
-    //
-
-    // open the result
-    let latest_status = match query_result {
-        Some(tracking_data) => &tracking_data.data.track_info.latest_status.status.unwrap(),
-        None => {
-            println!("tracking data not found for the client query");
-            return false;
-        }
-    };
-    //
-
-    if latest_status == "Delivered" {
-        return true;
-    } else {
-        return false;
-    }
-}
-
 /// GET delivered bool from tracking_data_database_form
 fn database_delivered_status_from_DBF(tracking_data_dbf: tracking_data_database_form) -> bool {
-    // open the result
-    match tracking_data_dbf.data.track_info.latest_status.status {
-        Some(latest_status) => {
-            if latest_status == "Delivered" {
-                return true;
-            } else {
-                return false;
-            }
-        }
-        None => {
-            println!("value not set");
-            return false;
-        }
-    };
-    //
+    tracking_data_dbf
+        .data
+        .track_info
+        .latest_status
+        .is_delivered()
 }
 
 /// GET remaining tracking quota from user ID hash
@@ -375,6 +668,223 @@ async fn database_decrement_user_quota(client: web::Data<Client>, user_id_hash:
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
 */
 
+/// Copy the user's own details from the relation record to the HTML form of the tracking data, viewers don't get to see
+/// the full shipping addresses or the full addresses of the events
+fn set_relation_details(
+    tracking_data_html: &mut tracking_data_HTML,
+    relation: &TrackingNumberUserRelation,
+) {
+    tracking_data_html.label = relation.label.clone();
+    tracking_data_html.note = relation.note.clone();
+    tracking_data_html.pinned = Some(relation.pinned);
+    tracking_data_html.color = relation.color.clone();
+    tracking_data_html.is_owner = Some(relation.role == RelationRole::Owner);
+    tracking_data_html.archived = Some(relation.archived);
+    if relation.role == RelationRole::Viewer {
+        tracking_data_html.shipping_info = tracking_data_html
+            .shipping_info
+            .as_ref()
+            .map(|shipping_info| shipping_info.redacted());
+        let redact = |address: &mut Option<
+            my_structs::tracking_data_formats::tracking_data_base::Address,
+        >| {
+            *address = address.as_ref().map(|address| address.redacted());
+        };
+        redact(&mut tracking_data_html.latest_event.address);
+        for provider in tracking_data_html.providers_data.iter_mut() {
+            provider
+                .provider_events
+                .iter_mut()
+                .for_each(|event| redact(&mut event.address));
+        }
+        tracking_data_html
+            .timeline
+            .iter_mut()
+            .for_each(|event| redact(&mut event.address));
+    }
+}
+
+/// Filter for the user's own relation record of a tracking number, the group chat relation records the user made are left out
+fn user_relation_filter(tracking_number: &str, user_id_hash: &str) -> Document {
+    doc! {"tracking_number": tracking_number, "user_id_hash": user_id_hash, "chat_id": Bson::Null}
+}
+
+/// Check that the relation record belongs to the owner of the tracking number, respond with 526 if not
+fn check_owner(relation: &TrackingNumberUserRelation) -> Result<(), HttpResponse> {
+    match relation.role {
+        RelationRole::Owner => Ok(()),
+        RelationRole::Viewer => {
+            println!("@NO_PERMISSION: user is a viewer of the tracking number");
+            Err(HttpResponse::build(
+                StatusCode::from_u16(526).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "only the owner of the tracking number can do that"})))
+        }
+    }
+}
+
+/// Check if the tracking number is a manual shipment, those are made by the user and the API never hears of them
+async fn is_manual_shipment(
+    client: web::Data<Client>,
+    tracking_number: &str,
+) -> Result<bool, HttpResponse> {
+    let db = client.database("teletrack");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let filter = doc! {"data.number": tracking_number, "manual": true};
+    match collection_tracking_data.count_documents(filter, None).await {
+        Ok(count) => Ok(count > 0),
+        Err(e) => {
+            println!("@IS_MANUAL_SHIPMENT: database error: {}", e);
+            Err(HttpResponse::InternalServerError().body(e.to_string()))
+        }
+    }
+}
+
+/// Check the tracking number isn't a manual shipment before asking the API about it, respond with 543 if it is
+async fn check_not_manual(
+    client: web::Data<Client>,
+    tracking_number: &str,
+) -> Result<(), HttpResponse> {
+    match is_manual_shipment(client, tracking_number).await? {
+        false => Ok(()),
+        true => {
+            println!(
+                "@CHECK_NOT_MANUAL: {} is a manual shipment",
+                tracking_number
+            );
+            Err(HttpResponse::build(
+                StatusCode::from_u16(543).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(
+                serde_json::json!({"expected error": "manual shipments aren't tracked by the API"}),
+            ))
+        }
+    }
+}
+
+/// Get the relation record and the tracking data of a manual shipment for changing its events, only the owner can change
+/// them, respond with 543 if the number isn't a manual shipment
+async fn manual_shipment_for_owner(
+    client: web::Data<Client>,
+    tracking_number: &str,
+    user_id_hash: &str,
+) -> Result<(TrackingNumberUserRelation, tracking_data_database_form), HttpResponse> {
+    let relation =
+        check_relation_and_get_record(client.clone(), tracking_number, user_id_hash).await?;
+    check_owner(&relation)?;
+
+    let db = client.database("teletrack");
+    let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
+        db.collection("tracking_data");
+    let filter = doc! {"data.number": tracking_number, "manual": true};
+    match collection_tracking_data.find_one(filter, None).await {
+        Ok(Some(tracking_data)) => Ok((relation, tracking_data)),
+        Ok(None) => {
+            println!(
+                "@MANUAL_SHIPMENT: {} is not a manual shipment",
+                tracking_number
+            );
+            Err(HttpResponse::build(
+                StatusCode::from_u16(543).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "not a manual shipment, its events come from the API"})))
+        }
+        Err(e) => {
+            println!("@MANUAL_SHIPMENT: database error: {}", e);
+            Err(HttpResponse::InternalServerError().body(e.to_string()))
+        }
+    }
+}
+
+/// Save the changed events of a manual shipment and respond with its tracking data in the HTML form like the other
+/// tracking data responses, the events are only saved over the ones they were changed from so two changes at the same
+/// time can't undo each other, respond with 549 if the shipment was changed in the meantime
+async fn save_manual_events(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    relation: TrackingNumberUserRelation,
+    mut tracking_data: tracking_data_database_form,
+    events: Vec<my_structs::tracking_data_formats::tracking_data_base::event>,
+) -> HttpResponse {
+    let read_updated_at = tracking_data.updated_at;
+    manual_shipments::set_manual_events(&mut tracking_data, events);
+    match tracking_history::save_tracking_data_if_unchanged(
+        &client,
+        &tracking_data,
+        read_updated_at,
+        snapshot_source::Manual,
+    )
+    .await
+    {
+        Ok(true) => HttpResponse::Ok().json(tracked_number_html_form(
+            &relation,
+            tracking_data,
+            &data.carrier_directory,
+        )),
+        Ok(false) => {
+            println!("@SAVE_MANUAL_EVENTS: the events were changed in the meantime");
+            HttpResponse::build(
+                StatusCode::from_u16(549).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "manual shipment was changed at the same time"}))
+        }
+        Err(e) => {
+            println!("@SAVE_MANUAL_EVENTS: error saving the events: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Check the X-Admin-Key header of the admin routes against ADMIN_API_KEY, respond with 401 if it's wrong or the key isn't set,
+/// the digests are compared so the time doesn't tell how much of the key was right
+fn check_admin(data: &AppState, request: &HttpRequest) -> Result<(), HttpResponse> {
+    let admin_api_key = match &data.admin_api_key {
+        Some(admin_api_key) => admin_api_key,
+        None => {
+            println!("@CHECK_ADMIN: ADMIN_API_KEY not set, admin routes are off");
+            return Err(HttpResponse::Unauthorized().finish());
+        }
+    };
+    let key_from_client = request
+        .headers()
+        .get("X-Admin-Key")
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or_default();
+    match Sha256::digest(key_from_client.as_bytes()) == Sha256::digest(admin_api_key.as_bytes()) {
+        true => Ok(()),
+        false => {
+            println!("@CHECK_ADMIN: admin key missing or wrong");
+            Err(HttpResponse::Unauthorized().finish())
+        }
+    }
+}
+
+/// Check the details the user wants to save for a tracking number, returns the reason if they're not acceptable
+fn validate_tracking_number_details(
+    details: &TrackingNumberDetailsFromClient,
+) -> Result<(), &'static str> {
+    if let Some(label) = &details.label {
+        if label.chars().count() > MAX_LABEL_LENGTH {
+            return Err("label is too long");
+        }
+    }
+    if let Some(note) = &details.note {
+        if note.chars().count() > MAX_NOTE_LENGTH {
+            return Err("note is too long");
+        }
+    }
+    // colors are hex codes like #1a2b3c
+    if let Some(color) = &details.color {
+        let is_hex_color = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !color.is_empty() && !is_hex_color {
+            return Err("color has to be a hex code like #1a2b3c");
+        }
+    }
+    Ok(())
+}
+
 /// Check if the userID hash exists on the data base, if it doesn't it means the request came from a new user and the server
 /// can't send notifications right now, respond with a status code 520:'User not found' which the client app should resolve
 /// by sending a UserID and Name of the user
@@ -415,6 +925,16 @@ async fn check_user_exists(
             Ok(user.user_id_hash) // Return the user ID hash as hex string
         }
         Ok(None) => {
+            // the client may still be holding an identifier from before a re-hash, the old one can't be trusted to get the
+            // current one (the legacy ones are plain SHA-256 of the user ID) so the client has to prove who the user is
+            let filter = doc! {"previous_user_id_hashes": &user_id_hash};
+            if let Ok(Some(_)) = collection.find_one(filter, None).await {
+                println!("@CHECK_USER_EXISTS: user found with a previous identifier");
+                return Err(HttpResponse::build(
+                    StatusCode::from_u16(522).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                )
+                .json(serde_json::json!({"expected error": "user identifier changed, recover it with the telegram init data"})));
+            }
             println!("@CHECK_USER_EXISTS: user not found");
             Err(HttpResponse::build(
                 StatusCode::from_u16(520).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
@@ -430,11 +950,14 @@ async fn check_user_exists(
 
 /// Create the user but before check again if the user already exists on the database, double check act as a guard in case this
 /// function is ever used in a context where it is not triggered by the predicted interaction
+/// the user ID hash is keyed with the server secret so the client can't compute it, it gets it back from here and keeps it
 // TODO: add lock so this can't be accessed while another thread is running this function
 async fn create_user(
     client: web::Data<Client>,
-    user_details: UserDetailsFromClient,
-) -> Result<bool, UserCheckError> {
+    user_id_hasher: &user_id_hasher,
+    user_id: i64,
+    user_name: String,
+) -> Result<String, UserCheckError> {
     println!("@CREATE_USER: creating user now...");
 
     let db = client.database("teletrack");
@@ -442,13 +965,14 @@ async fn create_user(
 
     // create the user document
     let user = UserDatabaseForm {
-        user_id: user_details.user_id.clone(),
-        user_id_hash: encode(Sha256::digest(
-            user_details.user_id.abs().to_string().as_bytes(),
-        )),
-        user_name: user_details.user_name,
+        user_id,
+        user_id_hash: user_id_hasher.hash_user_id(user_id),
+        user_name,
         remaining_tracking_quota: DEFAULT_TRACKING_QUOTA,
+        user_id_hash_key_id: Some(user_id_hasher.key_id().to_string()),
+        previous_user_id_hashes: Vec::new(),
     };
+    let user_id_hash = user.user_id_hash.clone();
 
     // check if the user exists already
     let filter = doc! {"user_id_hash": &user.user_id_hash};
@@ -465,12 +989,11 @@ async fn create_user(
             eprintln!("@CREATE_USER: database error in @CREATE_USER: {}", e);
             Err(UserCheckError::DatabaseError(e))
         }
-    }
-    .unwrap();
+    }?;
 
     // insert the user
     match collection.insert_one(user, None).await {
-        Ok(_) => Ok(true),
+        Ok(_) => Ok(user_id_hash),
         Err(e) => Err(UserCheckError::DatabaseError(e)),
     }
 }
@@ -488,7 +1011,7 @@ async fn check_relation(
     let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
         db.collection("tracking_number_user_relation");
     // set search filter
-    let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(tracking_number, user_id_hash);
     // find the relation record in the database
     match collection_relations.find_one(filter.clone(), None).await {
         Ok(Some(_)) => Ok(()),
@@ -506,23 +1029,23 @@ async fn check_relation(
     //
 }
 
-/// Function to check if the user has a relation to the tracking number in the database and return subscribed status
+/// Function to check if the user has a relation to the tracking number in the database and return the relation record
 // TODO: merge <-
-async fn check_relation_and_subscribed_status(
+async fn check_relation_and_get_record(
     client: web::Data<Client>,
     tracking_number: &str,
     user_id_hash: &str,
-) -> Result<bool, HttpResponse> {
+) -> Result<TrackingNumberUserRelation, HttpResponse> {
     // set database
     let db = client.database("teletrack");
     // set collection
     let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
         db.collection("tracking_number_user_relation");
     // set search filter
-    let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(tracking_number, user_id_hash);
     // find the relation record in the database
     match collection_relations.find_one(filter.clone(), None).await {
-        Ok(Some(relation_record)) => Ok(relation_record.is_subscribed),
+        Ok(Some(relation_record)) => Ok(relation_record),
         Ok(None) => {
             println!("@NO_PERMISSION: relation record not found");
             Err(HttpResponse::build(
@@ -537,11 +1060,14 @@ async fn check_relation_and_subscribed_status(
     //
 }
 
-/// Function to insert a relation record between a user and a tracking number
+/// Function to insert a relation record between a user and a tracking number, the user is a viewer if the number was shared
+/// with them by the owner in shared_by, the relation belongs to a group chat if chat_id is set
 async fn insert_relation(
     client: web::Data<Client>,
     tracking_number: String,
     user_id_hash: String,
+    shared_by: Option<String>,
+    chat_id: Option<i64>,
 ) -> Result<(), HttpResponse> {
     // create the relation record and put it in the database
     let tracking_user_relation: TrackingNumberUserRelation = TrackingNumberUserRelation {
@@ -549,6 +1075,22 @@ async fn insert_relation(
         carrier: None,
         user_id_hash: user_id_hash,
         is_subscribed: true,
+        label: None,
+        note: None,
+        pinned: false,
+        color: None,
+        role: match shared_by {
+            Some(_) => RelationRole::Viewer,
+            None => RelationRole::Owner,
+        },
+        shared_by,
+        chat_id,
+        archived: false,
+        archived_at: None,
+        unarchived: false,
+        registered_at: Some(Utc::now().timestamp()),
+        updated_at: Some(mongodb::bson::DateTime::now()),
+        id: None,
     };
     // set database
     let db = client.database("teletrack");
@@ -575,8 +1117,374 @@ async fn insert_relation(
     //
 }
 
-/// Function to insert the tracking data in database format to the database
-async fn refresh_and_return_tracking_data(
+/// Function to delete the viewer relation records and the share invites an owner made for a tracking number
+async fn delete_shared_access(
+    client: web::Data<Client>,
+    tracking_number: &str,
+    owner_user_id_hash: &str,
+) -> Result<(), HttpResponse> {
+    // set database and collections
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let collection_invites: mongodb::Collection<ShareInvite> = db.collection("share_invites");
+
+    // delete the viewers and leave tombstones for their clients, then delete the invites
+    let filter = doc! {"tracking_number": tracking_number, "role": "viewer", "shared_by": owner_user_id_hash};
+    let viewer_user_id_hashes: Vec<String> =
+        match collection_relations.find(filter.clone(), None).await {
+            Ok(cursor) => match cursor
+                .try_collect::<Vec<TrackingNumberUserRelation>>()
+                .await
+            {
+                Ok(viewers) => viewers.into_iter().map(|r| r.user_id_hash).collect(),
+                Err(e) => {
+                    println!("@DELETE_SHARED_ACCESS: error finding viewers: {}", e);
+                    return Err(HttpResponse::InternalServerError().body(e.to_string()));
+                }
+            },
+            Err(e) => {
+                println!("@DELETE_SHARED_ACCESS: error finding viewers: {}", e);
+                return Err(HttpResponse::InternalServerError().body(e.to_string()));
+            }
+        };
+    if let Err(e) = collection_relations.delete_many(filter, None).await {
+        println!("@DELETE_SHARED_ACCESS: error deleting viewers: {}", e);
+        return Err(HttpResponse::InternalServerError().body(e.to_string()));
+    }
+    insert_tombstones(client.clone(), tracking_number, viewer_user_id_hashes).await?;
+    let filter =
+        doc! {"tracking_number": tracking_number, "owner_user_id_hash": owner_user_id_hash};
+    if let Err(e) = collection_invites.delete_many(filter, None).await {
+        println!("@DELETE_SHARED_ACCESS: error deleting share invites: {}", e);
+        return Err(HttpResponse::InternalServerError().body(e.to_string()));
+    }
+    Ok(())
+}
+
+/// Function to record that relation records were deleted so the clients of the users can drop the number from their cache
+/// on the next sync (see @SYNC_TRACKED_NUMBERS)
+async fn insert_tombstones(
+    client: web::Data<Client>,
+    tracking_number: &str,
+    user_id_hashes: Vec<String>,
+) -> Result<(), HttpResponse> {
+    if user_id_hashes.is_empty() {
+        return Ok(());
+    }
+    // set database and collection
+    let db = client.database("teletrack");
+    let collection_tombstones: mongodb::Collection<RelationTombstone> =
+        db.collection("relation_tombstones");
+
+    let tombstones = user_id_hashes
+        .into_iter()
+        .map(|user_id_hash| RelationTombstone {
+            tracking_number: tracking_number.to_string(),
+            user_id_hash,
+            deleted_at: mongodb::bson::DateTime::now(),
+        });
+    match collection_tombstones.insert_many(tombstones, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            println!("@INSERT_TOMBSTONES: {}", e);
+            Err(HttpResponse::InternalServerError().body(e.to_string()))
+        }
+    }
+}
+
+/// Function to find which of the tracking numbers the user has a relation record for
+async fn check_relations_exist(
+    client: web::Data<Client>,
+    user_id_hash: &str,
+    tracking_numbers: Vec<String>,
+) -> Result<Vec<String>, HttpResponse> {
+    // set database and collection
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+
+    let filter = doc! {
+        "user_id_hash": user_id_hash,
+        "chat_id": Bson::Null,
+        "tracking_number": {"$in": tracking_numbers},
+    };
+    let relations = match collection_relations.find(filter, None).await {
+        Ok(cursor) => {
+            cursor
+                .try_collect::<Vec<TrackingNumberUserRelation>>()
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    match relations {
+        Ok(relations) => Ok(relations.into_iter().map(|r| r.tracking_number).collect()),
+        Err(e) => {
+            println!("@CHECK_RELATIONS_EXIST: {}", e);
+            Err(HttpResponse::InternalServerError().body(e.to_string()))
+        }
+    }
+}
+
+/// Function to get the user's relation records together with the tracking data of their numbers in one aggregation, the
+/// pipeline is one of the user tracked numbers pipelines (see database_pipelines.rs)
+async fn user_tracked_numbers(
+    client: web::Data<Client>,
+    pipeline: Vec<Document>,
+) -> Result<Vec<(TrackingNumberUserRelation, tracking_data_database_form)>, HttpResponse> {
+    Ok(user_tracked_numbers_joined(client, pipeline)
+        .await?
+        .into_iter()
+        .map(|joined| (joined.relation, joined.tracking_data))
+        .collect())
+}
+
+/// Same as @USER_TRACKED_NUMBERS but with the joined documents as they are, with the sort key of the list pages
+async fn user_tracked_numbers_joined(
+    client: web::Data<Client>,
+    pipeline: Vec<Document>,
+) -> Result<Vec<TrackedNumberJoined>, HttpResponse> {
+    // set database and relation
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+
+    // join the relation records with the tracking data
+    let tracked_numbers_cursor = match collection_relations.aggregate(pipeline, None).await {
+        Ok(query_result) => query_result,
+        Err(e) => {
+            println!("@USER_TRACKED_NUMBERS: {}", e);
+            return Err(HttpResponse::InternalServerError().body(e.to_string()));
+        }
+    };
+    let tracked_numbers: Vec<Document> = match tracked_numbers_cursor.try_collect().await {
+        Ok(tracked_numbers) => tracked_numbers,
+        Err(e) => {
+            println!("@USER_TRACKED_NUMBERS: {}", e);
+            return Err(HttpResponse::InternalServerError().body(e.to_string()));
+        }
+    };
+    //
+
+    // convert the joined documents
+    tracked_numbers
+        .into_iter()
+        .map(|tracked_number| {
+            match mongodb::bson::from_document::<TrackedNumberJoined>(tracked_number) {
+                Ok(joined) => Ok(joined),
+                Err(e) => {
+                    println!("@USER_TRACKED_NUMBERS: {}", e);
+                    Err(HttpResponse::InternalServerError().body(e.to_string()))
+                }
+            }
+        })
+        .collect()
+    //
+}
+
+/// Convert the tracking data to HTML form and set the is_user_tracked value and the user's details from the relation record,
+/// the providers 17TRACK didn't give a name get the one from the carrier directory
+fn tracked_number_html_form(
+    relation: &TrackingNumberUserRelation,
+    tracking_data: tracking_data_database_form,
+    carriers: &carrier_directory,
+) -> tracking_data_HTML {
+    let mut html_package_data_form = tracking_data.convert_to_HTML_form();
+    set_relation_details(&mut html_package_data_form, relation);
+    for provider in html_package_data_form.providers_data.iter_mut() {
+        if provider.provider_name.is_none() {
+            provider.provider_name = provider
+                .provider_key
+                .and_then(|key| carriers.get(key))
+                .map(|carrier| carrier.name.clone());
+        }
+    }
+    for provider in html_package_data_form
+        .timeline
+        .iter_mut()
+        .flat_map(|event| event.providers.iter_mut())
+    {
+        if provider.provider_name.is_none() {
+            provider.provider_name = provider
+                .provider_key
+                .and_then(|key| carriers.get(key))
+                .map(|carrier| carrier.name.clone());
+        }
+    }
+    // Check if the user is tracking this number and get subscription status
+    html_package_data_form.is_user_tracked = match database_delivered_status_from_DBF(tracking_data)
+    {
+        // If package is delivered, not tracked regardless of subscription
+        true => Some(false),
+        // If not delivered, check if user is tracking it
+        false => Some(relation.is_subscribed),
+    };
+    html_package_data_form
+}
+
+/// Function to make the ETag of the tracking data response for the user, from the hashes 17TRACK gives the providers and
+/// their events, the time the data was saved and everything from the relation record that's in the response
+fn tracking_data_etag(
+    tracking_data: &tracking_data_database_form,
+    relation: &TrackingNumberUserRelation,
+) -> String {
+    let tracking = &tracking_data.data.track_info.tracking;
+    let version = serde_json::json!([
+        tracking.providers_hash,
+        tracking
+            .providers
+            .iter()
+            .map(|provider| provider.events_hash)
+            .collect::<Vec<_>>(),
+        tracking_data
+            .updated_at
+            .map(|updated_at| updated_at.timestamp_millis()),
+        tracking_data.data.track_info.latest_status.status,
+        relation
+            .updated_at
+            .map(|updated_at| updated_at.timestamp_millis()),
+        relation.is_subscribed,
+        relation.label,
+        relation.note,
+        relation.pinned,
+        relation.color,
+        relation.role,
+        relation.archived,
+    ]);
+    let digest = Sha256::digest(version.to_string().as_bytes());
+    format!("\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Convert the tracking data to the summary form with the user's own details from the relation record, the carrier name
+/// comes from the carrier directory when 17TRACK didn't give one
+fn tracked_number_summary_form(
+    relation: &TrackingNumberUserRelation,
+    tracking_data: tracking_data_database_form,
+    carriers: &carrier_directory,
+) -> tracking_data_summary {
+    let mut summary = tracking_data.convert_to_summary_form();
+    if summary.carrier_name.is_none() {
+        summary.carrier_name = carriers
+            .get(tracking_data.data.carrier)
+            .map(|carrier| carrier.name.clone());
+    }
+    summary.label = relation.label.clone();
+    summary.pinned = Some(relation.pinned);
+    summary.color = relation.color.clone();
+    summary.is_user_tracked = match database_delivered_status_from_DBF(tracking_data) {
+        true => Some(false),
+        false => Some(relation.is_subscribed),
+    };
+    summary
+}
+
+/// Carriers the number format points to, for the client to offer when the API can't detect the carrier, in rank order
+/// with the names from the carrier directory
+fn carrier_suggestions(
+    candidates: &[number_detection::carrier_candidate],
+    carriers: &carrier_directory,
+) -> Vec<serde_json::Value> {
+    candidates
+        .iter()
+        .filter_map(|candidate| {
+            let carrier = carriers.get(candidate.carrier)?;
+            Some(serde_json::json!({
+                "carrier": carrier.key,
+                "name": carrier.name,
+                "format": candidate.format,
+            }))
+        })
+        .collect()
+}
+
+/// Function to get one page of the user's tracked numbers with the filters and sorting of the client's list query, the
+/// pipeline is one of the user tracked numbers pipelines (see database_pipelines.rs) and the database does the filtering,
+/// sorting and paging, returns the page and the cursor of the next page if there is one, unpaged returns every number
+async fn user_tracked_numbers_page(
+    client: web::Data<Client>,
+    mut pipeline: Vec<Document>,
+    query: &TrackedNumbersQueryFromClient,
+    paged: bool,
+) -> Result<
+    (
+        Vec<(TrackingNumberUserRelation, tracking_data_database_form)>,
+        Option<String>,
+    ),
+    HttpResponse,
+> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    // open the cursor, it has to be from a list with the same sorting
+    let cursor = match &query.cursor {
+        Some(cursor) => match base64::engine::general_purpose::URL_SAFE
+            .decode(cursor)
+            .ok()
+            .and_then(|cursor| serde_json::from_slice::<TrackedNumbersCursor>(&cursor).ok())
+        {
+            Some(cursor) if cursor.sort == query.sort => Some(cursor),
+            _ => {
+                return Err(HttpResponse::build(
+                    StatusCode::from_u16(545).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                )
+                .json(serde_json::json!({"expected error": "invalid list cursor"})))
+            }
+        },
+        None => None,
+    };
+    //
+
+    // filter, sort and cut the page in the database, one more than the page to know if there's a next one
+    let filter = database_pipelines::tracked_numbers_filter(
+        query.status.as_ref().map(|status| status.as_str()),
+        query.subscribed,
+        query.carrier,
+        query.label.as_deref(),
+    );
+    let sort_key = match query.sort {
+        TrackedNumbersSort::LastEvent => database_pipelines::last_event_sort_key(),
+        TrackedNumbersSort::Registered => database_pipelines::registered_sort_key(),
+        TrackedNumbersSort::Eta => database_pipelines::eta_sort_key(),
+    };
+    pipeline.extend(database_pipelines::tracked_numbers_page_stages(
+        filter,
+        sort_key,
+        cursor
+            .as_ref()
+            .map(|cursor| (cursor.key, cursor.tracking_number.as_str())),
+        paged.then_some(limit as i64 + 1),
+    ));
+    let mut page = user_tracked_numbers_joined(client, pipeline).await?;
+    //
+
+    let next_cursor = match paged && page.len() > limit {
+        true => {
+            page.truncate(limit);
+            page.last().map(|joined| {
+                let cursor = TrackedNumbersCursor {
+                    sort: query.sort,
+                    key: joined.sort_key.unwrap_or(i64::MAX),
+                    tracking_number: joined.relation.tracking_number.clone(),
+                };
+                base64::engine::general_purpose::URL_SAFE
+                    .encode(serde_json::to_vec(&cursor).unwrap_or_default())
+            })
+        }
+        false => None,
+    };
+
+    Ok((
+        page.into_iter()
+            .map(|joined| (joined.relation, joined.tracking_data))
+            .collect(),
+        next_cursor,
+    ))
+}
+
+/// Function to insert the tracking data in database format to the database
+async fn refresh_and_return_tracking_data(
     client: web::Data<Client>,
     data: web::Data<AppState>,
     tracking_number: String,
@@ -605,45 +1513,22 @@ async fn refresh_and_return_tracking_data(
 
     // convert the tracking_data_get_info to tracking_data_database_form
     let tracking_data_database_form = gettrackinfo_result.convert_to_tracking_data_dbf();
-    // set database
-    let db = client.database("teletrack");
-    // set collection
-    let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
-        db.collection("tracking_data");
-    let filter = doc! {"data.number": &tracking_number};
-
-    // delete any previous info with that tracking number
-    match collection_tracking_data.delete_many(filter, None).await {
-        Ok(delete_result) => {
-            println!("deleted {} tracking data docs", delete_result.deleted_count);
-            Ok(())
-        }
-        Err(e) => {
-            println!(
-                "@REFRESH_TRACKING_DATA: non fatal error deleting old tracking data: {}",
-                e
-            );
-            Err(e)
-        }
-    }
-    .unwrap();
-    //
 
-    // insert the fresh tracking info into the database
-    match collection_tracking_data
-        .insert_one(tracking_data_database_form.clone(), None)
-        .await
+    // replace the previous info with the fresh one in one step and keep the history
+    match tracking_history::save_tracking_data(
+        &client,
+        &tracking_data_database_form,
+        snapshot_source::Pull,
+    )
+    .await
     {
         Ok(_) => {
             // returning the fresh tracking info to the user
-            println!("tracking data inserted");
+            println!("tracking data saved");
             Ok(tracking_data_database_form)
         }
         Err(e) => {
-            println!(
-                "@REGISTER_TRACKING_NUMBER: error inserting tracking data: {}",
-                e
-            );
+            println!("@REFRESH_TRACKING_DATA: error saving tracking data: {}", e);
             Err(HttpResponse::InternalServerError().body(e.to_string()))
         }
     }
@@ -671,27 +1556,36 @@ async fn simulate_webhook_notification_one_user(
     tracking_number: &str,
 ) {
     // get tracking info from database
-    let tracking_data = database_tracking_data_from_number(client.clone(), tracking_number).await;
+    let tracking_data =
+        match database_tracking_data_from_number(client.clone(), tracking_number).await {
+            Ok(tracking_data) => tracking_data,
+            Err(_) => return,
+        };
     //
 
     // convert the tracking data to html format
     let tracking_data_html = tracking_data.convert_to_HTML_form();
 
     // build the message that will be displayed in the chat window and notification banner
-    // dump the description
-    let message = "Update on your order tracking: ".to_string()
-        + tracking_data_html.tracking_number.as_str()
-        + "\n"
-        + tracking_data_html
+    // dump the description, the relation was just made so there's no label yet
+    let message = webhook::tracking_update_message(
+        &tracking_data_html.tracking_number,
+        None,
+        &tracking_data_html
             .latest_event
             .description
-            .unwrap()
-            .as_str();
+            .unwrap_or_default(),
+    );
 
     // me ne frega
-    let _ =
-        webhook::notify_of_tracking_event_update(data.clone(), user_id, &message, tracking_number)
-            .await;
+    let _ = webhook::notify_of_tracking_event_update(
+        data.clone(),
+        user_id,
+        &message,
+        tracking_number,
+        notification_priority::Normal,
+    )
+    .await;
 }
 
 /*
@@ -703,16 +1597,31 @@ async fn simulate_webhook_notification_one_user(
 
     list of custom 5XX codes:
             520 - user doesn't exist yet, client should send request to create user
+            522 - user identifier was re-hashed, client should send the telegram init data to /recover_user and keep the one it gets
     TODO:   521 - user already exists, handle error
             525 - user doesn't have access to that number, no relation record found
+            526 - user is a viewer of that number, only the owner can do that
+            527 - share invite is invalid or expired
+            528 - share invite was already used
             530 - carrier not found, client should send a register number request that includes a carrier
-            531 - tracking number was not found by the API when trying to register it
+            531 - tracking number was not found by the API when trying to register it, or the API rejected the carrier
             533 - package has been marked delivered so it can't be re-tracked
             534 - already set to subscribed
             535 - already set to unsubscribed
             536 - no relation record found to delete
+            537 - unknown carrier, client should pick one from the /carriers list
+            538 - tracking number can't be valid (length, characters or check digit), nothing was sent to the API
+            539 - the carrier needs an extra parameter (postal code, phone digits, ship date), client should ask the user for it
             540 - tracking quota reached limit, sorry
             541 - relation record already exists
+            542 - provider quota is almost used up, new numbers are refused until it's topped up
+            543 - that's a manual shipment, the API doesn't track it / that's not a manual shipment, its events come from the API
+            544 - tracking number is not archived
+            545 - invalid list cursor, client should load the list again from the first page
+            546 - invalid or expired sync cursor, client should drop its cache and sync without a cursor
+            547 - invalid or expired live updates ticket, client should get a new one
+            548 - the API didn't change the carrier, it's already that carrier or it was changed too many times
+            549 - the manual shipment was changed at the same time, client should load it again and redo the change
 
 
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
@@ -721,9 +1630,11 @@ async fn simulate_webhook_notification_one_user(
 // CREATE USER
 
 /// Function for responding to a client request to create a new user
-/// the header has to have the hashed user ID like the other function, and the raw user ID + name in the body of the function as a json
+/// the header has to have the hashed user ID like the other function, and the init data + name in the body of the function as a json,
+/// the user is only created for the user telegram signed the init data for, the identifier is the credential of the user
 async fn create_user_handler(
     client: web::Data<Client>,
+    app_data: web::Data<AppState>,
     request: HttpRequest,
     data: Json<UserDetailsFromClient>,
 ) -> impl Responder {
@@ -740,10 +1651,76 @@ async fn create_user_handler(
         }
 
         // user doesn't exist yet
-        Err(response) => match create_user(client.clone(), data.into_inner()).await {
-            Ok(_) => return HttpResponse::Ok().body("user created"),
-            Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
-        },
+        Err(response) => {
+            let user_details = data.into_inner();
+            let user_id = match verify_telegram_init_data(
+                &user_details.init_data,
+                &app_data.telegram_bot_token,
+            ) {
+                Ok(user_id) => user_id,
+                Err(e) => {
+                    println!("@CREATE_USER: {}", e);
+                    return HttpResponse::Unauthorized()
+                        .json(serde_json::json!({"error": e.to_string()}));
+                }
+            };
+            match create_user(
+                client.clone(),
+                &app_data.user_id_hasher,
+                user_id,
+                user_details.user_name,
+            )
+            .await
+            {
+                // the client has to save the identifier and send it in the X-User-ID-Hash header from now on
+                Ok(user_id_hash) => return HttpResponse::Ok().json(
+                    serde_json::json!({"message": "user created", "user_id_hash": user_id_hash}),
+                ),
+                Err(UserCheckError::UserAlreadyExists) => {
+                    return HttpResponse::build(
+                        StatusCode::from_u16(521).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    )
+                    .json(serde_json::json!({"unexpected error": "user already exists"}))
+                }
+                Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Function for responding to a client that got a 522 because it's still using an identifier from before a re-hash, the
+/// user is only trusted from the init data telegram signed, the old identifier alone doesn't prove anything
+async fn recover_user_handler(
+    client: web::Data<Client>,
+    app_data: web::Data<AppState>,
+    data: Json<UserRecoveryFromClient>,
+) -> impl Responder {
+    let user_id = match verify_telegram_init_data(&data.init_data, &app_data.telegram_bot_token) {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            println!("@RECOVER_USER: {}", e);
+            return HttpResponse::Unauthorized().json(serde_json::json!({"error": e.to_string()}));
+        }
+    };
+
+    // the identifier is computed again instead of read so only a user made with the current secret is recovered
+    let user_id_hash = app_data.user_id_hasher.hash_user_id(user_id);
+    let db = client.database("teletrack");
+    let collection: mongodb::Collection<UserDatabaseForm> = db.collection("users");
+    match collection
+        .find_one(doc! {"user_id_hash": &user_id_hash}, None)
+        .await
+    {
+        Ok(Some(_)) => HttpResponse::Ok()
+            .json(serde_json::json!({"message": "user recovered", "user_id_hash": user_id_hash})),
+        Ok(None) => HttpResponse::build(
+            StatusCode::from_u16(520).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "user doesn't exist yet"})),
+        Err(e) => {
+            eprintln!("@RECOVER_USER: database error: {}", e);
+            HttpResponse::InternalServerError().body(format!("database error: {}", e))
+        }
     }
 }
 
@@ -767,6 +1744,18 @@ async fn register_tracking_number(
     };
     //
 
+    register_tracking_number_for_user(client, data, user_id_hash, tracking_details.into_inner())
+        .await
+}
+
+/// Function for registering a tracking number for a user that was already checked, the register handler and the bulk
+/// register handler both go through here so a number gets the same checks and responses either way
+async fn register_tracking_number_for_user(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    user_id_hash: String,
+    tracking_details: trackingapi::tracking_number_carrier,
+) -> HttpResponse {
     // check if the user has reached the tracking quota limit
     let user_quota = database_quota_from_hash(client.clone(), &user_id_hash).await;
     if user_quota <= 0 {
@@ -778,6 +1767,57 @@ async fn register_tracking_number(
     }
     //
 
+    // check the carrier is one the API knows
+    if let Some(carrier) = tracking_details.carrier {
+        if data.carrier_directory.get(carrier).is_none() {
+            println!("@REGISTER_TRACKING_NUMBER: unknown carrier {}", carrier);
+            return HttpResponse::build(
+                StatusCode::from_u16(537).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "unknown carrier"}));
+        }
+    }
+    //
+
+    // check the carrier parameter fits its type
+    if let Some(param) = &tracking_details.param {
+        if let Err(reason) = param.validate() {
+            println!("@REGISTER_TRACKING_NUMBER: {}", reason);
+            return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+        }
+    }
+    //
+
+    // check the number locally so obvious typos don't use provider quota, the candidates are suggested if the API can't
+    // detect the carrier
+    let carrier_candidates = match number_detection::detect_carriers(&tracking_details.number) {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            println!(
+                "@REGISTER_TRACKING_NUMBER: invalid tracking number {}: {}",
+                tracking_details.number, e
+            );
+            return HttpResponse::build(
+                StatusCode::from_u16(538).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "invalid tracking number", "reason": e.to_string()}));
+        }
+    };
+    //
+
+    // check the 17TRACK account has quota left for a new number
+    if let Err(e) = data
+        .provider_quota
+        .check_registration(&data.tracking_client, &data.notification_service)
+        .await
+    {
+        return HttpResponse::build(
+            StatusCode::from_u16(542).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "provider quota reached limit", "reason": e.to_string()}));
+    }
+    //
+
     // register the tracking number with the API, throws error
     // the bool value is for knowing whether to pull the tracking info to simulate a webhook update for the user
     let was_registered = match register_single(data.clone(), tracking_details.clone()).await {
@@ -796,13 +1836,24 @@ async fn register_tracking_number(
             )
             .json(serde_json::json!({"expected error": "not found"}));
         }
+        // the carrier needs more than the number, ask the user
+        Err(tracking_error::CarrierParamRequired(message)) => {
+            println!("@REGISTER_TRACKING_NUMBER: carrier parameter required");
+            return HttpResponse::build(
+                StatusCode::from_u16(539).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "carrier parameter required", "message": message}));
+        }
         // unable to find carrier, try again with specific carrier
         Err(tracking_error::RetryTrackRegisterWithCarrier) => {
             println!("@REGISTER_TRACKING_NUMBER: carrier not found, retry with specific carrier");
             return HttpResponse::build(
                 StatusCode::from_u16(530).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
             )
-            .json(serde_json::json!({"expected error": "retry with carrier"}));
+            .json(serde_json::json!({
+                "expected error": "retry with carrier",
+                "candidates": carrier_suggestions(&carrier_candidates, &data.carrier_directory),
+            }));
         }
         // unexpected error
         Err(e) => {
@@ -816,7 +1867,7 @@ async fn register_tracking_number(
     let db = client.database("teletrack");
     let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
         db.collection("tracking_number_user_relation");
-    let filter = doc! {"tracking_number": &tracking_details.number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(&tracking_details.number, &user_id_hash);
     let duplicate_relation_search = collection_relations.find_one(filter.clone(), None).await;
     if let Ok(Some(_)) = duplicate_relation_search {
         println!("relation already exists");
@@ -835,6 +1886,8 @@ async fn register_tracking_number(
         client.clone(),
         tracking_details.number.clone(),
         user_id_hash.clone(),
+        None,
+        None,
     )
     .await
     {
@@ -870,6 +1923,113 @@ async fn register_tracking_number(
     return HttpResponse::Ok().body("registered tracking number");
 }
 
+// EXTRACT TRACKING NUMBERS
+
+/// Function for finding the tracking numbers in text the user pasted, a message or a whole shipping email, it's all done
+/// here without the API (see number_extraction.rs), the numbers the user already tracks are flagged so the client only
+/// offers to register the new ones
+async fn extract_tracking_numbers(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    extraction: Json<ExtractionFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let text = extraction.into_inner().text;
+    if text.len() > number_extraction::MAX_INPUT_LENGTH {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "text is too long"}));
+    }
+    let extracted = number_extraction::extract_tracking_numbers(&text);
+    println!(
+        "@EXTRACT_TRACKING_NUMBERS: found {} numbers",
+        extracted.len()
+    );
+
+    // flag the numbers the user already tracks
+    let numbers = extracted.iter().map(|e| e.number.clone()).collect();
+    let tracked_numbers = match check_relations_exist(client.clone(), &user_id_hash, numbers).await
+    {
+        Ok(tracked_numbers) => tracked_numbers,
+        Err(response) => return response,
+    };
+    //
+
+    let numbers = extracted
+        .iter()
+        .map(|extracted| {
+            serde_json::json!({
+                "number": extracted.number,
+                "carrier_from_url": extracted.carrier_from_url.map(|carrier| serde_json::json!({
+                    "carrier": carrier,
+                    "name": data.carrier_directory.get(carrier).map(|entry| entry.name.clone()),
+                })),
+                "candidates": carrier_suggestions(&extracted.candidates, &data.carrier_directory),
+                "is_tracked": tracked_numbers.contains(&extracted.number),
+            })
+        })
+        .collect::<Vec<_>>();
+    HttpResponse::Ok().json(serde_json::json!({"numbers": numbers}))
+}
+
+/// Function for registering the numbers the user picked from the found ones with one tap, every number goes through the
+/// same registration as a single one, one after the other so the quotas are checked for each, the response has the status
+/// and the response of the registration for every number
+async fn register_tracking_numbers_bulk(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    bulk: Json<BulkRegisterFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let numbers = bulk.into_inner().numbers;
+    if numbers.len() > MAX_BULK_REGISTER {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "too many numbers at once"}));
+    }
+
+    let mut results = Vec::with_capacity(numbers.len());
+    for tracking_details in numbers {
+        let tracking_number = tracking_details.number.clone();
+        let response = register_tracking_number_for_user(
+            client.clone(),
+            data.clone(),
+            user_id_hash.clone(),
+            tracking_details,
+        )
+        .await;
+        let status = response.status().as_u16();
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap_or_default();
+        // the expected errors are json, the rest is text
+        let result = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_else(|_| {
+            serde_json::Value::String(String::from_utf8_lossy(&body).into_owned())
+        });
+        results.push(
+            serde_json::json!({"number": tracking_number, "status": status, "result": result}),
+        );
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({"results": results}))
+}
+
 /// Function for stopping the tracking of a single number, this will pause the updates sent to the webhook, check if any other user is subscribed to that
 /// number on the database before proceeding, update in two stages, turn off notifications then if no one else is linked to that number, untrack it
 async fn stop_tracking_number(
@@ -892,7 +2052,7 @@ async fn stop_tracking_number(
     let db = client.database("teletrack");
     let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
         db.collection("tracking_number_user_relation");
-    let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(&tracking_number, &user_id_hash);
 
     // check if the user has permission for that number
     match check_relation(client.clone(), &tracking_number, &user_id_hash).await {
@@ -901,7 +2061,8 @@ async fn stop_tracking_number(
     }
 
     // send request to the DB to change the is_subscribed value to false
-    let database_update = doc! {"$set":{"is_subscribed": false}};
+    let database_update =
+        doc! {"$set":{"is_subscribed": false, "updated_at": mongodb::bson::DateTime::now()}};
     let update_result = match collection_relations
         .update_one(filter, database_update, None)
         .await
@@ -966,10 +2127,33 @@ async fn retrack_stopped_number(
         Err(result) => return result,
     }
 
-    // get the data about this number from the API
-    let number_status =
-        match check_number_status_single(data.clone(), tracking_number.clone()).await {
-            Ok(number_status) => number_status,
+    // get the important tracking and status information to check from the API, manual shipments aren't on the API so
+    // their stored status is used
+    let is_manual = match is_manual_shipment(client.clone(), &tracking_number).await {
+        Ok(is_manual) => is_manual,
+        Err(response) => return response,
+    };
+    let (tracking_status, package_status) = match is_manual {
+        true => {
+            let tracking_data =
+                match database_tracking_data_from_number(client.clone(), &tracking_number).await {
+                    Ok(tracking_data) => tracking_data,
+                    Err(response) => return response,
+                };
+            let package_status = tracking_data.data.track_info.latest_status.status;
+            (
+                tracking_status::Tracking,
+                package_status.unwrap_or(main_status::NotFound),
+            )
+        }
+        false => match check_number_status_single(data.clone(), tracking_number.clone()).await {
+            Ok(number_status) => {
+                let accepted = &number_status.data.accepted[0];
+                (
+                    accepted.tracking_status.clone(),
+                    accepted.package_status.clone(),
+                )
+            }
             Err(e) => {
                 println!(
                     "@RETRACK_STOPPED_NUMBER: error getting the number status data: {}",
@@ -977,15 +2161,12 @@ async fn retrack_stopped_number(
                 );
                 return HttpResponse::InternalServerError().body(e.to_string());
             }
-        };
+        },
+    };
     //
 
-    // get the important tracking and status information to check
-    let tracking_status = &number_status.data.accepted[0].tracking_status;
-    let package_status = &number_status.data.accepted[0].package_status;
-
     // if the package has been delivered do not update the subscribe value in the database
-    if package_status == "Delivered" {
+    if package_status.is_delivered() {
         println!("the package has been marked delivered and there won't be ant new updates");
         return HttpResponse::build(
             StatusCode::from_u16(533).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
@@ -995,11 +2176,12 @@ async fn retrack_stopped_number(
     //
 
     // send request to the DB to change the is_subscribed value to true
-    let database_update = doc! {"$set":{"is_subscribed": true}};
+    let database_update =
+        doc! {"$set":{"is_subscribed": true, "updated_at": mongodb::bson::DateTime::now()}};
     let db = client.database("teletrack");
     let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
         db.collection("tracking_number_user_relation");
-    let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(&tracking_number, &user_id_hash);
     let update_result = match collection_relations
         .update_one(filter, database_update, None)
         .await
@@ -1014,7 +2196,7 @@ async fn retrack_stopped_number(
     //
 
     // activate it if it's stopped and not yet delivered
-    if tracking_status == "Stopped" && package_status != "Delivered" {
+    if tracking_status.is_stopped() && !package_status.is_delivered() {
         match retrack_stopped_number_single(data.clone(), tracking_number).await {
             Ok(_) => {
                 println!("number has been re-tracked on the API");
@@ -1068,13 +2250,19 @@ async fn delete_tracking_number(
         db.collection("tracking_number_user_relation");
 
     // check if the user has permission for that number
-    match check_relation(client.clone(), &tracking_number, &user_id_hash).await {
-        Ok(_) => (),
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
         Err(result) => return result,
-    }
+    };
 
     // send request to the DB to remove the relation record
-    let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash};
+    let filter = user_relation_filter(&tracking_number, &user_id_hash);
     let update_result = match collection_relations.delete_one(filter, None).await {
         Ok(update_result) => update_result,
         // database error
@@ -1088,6 +2276,33 @@ async fn delete_tracking_number(
     // resolve the delete response from the database
     if update_result.deleted_count > 0 {
         println!("successfully deleted the relation record from the database");
+        if let Err(response) =
+            insert_tombstones(client.clone(), &tracking_number, vec![user_id_hash.clone()]).await
+        {
+            return response;
+        }
+
+        // the groups the user linked the number to stop getting its updates too, their relation records would otherwise keep
+        // the number registered on the API
+        let filter = doc! {"tracking_number": &tracking_number, "user_id_hash": &user_id_hash, "chat_id": {"$ne": Bson::Null}};
+        if let Err(e) = collection_relations.delete_many(filter, None).await {
+            println!(
+                "@DELETE_TRACKING_NUMBER: error deleting the group relations: {}",
+                e
+            );
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+        //
+
+        // the users the owner shared the number with lose access together with the owner
+        if relation.role == RelationRole::Owner {
+            if let Err(response) =
+                delete_shared_access(client.clone(), &tracking_number, &user_id_hash).await
+            {
+                return response;
+            }
+        }
+        //
 
         // check if there are any other relation docs with that number
         let filter = doc! {"tracking_number": &tracking_number};
@@ -1100,20 +2315,47 @@ async fn delete_tracking_number(
         };
         //
 
-        // check the response from the database and delete the number from the API register if there are none
+        // check the response from the database and delete the number from the API register if there are none, manual
+        // shipments aren't on the API and nobody can open them again so their tracking data is deleted instead
         if other_relations_count == 0 {
-            println!("delete from API would happen here but its been disabled for now");
-            //TODO: put this back in later
-            let _ = match delete_number_single(data.clone(), tracking_number).await {
-                Ok(_) => {
-                    println!("number has been deleted on the API");
-                    Ok(())
+            match is_manual_shipment(client.clone(), &tracking_number).await {
+                Ok(true) => {
+                    let collection_tracking_data: mongodb::Collection<Document> =
+                        db.collection("tracking_data");
+                    if let Err(e) = collection_tracking_data
+                        .delete_one(doc! {"data.number": &tracking_number}, None)
+                        .await
+                    {
+                        println!(
+                            "@DELETE_TRACKING_NUMBER: error deleting the manual shipment: {}",
+                            e
+                        );
+                    }
+                    if let Err(e) =
+                        tracking_history::delete_tracking_history(&client, &tracking_number).await
+                    {
+                        println!(
+                            "@DELETE_TRACKING_NUMBER: error deleting the history of the manual shipment: {}",
+                            e
+                        );
+                    }
                 }
-                Err(e) => {
-                    println!("@DELETE_TRACKING_NUMBER: error deleting_number: {},", e);
-                    Err(e)
+                Ok(false) => {
+                    println!("delete from API would happen here but its been disabled for now");
+                    //TODO: put this back in later
+                    let _ = match delete_number_single(data.clone(), tracking_number).await {
+                        Ok(_) => {
+                            println!("number has been deleted on the API");
+                            Ok(())
+                        }
+                        Err(e) => {
+                            println!("@DELETE_TRACKING_NUMBER: error deleting_number: {},", e);
+                            Err(e)
+                        }
+                    };
                 }
-            };
+                Err(response) => return response,
+            }
         }
 
         return HttpResponse::Ok().finish(); // professionalism
@@ -1131,12 +2373,36 @@ async fn delete_tracking_number(
 /// opens the tracking page on the client, be that from the starting screen or from a notification, this is the only method that returns the tracking
 /// data to the client because telegram miniapp is ass and doesn't have actual notifications
 async fn get_tracking_data_from_database(
-    client: web::Data<Client>,                     // for db
+    client: web::Data<Client>, // for db
+    data: web::Data<AppState>,
     tracking_data: Json<just_the_tracking_number>, // for knowing which number to query
     request: HttpRequest,                          // user in here
 ) -> impl Responder {
+    tracking_data_response(client, data, request, tracking_data.into_inner().number).await
+}
+
+/// Same as @GET_TRACKING_DATA_FROM_DATABASE as a GET request with the number in the path, so the browser and the telegram
+/// WebView can cache the response and revalidate it with the ETag
+async fn get_tracking_data(
+    client: web::Data<Client>, // for db
+    data: web::Data<AppState>,
+    path: web::Path<String>, // tracking number
+    request: HttpRequest,    // user in here
+) -> impl Responder {
+    let tracking_number = number_detection::normalize_tracking_number(&path.into_inner());
+    tracking_data_response(client, data, request, tracking_number).await
+}
+
+/// Function to build the response with the tracking data of a number for the user, answers 304 without a body when the
+/// client already has the version in the If-None-Match header
+async fn tracking_data_response(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    request: HttpRequest,
+    tracking_number: String,
+) -> HttpResponse {
     // check if user exists
-    let user_id_hash = match check_user_exists(client.clone(), request).await {
+    let user_id_hash = match check_user_exists(client.clone(), request.clone()).await {
         // continue
         Ok(user_id) => user_id,
         // user doesn't exist, respond with 520
@@ -1144,47 +2410,111 @@ async fn get_tracking_data_from_database(
     };
     //
 
-    let tracking_number = tracking_data.into_inner().number.clone();
+    // check if the user has permission for that number, also checks if the number is registered and gets the relation record
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(response) => {
+            return response;
+        }
+    };
+    //
 
-    // check if the user has permission for that number, also checks if the number is registered and gets the subscribed value
-    let is_user_tracked =
-        match check_relation_and_subscribed_status(client.clone(), &tracking_number, &user_id_hash)
-            .await
-        {
-            Ok(status) => status,
-            Err(response) => {
-                return response;
-            }
+    // get the tracking data from database
+    let tracking_data =
+        match database_tracking_data_from_number(client.clone(), &tracking_number).await {
+            Ok(tracking_data) => tracking_data,
+            Err(response) => return response,
         };
     //
 
-    // get the tracking data from database
-    let tracking_data = database_tracking_data_from_number(client.clone(), &tracking_number).await;
+    // answer without the body if the client's version is still the same
+    let etag = tracking_data_etag(&tracking_data, &relation);
+    let client_has_etag = request
+        .headers()
+        .get("If-None-Match")
+        .and_then(|header| header.to_str().ok())
+        .is_some_and(|header| {
+            header.split(',').map(str::trim).any(|client_etag| {
+                client_etag == "*" || client_etag.trim_start_matches("W/") == etag
+            })
+        });
+    if client_has_etag {
+        return HttpResponse::NotModified()
+            .insert_header(("ETag", etag))
+            .insert_header(("Cache-Control", "private, no-cache"))
+            .insert_header(("Vary", "X-User-ID-Hash"))
+            .finish();
+    }
     //
 
-    // convert the tracking data to the HTML form
-    let mut tracking_data_html = tracking_data.convert_to_HTML_form();
+    // convert the tracking data to the HTML form and add the user's own details and the is_user_tracked value
+    let tracking_data_html =
+        tracked_number_html_form(&relation, tracking_data, &data.carrier_directory);
 
-    // set the is_user_tracked value
-    match database_delivered_status(client, &tracking_number).await {
-        true => {
-            tracking_data_html.is_user_tracked = Some(false);
-            println!("package has been marked delivered");
-        }
-        false => {
-            tracking_data_html.is_user_tracked = Some(is_user_tracked);
-            println!("package has not been marked delivered");
+    HttpResponse::Ok()
+        .insert_header(("ETag", etag))
+        .insert_header(("Cache-Control", "private, no-cache"))
+        .insert_header(("Vary", "X-User-ID-Hash"))
+        .json(tracking_data_html)
+}
+
+/// Function for responding to a user request for their tracked numbers' tracking details and events, one page at a time
+/// with the filters and sorting from the optional query in the body, all of them as a bare array when the query has
+/// neither a cursor nor a limit, archived numbers are left out
+/// (see @GET_ARCHIVED_TRACKING_NUMBERS)
+async fn get_user_tracked_numbers_details(
+    client: web::Data<Client>, // for db
+    data: web::Data<AppState>,
+    query: Option<Json<TrackedNumbersQueryFromClient>>, // page, filters and sorting
+    request: HttpRequest,                               // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let query = query.map(|query| query.into_inner()).unwrap_or_default();
+    let filter =
+        doc! {"user_id_hash": &user_id_hash, "chat_id": Bson::Null, "archived": {"$ne": true}};
+    let pipeline = database_pipelines::user_tracked_numbers_pipeline(filter);
+
+    // the clients from before the pages send neither a cursor nor a limit and get every number as a bare array
+    let paged = query.cursor.is_some() || query.limit.is_some();
+    match user_tracked_numbers_page(client, pipeline, &query, paged).await {
+        Ok((page, next_cursor)) => {
+            let items: Vec<tracking_data_HTML> = page
+                .into_iter()
+                .map(|(relation, tracking_data)| {
+                    tracked_number_html_form(&relation, tracking_data, &data.carrier_directory)
+                })
+                .collect();
+            match paged {
+                true => HttpResponse::Ok().json(TrackedNumbersPage { items, next_cursor }),
+                false => HttpResponse::Ok().json(items),
+            }
         }
+        Err(response) => response,
     }
-
-    HttpResponse::Ok().body(serde_json::to_string(&tracking_data_html).unwrap())
 }
 
-/// Function for responding to a user request for all their tracked numbers' tracking details and events
-// TODO: @$lookup doc joint search actual SQL
-async fn get_user_tracked_numbers_details(
+/// Function for responding to a user request for the summaries of their tracked numbers, same pages, filters and sorting
+/// as @GET_USER_TRACKED_NUMBERS_DETAILS but without the events so the list loads fast, the events of a number come from
+/// @GET_TRACKING_DATA_FROM_DATABASE when the client opens it
+async fn get_user_tracked_numbers_summary(
     client: web::Data<Client>, // for db
-    request: HttpRequest,      // user in here
+    data: web::Data<AppState>,
+    query: Option<Json<TrackedNumbersQueryFromClient>>, // page, filters and sorting
+    request: HttpRequest,                               // user in here
 ) -> impl Responder {
     // check if user exists
     let user_id_hash = match check_user_exists(client.clone(), request).await {
@@ -1195,142 +2525,1803 @@ async fn get_user_tracked_numbers_details(
     };
     //
 
-    // set database, relation and filter
-    let db = client.database("teletrack");
-    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
-        db.collection("tracking_number_user_relation");
-    let filter = doc! {"user_id_hash": &user_id_hash};
+    let query = query.map(|query| query.into_inner()).unwrap_or_default();
+    let filter =
+        doc! {"user_id_hash": &user_id_hash, "chat_id": Bson::Null, "archived": {"$ne": true}};
+    let pipeline = database_pipelines::user_tracked_numbers_summary_pipeline(filter);
+
+    match user_tracked_numbers_page(client, pipeline, &query, true).await {
+        Ok((page, next_cursor)) => HttpResponse::Ok().json(TrackedNumbersPage {
+            items: page
+                .into_iter()
+                .map(|(relation, tracking_data)| {
+                    tracked_number_summary_form(&relation, tracking_data, &data.carrier_directory)
+                })
+                .collect(),
+            next_cursor,
+        }),
+        Err(response) => response,
+    }
+}
 
-    // TODO: @$lookup doc joint search actual SQL
+// HISTORY
 
-    // get a result of a search for all the user's tracked numbers
-    let tracking_numbers_cursor = match collection_relations.find(filter.clone(), None).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            println!("@GET_USER_TRACKED_NUMBERS_DETAILS: {}", e);
-            return HttpResponse::InternalServerError().body(e.to_string());
-        }
+/// Function for responding with how the status, the ETA and the latest event of a number changed over time, oldest first,
+/// viewers can see it too, the history starts when the number was first saved after it was kept (see tracking_history.rs)
+async fn get_tracking_history(
+    client: web::Data<Client>,
+    tracking_data: Json<just_the_tracking_number>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
     };
     //
 
-    // convert the cursor to a list of tracking numbers, and user subscribed status that the user is tracking
-    let user_tracked_numbers_and_status: Vec<(String, bool)> = match tracking_numbers_cursor
-        .try_collect::<Vec<TrackingNumberUserRelation>>()
-        .await
+    // check if the user has permission for that number
+    let tracking_number = tracking_data.into_inner().number;
+    if let Err(response) =
+        check_relation_and_get_record(client.clone(), &tracking_number, &user_id_hash).await
     {
-        Ok(relations) => relations
-            .into_iter()
-            .map(|r| (r.tracking_number, r.is_subscribed))
-            .collect(),
+        return response;
+    }
+    //
+
+    match tracking_history::tracking_history(&client, &tracking_number).await {
+        Ok(history) => HttpResponse::Ok().json(serde_json::json!({
+            "tracking_number": tracking_number,
+            "history": history
+                .iter()
+                .map(|snapshot| snapshot.convert_to_client_form())
+                .collect::<Vec<_>>(),
+        })),
         Err(e) => {
-            println!("@GET_USER_TRACKED_NUMBERS_DETAILS: {}", e);
-            return HttpResponse::InternalServerError().body(e.to_string());
+            println!("@GET_TRACKING_HISTORY: error getting the history: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
         }
+    }
+}
+
+// SYNC
+
+/// Function for responding to a client's sync request, returns the user's tracked numbers that changed since the cursor from
+/// the previous sync and the numbers the user stopped having, with a new cursor for the next sync, archived numbers are
+/// included with the archived flag so the client can keep them out of the main list
+async fn sync_tracked_numbers(
+    client: web::Data<Client>, // for db
+    data: web::Data<AppState>,
+    query: web::Query<SyncQueryFromClient>, // cursor from the previous sync
+    request: HttpRequest,                   // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
     };
     //
 
-    // set database, relation and filter for tracking data
-    let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
-        db.collection("tracking_data");
-    let filter = doc! {"data.number": { "$in":
-    &user_tracked_numbers_and_status.iter().map(|(number, _)| number).collect::<Vec<_>>() }};
+    // the new cursor is taken before the queries so nothing that changes while they run is missed
+    let now = Utc::now().timestamp_millis();
 
-    // get every tracking numbers' details from the database
-    let tracking_data_cursor = match collection_tracking_data.find(filter, None).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            println!("@GET_USER_TRACKED_NUMBERS_DETAILS: {}", e);
-            return HttpResponse::InternalServerError().body(e.to_string());
+    // open the cursor, tombstones older than the TTL are gone so those cursors can't be trusted
+    let since = match &query.since {
+        Some(cursor) => match base64::engine::general_purpose::URL_SAFE
+            .decode(cursor)
+            .ok()
+            .and_then(|cursor| serde_json::from_slice::<SyncCursor>(&cursor).ok())
+        {
+            Some(cursor) if cursor.since > now - SYNC_TOMBSTONE_TTL_DAYS * 24 * 60 * 60 * 1000 => {
+                Some(mongodb::bson::DateTime::from_millis(cursor.since))
+            }
+            _ => {
+                return HttpResponse::build(
+                    StatusCode::from_u16(546).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                )
+                .json(serde_json::json!({"expected error": "invalid or expired sync cursor"}))
+            }
+        },
+        None => None,
+    };
+    //
+
+    // get the changed numbers
+    let filter = doc! {"user_id_hash": &user_id_hash, "chat_id": Bson::Null};
+    let pipeline = database_pipelines::user_changed_tracked_numbers_pipeline(filter, since);
+    let changed: Vec<tracking_data_HTML> =
+        match user_tracked_numbers(client.clone(), pipeline).await {
+            Ok(changed) => changed
+                .into_iter()
+                .map(|(relation, tracking_data)| {
+                    tracked_number_html_form(&relation, tracking_data, &data.carrier_directory)
+                })
+                .collect(),
+            Err(response) => return response,
+        };
+    //
+
+    // get the deleted numbers, leaving out the ones the user tracks again
+    let deleted: Vec<String> = match since {
+        Some(since) => {
+            let db = client.database("teletrack");
+            let collection_tombstones: mongodb::Collection<RelationTombstone> =
+                db.collection("relation_tombstones");
+            let filter = doc! {"user_id_hash": &user_id_hash, "deleted_at": {"$gte": since}};
+            let tombstones = match collection_tombstones.find(filter, None).await {
+                Ok(cursor) => cursor.try_collect::<Vec<RelationTombstone>>().await,
+                Err(e) => Err(e),
+            };
+            let tombstones = match tombstones {
+                Ok(tombstones) => tombstones,
+                Err(e) => {
+                    println!("@SYNC_TRACKED_NUMBERS: {}", e);
+                    return HttpResponse::InternalServerError().body(e.to_string());
+                }
+            };
+            let current_numbers = match check_relations_exist(
+                client.clone(),
+                &user_id_hash,
+                tombstones
+                    .iter()
+                    .map(|t| t.tracking_number.clone())
+                    .collect(),
+            )
+            .await
+            {
+                Ok(current_numbers) => current_numbers,
+                Err(response) => return response,
+            };
+            let mut deleted: Vec<String> = tombstones
+                .into_iter()
+                .map(|t| t.tracking_number)
+                .filter(|number| !current_numbers.contains(number))
+                .collect();
+            deleted.sort();
+            deleted.dedup();
+            deleted
         }
+        None => Vec::new(),
     };
     //
 
-    // convert the cursor to a vector of tracking data HTML form
-    let user_tracked_numbers_details: Vec<tracking_data_HTML> = match tracking_data_cursor
-        .try_collect::<Vec<tracking_data_database_form>>()
-        .await
+    let cursor = SyncCursor {
+        since: now - SYNC_CURSOR_OVERLAP_MILLIS,
+    };
+    HttpResponse::Ok().json(SyncResponse {
+        changed,
+        deleted,
+        cursor: base64::engine::general_purpose::URL_SAFE
+            .encode(serde_json::to_vec(&cursor).unwrap_or_default()),
+    })
+}
+
+// CARRIERS
+
+/// Function for searching the carrier directory, for the client to let the user pick the carrier when the API couldn't
+/// detect it (530), the directory is the same for everyone so there's no user check and the response can be cached
+async fn search_carriers(
+    data: web::Data<AppState>,
+    query: web::Query<CarrierSearchFromClient>,
+) -> impl Responder {
+    let carriers = data
+        .carrier_directory
+        .search(query.q.as_deref().unwrap_or_default());
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=86400"))
+        .json(carriers)
+}
+
+// LIVE UPDATES
+
+/// Function for giving the client a short lived ticket for opening the live updates event stream, the ticket is signed so
+/// the stream knows the user without the header
+async fn create_live_updates_ticket(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let expires_at = Utc::now().timestamp() + LIVE_UPDATES_TICKET_TTL_SECONDS;
+    let ticket = data
+        .user_id_hasher
+        .sign_token(&format!("live.{}.{}", user_id_hash, expires_at));
+    HttpResponse::Ok().json(serde_json::json!({"ticket": ticket, "expires_at": expires_at}))
+}
+
+/// Function for opening the server-sent event stream of the user's live updates, the webhook sends a tracking_update event
+/// with the summary of the number to every open mini app of the users tracking it
+async fn open_live_updates(
+    data: web::Data<AppState>,
+    query: web::Query<LiveUpdatesTicketFromClient>,
+) -> impl Responder {
+    // check the ticket, the payload is live.<user ID hash>.<expiry time>
+    let user_id_hash = match data
+        .user_id_hasher
+        .verify_token(&query.ticket)
+        .and_then(|payload| payload.strip_prefix("live."))
+        .and_then(|payload| payload.split_once('.'))
     {
-        // convert the tracking data to HTML form and set the is_user_tracked value from the relation record
-        Ok(tracking_data_dbf) => tracking_data_dbf
-            .into_iter()
-            .map(|pkg| {
-                let mut html_package_data_form = pkg.convert_to_HTML_form();
-                // Check if the user is tracking this number and get subscription status
-                let is_user_tracked = match database_delivered_status_from_DBF(pkg.clone()) {
-                    // If package is delivered, not tracked regardless of subscription
-                    true => Some(false),
-                    // If not delivered, check if user is tracking it
-                    false => user_tracked_numbers_and_status
-                        .iter()
-                        .find(|(tracking_num, _)| {
-                            *tracking_num == html_package_data_form.tracking_number
-                        })
-                        .map(|(_, is_subscribed)| *is_subscribed),
-                };
-                html_package_data_form.is_user_tracked = is_user_tracked;
-                html_package_data_form
-            })
-            .collect(),
+        Some((user_id_hash, expires_at))
+            if expires_at
+                .parse::<i64>()
+                .is_ok_and(|expires_at| expires_at > Utc::now().timestamp()) =>
+        {
+            user_id_hash.to_string()
+        }
+        _ => {
+            return HttpResponse::build(
+                StatusCode::from_u16(547).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "invalid or expired live updates ticket"}))
+        }
+    };
+    //
+
+    let events = data.live_updates.subscribe(user_id_hash);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // no buffering on the proxy so the events go out right away
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events.map(Ok::<_, actix_web::Error>))
+}
+
+// ARCHIVE
+
+/// Function for responding to a user request for the tracking details of their archived numbers, the numbers get archived
+/// by the archive job some days after they were delivered
+async fn get_archived_tracking_numbers(
+    client: web::Data<Client>, // for db
+    data: web::Data<AppState>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let filter = doc! {"user_id_hash": &user_id_hash, "chat_id": Bson::Null, "archived": true};
+    let pipeline = database_pipelines::user_tracked_numbers_pipeline(filter);
+    match user_tracked_numbers(client, pipeline).await {
+        Ok(archived_numbers) => HttpResponse::Ok().json(
+            archived_numbers
+                .into_iter()
+                .map(|(relation, tracking_data)| {
+                    tracked_number_html_form(&relation, tracking_data, &data.carrier_directory)
+                })
+                .collect::<Vec<tracking_data_HTML>>(),
+        ),
+        Err(response) => response,
+    }
+}
+
+/// Function for moving an archived number back to the user's main list, the relation is flagged so the archive job doesn't
+/// archive it again
+async fn unarchive_tracking_number(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    tracking_data: Json<just_the_tracking_number>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let tracking_number = tracking_data.into_inner().number;
+
+    // check if the user has permission for that number and if it's archived
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(response) => return response,
+    };
+    if !relation.archived {
+        return HttpResponse::build(
+            StatusCode::from_u16(544).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "tracking number is not archived"}));
+    }
+    //
+
+    // set database, relation and filter
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+
+    // the archive job may have deleted the number from the API, it's registered again or no updates would come for it
+    let filter = doc! {"data.number": &tracking_number, "deleted_from_provider": true};
+    let deleted_tracking_data = match collection_tracking_data.find_one(filter, None).await {
+        Ok(deleted_tracking_data) => deleted_tracking_data,
         Err(e) => {
-            println!("@GET_USER_TRACKED_NUMBERS_DETAILS: {}", e);
+            println!("@UNARCHIVE_TRACKING_NUMBER: {}", e);
             return HttpResponse::InternalServerError().body(e.to_string());
         }
     };
+    if let Some(deleted_tracking_data) = deleted_tracking_data {
+        if let Err(e) = data
+            .provider_quota
+            .check_registration(&data.tracking_client, &data.notification_service)
+            .await
+        {
+            return HttpResponse::build(
+                StatusCode::from_u16(542).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "provider quota reached limit", "reason": e.to_string()}));
+        }
+        let tracking_details = trackingapi::tracking_number_carrier {
+            number: tracking_number.clone(),
+            carrier: deleted_tracking_data
+                .get_document("data")
+                .ok()
+                .and_then(|data| data.get_i32("carrier").ok()),
+            param: None,
+        };
+        match register_single(data.clone(), tracking_details).await {
+            Ok(_) | Err(tracking_error::TrackingAlreadyRegistered) => (),
+            Err(e) => {
+                println!("@UNARCHIVE_TRACKING_NUMBER: error registering again: {}", e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        }
+        if let Err(e) = collection_tracking_data
+            .update_one(
+                doc! {"data.number": &tracking_number},
+                doc! {"$unset": {"deleted_from_provider": ""}},
+                None,
+            )
+            .await
+        {
+            println!("@UNARCHIVE_TRACKING_NUMBER: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
     //
 
-    HttpResponse::Ok().json(user_tracked_numbers_details)
+    let filter = user_relation_filter(&tracking_number, &user_id_hash);
+    let update = doc! {"$set": {
+        "archived": false,
+        "archived_at": Bson::Null,
+        "unarchived": true,
+        "updated_at": mongodb::bson::DateTime::now(),
+    }};
+
+    match collection_relations.update_one(filter, update, None).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            println!("@UNARCHIVE_TRACKING_NUMBER: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+// CHANGE CARRIER
+
+/// Function for correcting the carrier of a tracking number when the API detected the wrong one, only the owner can change it
+/// because it changes the number for everyone tracking it, the tracking data is pulled again with the new carrier and the
+/// other followers are told about the correction
+async fn change_carrier(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    carrier_data: Json<ChangeCarrierFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let ChangeCarrierFromClient {
+        number: tracking_number,
+        carrier,
+    } = carrier_data.into_inner();
+
+    // check the carrier is one the API knows
+    let carrier_name = match data.carrier_directory.get(carrier) {
+        Some(carrier) => carrier.name.clone(),
+        None => {
+            println!("@CHANGE_CARRIER: unknown carrier {}", carrier);
+            return HttpResponse::build(
+                StatusCode::from_u16(537).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "unknown carrier"}));
+        }
+    };
+    //
+
+    // check if the user has permission for that number and owns it
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(response) => return response,
+    };
+    if let Err(response) = check_owner(&relation) {
+        return response;
+    }
+    if let Err(response) = check_not_manual(client.clone(), &tracking_number).await {
+        return response;
+    }
+    //
+
+    // change the carrier on the API, from the one it tracks the number with now
+    let carrier_old = match database_carrier_from_number(client.clone(), &tracking_number).await {
+        Ok(carrier_old) => carrier_old.or(relation.carrier),
+        Err(response) => return response,
+    };
+    match change_carrier_single(data.clone(), tracking_number.clone(), carrier_old, carrier).await {
+        Ok(_) => (),
+        Err(tracking_error::CarrierChangeRejected) => {
+            return HttpResponse::build(
+                StatusCode::from_u16(548).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "carrier was not changed"}));
+        }
+        Err(e) => {
+            println!("@CHANGE_CARRIER: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+    //
+
+    // the carrier is the same for everyone tracking the number
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let update = doc! {"$set": {"carrier": carrier, "updated_at": mongodb::bson::DateTime::now()}};
+    if let Err(e) = collection_relations
+        .update_many(doc! {"tracking_number": &tracking_number}, update, None)
+        .await
+    {
+        println!("@CHANGE_CARRIER: error updating the relations: {}", e);
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    //
+
+    // tell the other followers, the user's own private chat already knows
+    let user_id = database_user_id_from_hash(client.clone(), &user_id_hash).await;
+    match webhook::get_user_ids_related_to_tracking_number(client.clone(), tracking_number.clone())
+        .await
+    {
+        Ok(followers) => {
+            let description = format!("The carrier was corrected to {}", carrier_name);
+            let user_messages = followers
+                .into_iter()
+                .filter(|(chat_id, _)| *chat_id != user_id)
+                .map(|(chat_id, label)| {
+                    let message = webhook::tracking_update_message(
+                        &tracking_number,
+                        label.as_deref(),
+                        &description,
+                    );
+                    (chat_id, message)
+                })
+                .collect::<Vec<_>>();
+            if !user_messages.is_empty() {
+                webhook::send_notifications_to_users(
+                    data.clone(),
+                    user_messages,
+                    &tracking_number,
+                    notification_priority::Normal,
+                )
+                .await;
+            }
+        }
+        Err(_) => println!("@CHANGE_CARRIER: failed to get the followers to notify"),
+    }
+    //
+
+    // pull the tracking data again with the new carrier, 17TRACK can take a while so the webhook brings it if it's not ready
+    match refresh_and_return_tracking_data(client.clone(), data.clone(), tracking_number.clone())
+        .await
+    {
+        Ok(tracking_data_dbf) => HttpResponse::Ok().json(tracked_number_html_form(
+            &relation,
+            tracking_data_dbf,
+            &data.carrier_directory,
+        )),
+        Err(_) => {
+            println!("@CHANGE_CARRIER: tracking data not ready yet");
+            HttpResponse::Ok().json(serde_json::json!({"message": "carrier changed"}))
+        }
+    }
+}
+
+// CHANGE CARRIER PARAMETER
+
+/// Function for giving the API the extra parameter a carrier needs to track a number that's already registered, owner only
+/// like the carrier change, the tracking data is pulled again after
+async fn change_carrier_param(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    param_data: Json<CarrierParamFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let CarrierParamFromClient {
+        number: tracking_number,
+        param,
+    } = param_data.into_inner();
+    if let Err(reason) = param.validate() {
+        println!("@CHANGE_CARRIER_PARAM: {}", reason);
+        return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+    }
+
+    // check if the user has permission for that number and owns it
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(response) => return response,
+    };
+    if let Err(response) = check_owner(&relation) {
+        return response;
+    }
+    if let Err(response) = check_not_manual(client.clone(), &tracking_number).await {
+        return response;
+    }
+    //
+
+    // give the parameter to the API, with the carrier it tracks the number with
+    let carrier = match database_carrier_from_number(client.clone(), &tracking_number).await {
+        Ok(carrier) => carrier.or(relation.carrier),
+        Err(response) => return response,
+    };
+    match change_info_single(data.clone(), tracking_number.clone(), carrier, param).await {
+        Ok(_) => (),
+        Err(tracking_error::CarrierParamRequired(message)) => {
+            return HttpResponse::build(
+                StatusCode::from_u16(539).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "carrier parameter required", "message": message}));
+        }
+        Err(e) => {
+            println!("@CHANGE_CARRIER_PARAM: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+    //
+
+    // pull the tracking data again, the webhook brings it later if it's not ready
+    match refresh_and_return_tracking_data(client.clone(), data.clone(), tracking_number.clone())
+        .await
+    {
+        Ok(tracking_data_dbf) => HttpResponse::Ok().json(tracked_number_html_form(
+            &relation,
+            tracking_data_dbf,
+            &data.carrier_directory,
+        )),
+        Err(_) => {
+            println!("@CHANGE_CARRIER_PARAM: tracking data not ready yet");
+            HttpResponse::Ok().json(serde_json::json!({"message": "carrier parameter changed"}))
+        }
+    }
+}
+
+// MANUAL SHIPMENTS
+
+/// Function for making a manual shipment, for couriers 17TRACK doesn't know and parcels handed over in person, the user gets
+/// a made up number and adds the events themselves (see manual_shipments.rs), it uses the user's tracking quota like a
+/// registered number but the API isn't called so the provider quota isn't used
+async fn create_manual_shipment(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    shipment: Json<ManualShipmentFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    // check if the user has reached the tracking quota limit
+    if database_quota_from_hash(client.clone(), &user_id_hash).await <= 0 {
+        println!("@CREATE_MANUAL_SHIPMENT: user has reached the tracking quota limit");
+        return HttpResponse::build(
+            StatusCode::from_u16(540).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "user has reached the tracking quota limit"}));
+    }
+    //
+
+    // check what the user entered
+    let ManualShipmentFromClient {
+        reference,
+        courier_name,
+        events,
+    } = shipment.into_inner();
+    if let Err(reason) =
+        manual_shipments::validate_shipment_details(reference.as_deref(), courier_name.as_deref())
+    {
+        println!("@CREATE_MANUAL_SHIPMENT: {}", reason);
+        return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+    }
+    if events.len() > manual_shipments::MAX_MANUAL_EVENTS {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "too many events"}));
+    }
+    let events = match events
+        .iter()
+        .map(|event| event.convert_to_event())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(events) => events,
+        Err(reason) => {
+            println!("@CREATE_MANUAL_SHIPMENT: {}", reason);
+            return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+        }
+    };
+    //
+
+    // make the tracking data and put it in the database
+    let tracking_number = manual_shipments::new_manual_number();
+    let mut tracking_data = manual_shipments::new_manual_tracking_data(
+        tracking_number.clone(),
+        reference,
+        courier_name,
+    );
+    manual_shipments::set_manual_events(&mut tracking_data, events);
+    if let Err(e) =
+        tracking_history::save_tracking_data(&client, &tracking_data, snapshot_source::Manual).await
+    {
+        println!(
+            "@CREATE_MANUAL_SHIPMENT: error inserting tracking data: {}",
+            e
+        );
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    //
+
+    // create the relation record like for any other number
+    if let Err(response) = insert_relation(
+        client.clone(),
+        tracking_number.clone(),
+        user_id_hash.clone(),
+        None,
+        None,
+    )
+    .await
+    {
+        return response;
+    }
+    let _ = database_decrement_user_quota(client.clone(), &user_id_hash).await;
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(response) => return response,
+    };
+    //
+
+    println!("@CREATE_MANUAL_SHIPMENT: created {}", tracking_number);
+    HttpResponse::Ok().json(tracked_number_html_form(
+        &relation,
+        tracking_data,
+        &data.carrier_directory,
+    ))
+}
+
+/// Function for adding an event to a manual shipment, owner only, responds with the tracking data
+async fn add_manual_event(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    event_data: Json<ManualEventFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let ManualEventFromClient {
+        number: tracking_number,
+        event,
+    } = event_data.into_inner();
+    let event = match event.convert_to_event() {
+        Ok(event) => event,
+        Err(reason) => {
+            println!("@ADD_MANUAL_EVENT: {}", reason);
+            return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+        }
+    };
+
+    let (relation, tracking_data) =
+        match manual_shipment_for_owner(client.clone(), &tracking_number, &user_id_hash).await {
+            Ok(shipment) => shipment,
+            Err(response) => return response,
+        };
+    let mut events = manual_shipments::manual_events(&tracking_data);
+    if events.len() >= manual_shipments::MAX_MANUAL_EVENTS {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "too many events"}));
+    }
+    events.push(event);
+
+    save_manual_events(client, data, relation, tracking_data, events).await
+}
+
+/// Function for changing an event of a manual shipment, owner only, the index is the place of the event in the events of
+/// the tracking data, newest first, responds with the tracking data
+async fn edit_manual_event(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    event_data: Json<EditManualEventFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let EditManualEventFromClient {
+        number: tracking_number,
+        index,
+        event,
+    } = event_data.into_inner();
+    let event = match event.convert_to_event() {
+        Ok(event) => event,
+        Err(reason) => {
+            println!("@EDIT_MANUAL_EVENT: {}", reason);
+            return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+        }
+    };
+
+    let (relation, tracking_data) =
+        match manual_shipment_for_owner(client.clone(), &tracking_number, &user_id_hash).await {
+            Ok(shipment) => shipment,
+            Err(response) => return response,
+        };
+    let mut events = manual_shipments::manual_events(&tracking_data);
+    match events.get_mut(index) {
+        Some(old_event) => *old_event = event,
+        None => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"expected error": "no event at that index"}))
+        }
+    }
+
+    save_manual_events(client, data, relation, tracking_data, events).await
+}
+
+/// Function for removing an event from a manual shipment, owner only, responds with the tracking data
+async fn remove_manual_event(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    event_data: Json<ManualEventIndexFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let ManualEventIndexFromClient {
+        number: tracking_number,
+        index,
+    } = event_data.into_inner();
+    let (relation, tracking_data) =
+        match manual_shipment_for_owner(client.clone(), &tracking_number, &user_id_hash).await {
+            Ok(shipment) => shipment,
+            Err(response) => return response,
+        };
+    let mut events = manual_shipments::manual_events(&tracking_data);
+    if index >= events.len() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "no event at that index"}));
+    }
+    events.remove(index);
+
+    save_manual_events(client, data, relation, tracking_data, events).await
+}
+
+// EDIT TRACKING NUMBER DETAILS
+
+/// Function for saving the user's own label, note, pinned flag and color for a tracking number, they are saved on the relation
+/// record so every user tracking the same number can name it differently
+async fn edit_tracking_number_details(
+    client: web::Data<Client>,
+    details: Json<TrackingNumberDetailsFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let details = details.into_inner();
+
+    // check if the user has permission for that number
+    match check_relation(client.clone(), &details.number, &user_id_hash).await {
+        Ok(_) => (),
+        Err(result) => return result,
+    }
+
+    // check the values before saving them
+    if let Err(reason) = validate_tracking_number_details(&details) {
+        println!("@EDIT_TRACKING_NUMBER_DETAILS: {}", reason);
+        return HttpResponse::BadRequest().json(serde_json::json!({"expected error": reason}));
+    }
+    //
+
+    // build the update, only the values sent by the client are changed and blank strings clear the value
+    let mut set_values = doc! {};
+    for (field, value) in [
+        ("label", &details.label),
+        ("note", &details.note),
+        ("color", &details.color),
+    ] {
+        match value.as_deref().map(str::trim) {
+            Some("") => {
+                set_values.insert(field, Bson::Null);
+            }
+            Some(value) => {
+                set_values.insert(field, value);
+            }
+            None => (),
+        }
+    }
+    if let Some(pinned) = details.pinned {
+        set_values.insert("pinned", pinned);
+    }
+    if set_values.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"expected error": "nothing to change"}));
+    }
+    set_values.insert("updated_at", mongodb::bson::DateTime::now());
+    //
+
+    // send request to the DB to save the details on the relation record
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let filter = user_relation_filter(&details.number, &user_id_hash);
+    match collection_relations
+        .update_one(filter, doc! {"$set": set_values}, None)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().body("tracking number details saved"),
+        // database error
+        Err(e) => {
+            println!("@EDIT_TRACKING_NUMBER_DETAILS: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+// SHARE TRACKING NUMBER
+
+/// Function for the owner of a tracking number to make an invite for sharing it, the invite is a signed token that the bot
+/// sends to the owner as a mini app link so they can forward it, the user that opens it becomes a viewer of the number
+async fn create_share_invite(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    tracking_data: Json<just_the_tracking_number>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let tracking_number = tracking_data.into_inner().number.clone();
+
+    // check if the user is the owner of that number
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(result) => return result,
+    };
+    if let Err(response) = check_owner(&relation) {
+        return response;
+    }
+    //
+
+    // save the invite
+    let invite = ShareInvite {
+        invite_id: ObjectId::new().to_hex(),
+        tracking_number: tracking_number.clone(),
+        owner_user_id_hash: user_id_hash.clone(),
+        expires_at: Utc::now().timestamp() + SHARE_INVITE_TTL_HOURS * 3600,
+        accepted_by: None,
+    };
+    let db = client.database("teletrack");
+    let collection_invites: mongodb::Collection<ShareInvite> = db.collection("share_invites");
+    if let Err(e) = collection_invites.insert_one(&invite, None).await {
+        println!("@CREATE_SHARE_INVITE: error inserting share invite: {}", e);
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    //
+
+    // sign the invite and send it to the owner through the bot
+    let share_token = data
+        .user_id_hasher
+        .sign_token(&format!("{}.{}", invite.invite_id, invite.expires_at));
+    let user_id = database_user_id_from_hash(client.clone(), &user_id_hash).await;
+    let share_link = match &*data.notification_service {
+        Ok(service) => {
+            match service
+                .send_share_invite(user_id, &tracking_number, &share_token)
+                .await
+            {
+                Ok(share_link) => share_link,
+                Err(e) => {
+                    println!("@CREATE_SHARE_INVITE: error sending the invite: {}", e);
+                    return HttpResponse::InternalServerError().body(e.to_string());
+                }
+            }
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    //
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "share_token": share_token,
+        "share_link": share_link,
+        "expires_at": invite.expires_at,
+    }))
+}
+
+/// Function for accepting a share invite, checks the token and makes the user a viewer of the tracking number, viewers
+/// don't use tracking quota since the owner is already paying for the number
+async fn accept_share_invite(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    share_data: Json<ShareTokenFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let invalid_invite = || {
+        HttpResponse::build(StatusCode::from_u16(527).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+            .json(serde_json::json!({"expected error": "share invite is invalid or expired"}))
+    };
+
+    // check the signature and the expiry time of the token
+    let share_token = share_data.into_inner().share_token;
+    let (invite_id, expires_at) = match data
+        .user_id_hasher
+        .verify_token(&share_token)
+        .and_then(|payload| payload.split_once('.'))
+        .and_then(|(invite_id, expires_at)| Some((invite_id, expires_at.parse::<i64>().ok()?)))
+    {
+        Some(invite) => invite,
+        None => {
+            println!("@ACCEPT_SHARE_INVITE: bad share token");
+            return invalid_invite();
+        }
+    };
+    if expires_at < Utc::now().timestamp() {
+        println!("@ACCEPT_SHARE_INVITE: share invite expired");
+        return invalid_invite();
+    }
+    //
+
+    // find the invite
+    let db = client.database("teletrack");
+    let collection_invites: mongodb::Collection<ShareInvite> = db.collection("share_invites");
+    let invite = match collection_invites
+        .find_one(doc! {"invite_id": invite_id}, None)
+        .await
+    {
+        Ok(Some(invite)) => invite,
+        Ok(None) => {
+            println!("@ACCEPT_SHARE_INVITE: share invite not found");
+            return invalid_invite();
+        }
+        Err(e) => {
+            println!("@ACCEPT_SHARE_INVITE: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+    //
+
+    // the owner might have deleted the number since making the invite
+    if check_relation(
+        client.clone(),
+        &invite.tracking_number,
+        &invite.owner_user_id_hash,
+    )
+    .await
+    .is_err()
+    {
+        println!("@ACCEPT_SHARE_INVITE: owner doesn't track the number anymore");
+        return invalid_invite();
+    }
+    //
+
+    // check if a duplicate of the relation record exists, this is also the case for the owner opening their own invite
+    if check_relation(client.clone(), &invite.tracking_number, &user_id_hash)
+        .await
+        .is_ok()
+    {
+        println!("relation already exists");
+        return HttpResponse::build(
+            StatusCode::from_u16(541).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "relation record already exists"}));
+    }
+    //
+
+    // use up the invite, only one user can accept it
+    let filter = doc! {"invite_id": &invite.invite_id, "accepted_by": Bson::Null};
+    let update = doc! {"$set": {"accepted_by": &user_id_hash}};
+    match collection_invites.update_one(filter, update, None).await {
+        Ok(update_result) if update_result.modified_count > 0 => (),
+        Ok(_) => {
+            println!("@ACCEPT_SHARE_INVITE: share invite already used");
+            return HttpResponse::build(
+                StatusCode::from_u16(528).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .json(serde_json::json!({"expected error": "share invite was already used"}));
+        }
+        Err(e) => {
+            println!("@ACCEPT_SHARE_INVITE: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+    //
+
+    // create the viewer relation record, no quota used
+    match insert_relation(
+        client.clone(),
+        invite.tracking_number.clone(),
+        user_id_hash,
+        Some(invite.owner_user_id_hash),
+        None,
+    )
+    .await
+    {
+        Ok(_) => {
+            HttpResponse::Ok().json(serde_json::json!({"tracking_number": invite.tracking_number}))
+        }
+        Err(response) => response,
+    }
+}
+
+/// Function for the owner of a tracking number to list the users they shared it with
+async fn list_viewers(
+    client: web::Data<Client>,
+    tracking_data: Json<just_the_tracking_number>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let tracking_number = tracking_data.into_inner().number.clone();
+
+    // check if the user is the owner of that number
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(result) => return result,
+    };
+    if let Err(response) = check_owner(&relation) {
+        return response;
+    }
+    //
+
+    // get the viewer relation records
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let filter =
+        doc! {"tracking_number": &tracking_number, "role": "viewer", "shared_by": &user_id_hash};
+    let viewers = match collection_relations.find(filter, None).await {
+        Ok(cursor) => match cursor
+            .try_collect::<Vec<TrackingNumberUserRelation>>()
+            .await
+        {
+            Ok(viewers) => viewers,
+            Err(e) => {
+                println!("@LIST_VIEWERS: {}", e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        },
+        Err(e) => {
+            println!("@LIST_VIEWERS: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+    //
+
+    // get the names of the viewers, the user ID hashes are credentials so they are never sent to another user
+    let collection_users: mongodb::Collection<UserDatabaseForm> = db.collection("users");
+    let filter =
+        doc! {"user_id_hash": {"$in": viewers.iter().map(|v| &v.user_id_hash).collect::<Vec<_>>()}};
+    let users = match collection_users.find(filter, None).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<UserDatabaseForm>>().await {
+            Ok(users) => users,
+            Err(e) => {
+                println!("@LIST_VIEWERS: {}", e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        },
+        Err(e) => {
+            println!("@LIST_VIEWERS: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+    let viewers_list: Vec<serde_json::Value> = viewers
+        .iter()
+        .map(|viewer| {
+            let user_name = users
+                .iter()
+                .find(|u| u.user_id_hash == viewer.user_id_hash)
+                .map(|u| u.user_name.clone());
+            serde_json::json!({
+                "viewer_id": viewer.id.map(|id| id.to_hex()),
+                "user_name": user_name,
+                "is_subscribed": viewer.is_subscribed,
+            })
+        })
+        .collect();
+    //
+
+    HttpResponse::Ok().json(viewers_list)
+}
+
+/// Function for the owner of a tracking number to take away a viewer's access to it
+async fn revoke_viewer(
+    client: web::Data<Client>,
+    viewer_data: Json<ViewerFromClient>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+    //
+
+    let viewer_data = viewer_data.into_inner();
+
+    // check if the user is the owner of that number
+    let relation =
+        match check_relation_and_get_record(client.clone(), &viewer_data.number, &user_id_hash)
+            .await
+        {
+            Ok(relation) => relation,
+            Err(result) => return result,
+        };
+    if let Err(response) = check_owner(&relation) {
+        return response;
+    }
+    //
+
+    let viewer_relation_id = match ObjectId::parse_str(&viewer_data.viewer_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"expected error": "invalid viewer id"}))
+        }
+    };
+
+    // send request to the DB to remove the viewer relation record
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+    let filter = doc! {
+        "_id": viewer_relation_id,
+        "tracking_number": &viewer_data.number,
+        "role": "viewer",
+        "shared_by": &user_id_hash,
+    };
+    match collection_relations.find_one_and_delete(filter, None).await {
+        Ok(Some(viewer_relation)) => {
+            println!("viewer access revoked");
+            match insert_tombstones(
+                client.clone(),
+                &viewer_data.number,
+                vec![viewer_relation.user_id_hash],
+            )
+            .await
+            {
+                Ok(_) => HttpResponse::Ok().finish(),
+                Err(response) => response,
+            }
+        }
+        Ok(None) => HttpResponse::build(
+            StatusCode::from_u16(536).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(serde_json::json!({"expected error": "no relation record found to delete"})),
+        Err(e) => {
+            println!("@REVOKE_VIEWER: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Function for pulling data of a number from the API and saving in the database
+/// (for testing purposes mostly)
+async fn pull_data_from_API(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    tracking_data: Json<just_the_tracking_number>,
+    request: HttpRequest, // user in here
+) -> impl Responder {
+    // check if user exists
+    let user_id_hash = match check_user_exists(client.clone(), request).await {
+        // continue
+        Ok(user_id) => user_id,
+        // user doesn't exist, respond with 520
+        Err(response) => return response,
+    };
+
+    // get from request tracking number
+    let tracking_number = tracking_data.into_inner().number.clone();
+
+    // check if the user has permission for that number, the relation decides what the user gets to see
+    let relation = match check_relation_and_get_record(
+        client.clone(),
+        &tracking_number,
+        &user_id_hash,
+    )
+    .await
+    {
+        Ok(relation) => relation,
+        Err(result) => return result,
+    };
+    if let Err(response) = check_not_manual(client.clone(), &tracking_number).await {
+        return response;
+    }
+
+    // send request to the API for tracking data and put it in the database
+    match refresh_and_return_tracking_data(client.clone(), data.clone(), tracking_number.clone())
+        .await
+    {
+        Ok(tracking_data_dbf) => {
+            println!("tracking data pulled from API and saved to database");
+            // return the HTML form with the user's own details, viewers get the addresses redacted
+            return HttpResponse::Ok().json(tracked_number_html_form(
+                &relation,
+                tracking_data_dbf,
+                &data.carrier_directory,
+            ));
+        }
+        Err(response) => {
+            return response;
+        }
+    }
+}
+// ADMIN
+
+/// Function for getting the report of the last reconciliation with the provider (see jobs.rs), admin only
+async fn get_reconciliation_report(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    request: HttpRequest, // admin key in here
+) -> impl Responder {
+    if let Err(response) = check_admin(&data, &request) {
+        return response;
+    }
+    match jobs::latest_reconciliation_report(&client).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            println!("@GET_RECONCILIATION_REPORT: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Function for running the reconciliation with the provider right now instead of waiting for the job, admin only, responds
+/// with the report
+async fn run_reconciliation(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    query: web::Query<ReconcileQueryFromClient>,
+    request: HttpRequest, // admin key in here
+) -> impl Responder {
+    if let Err(response) = check_admin(&data, &request) {
+        return response;
+    }
+    let auto_fix = query.fix.unwrap_or(false);
+    match jobs::reconcile_with_provider(&client, &data.tracking_client, auto_fix).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            println!("@RUN_RECONCILIATION: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Function for getting the 17TRACK account quota and the thresholds of the registration guard, admin only
+async fn get_provider_quota(data: web::Data<AppState>, request: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(&data, &request) {
+        return response;
+    }
+    match data.tracking_client.get_quota().await {
+        Ok(quota) => HttpResponse::Ok().json(serde_json::json!({
+            "quota": quota,
+            "alert_below": data.provider_quota.alert_below,
+            "refuse_below": data.provider_quota.refuse_below,
+        })),
+        Err(e) => {
+            println!("@GET_PROVIDER_QUOTA: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// Function for the metrics in the Prometheus text format, admin only, the scraper sends the key in X-Admin-Key
+async fn metrics(data: web::Data<AppState>, request: HttpRequest) -> impl Responder {
+    if let Err(response) = check_admin(&data, &request) {
+        return response;
+    }
+    let quota = match data.tracking_client.get_quota().await {
+        Ok(quota) => quota,
+        Err(e) => {
+            println!("@METRICS: {}", e);
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    };
+
+    // name, help, value, values the API didn't give are left out
+    let gauges = [
+        (
+            "teletrack_provider_quota_total",
+            "Total 17TRACK registrations of the plan",
+            quota.quota_total,
+        ),
+        (
+            "teletrack_provider_quota_used",
+            "17TRACK registrations used",
+            quota.quota_used,
+        ),
+        (
+            "teletrack_provider_quota_remaining",
+            "17TRACK registrations left",
+            quota.quota_remain,
+        ),
+        (
+            "teletrack_provider_quota_today_used",
+            "17TRACK registrations used today",
+            quota.today_used,
+        ),
+        (
+            "teletrack_provider_quota_alert_below",
+            "Remaining quota the owner is alerted under",
+            Some(data.provider_quota.alert_below),
+        ),
+        (
+            "teletrack_provider_quota_refuse_below",
+            "Remaining quota new registrations are refused under",
+            Some(data.provider_quota.refuse_below),
+        ),
+    ];
+    let body: String = gauges
+        .iter()
+        .filter_map(|(name, help, value)| {
+            let value = (*value)?;
+            Some(format!(
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"
+            ))
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    PREFLIGHT OPTIONS HANDLERS FOR ROUTING HANDLERS
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+#[options("/write")]
+async fn write_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/recover_user")]
+async fn recover_user_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header(("Access-Control-Allow-Headers", "Content-Type"))
+        .finish()
+}
+#[options("/create_user")]
+async fn create_user_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/register_tracking_number")]
+async fn register_tracking_number_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/stop_tracking_number")]
+async fn stop_tracking_number_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/retrack_stopped_number")]
+async fn retrack_stopped_number_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/delete_tracking_number")]
+async fn delete_tracking_number_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/get_tracking_data")]
+async fn get_tracking_data_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/history")]
+async fn history_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/tracking_data/{number}")]
+async fn tracking_data_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "GET, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash, If-None-Match",
+        ))
+        .finish()
+}
+
+#[options("/get_user_tracked_numbers_details")]
+async fn get_user_tracked_numbers_details_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/get_user_tracked_numbers_summary")]
+async fn get_user_tracked_numbers_summary_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
 }
 
-/// Function for pulling data of a number from the API and saving in the database
-/// (for testing purposes mostly)
-async fn pull_data_from_API(
-    client: web::Data<Client>,
-    data: web::Data<AppState>,
-    tracking_data: Json<just_the_tracking_number>,
-    request: HttpRequest, // user in here
-) -> impl Responder {
-    // check if user exists
-    let user_id_hash = match check_user_exists(client.clone(), request).await {
-        // continue
-        Ok(user_id) => user_id,
-        // user doesn't exist, respond with 520
-        Err(response) => return response,
-    };
+#[options("/edit_tracking_number_details")]
+async fn edit_tracking_number_details_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/create_share_invite")]
+async fn create_share_invite_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/accept_share_invite")]
+async fn accept_share_invite_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/list_viewers")]
+async fn list_viewers_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/revoke_viewer")]
+async fn revoke_viewer_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
+#[options("/sync")]
+async fn sync_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "GET, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
 
-    // get from request tracking number
-    let tracking_number = tracking_data.into_inner().number.clone();
+#[options("/live_updates_ticket")]
+async fn live_updates_ticket_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
 
-    // check if the user has permission for that number
-    match check_relation(client.clone(), &tracking_number, &user_id_hash).await {
-        Ok(_) => (),
-        Err(result) => return result,
-    }
+#[options("/carriers")]
+async fn carriers_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "GET, OPTIONS"))
+        .insert_header(("Access-Control-Allow-Headers", "Content-Type"))
+        .finish()
+}
 
-    // send request to the API for tracking data and put it in the database
-    match refresh_and_return_tracking_data(client.clone(), data.clone(), tracking_number.clone())
-        .await
-    {
-        Ok(tracking_data_dbf) => {
-            println!("tracking data pulled from API and saved to database");
-            // convert the tracking data to HTML form
-            let tracking_data_html = tracking_data_dbf.convert_to_HTML_form();
-            // return the HTML form to the user
-            return HttpResponse::Ok().body(serde_json::to_string(&tracking_data_html).unwrap());
-        }
-        Err(response) => {
-            return response;
-        }
-    }
+#[options("/archived")]
+async fn archived_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
 }
-/*
--------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
-    PREFLIGHT OPTIONS HANDLERS FOR ROUTING HANDLERS
 
--------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
-*/
+#[options("/unarchive_tracking_number")]
+async fn unarchive_tracking_number_options() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header((
+            "Access-Control-Allow-Origin",
+            "https://teletrack-twa-1b3480c228a6.herokuapp.com",
+        ))
+        .insert_header(("Access-Control-Allow-Methods", "POST, OPTIONS"))
+        .insert_header((
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-User-ID-Hash",
+        ))
+        .finish()
+}
 
-#[options("/write")]
-async fn write_options() -> impl Responder {
+#[options("/change_carrier")]
+async fn change_carrier_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1343,8 +4334,9 @@ async fn write_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/create_user")]
-async fn create_user_options() -> impl Responder {
+
+#[options("/change_carrier_param")]
+async fn change_carrier_param_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1357,8 +4349,9 @@ async fn create_user_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/register_tracking_number")]
-async fn register_tracking_number_options() -> impl Responder {
+
+#[options("/extract_tracking_numbers")]
+async fn extract_tracking_numbers_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1371,8 +4364,9 @@ async fn register_tracking_number_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/stop_tracking_number")]
-async fn stop_tracking_number_options() -> impl Responder {
+
+#[options("/register_tracking_numbers_bulk")]
+async fn register_tracking_numbers_bulk_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1385,8 +4379,9 @@ async fn stop_tracking_number_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/retrack_stopped_number")]
-async fn retrack_stopped_number_options() -> impl Responder {
+
+#[options("/manual_shipment")]
+async fn create_manual_shipment_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1399,8 +4394,9 @@ async fn retrack_stopped_number_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/delete_tracking_number")]
-async fn delete_tracking_number_options() -> impl Responder {
+
+#[options("/manual_shipment/add_event")]
+async fn add_manual_event_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1413,8 +4409,9 @@ async fn delete_tracking_number_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/get_tracking_data")]
-async fn get_tracking_data_options() -> impl Responder {
+
+#[options("/manual_shipment/edit_event")]
+async fn edit_manual_event_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1427,8 +4424,9 @@ async fn get_tracking_data_options() -> impl Responder {
         ))
         .finish()
 }
-#[options("/get_user_tracked_numbers_details")]
-async fn get_user_tracked_numbers_details_options() -> impl Responder {
+
+#[options("/manual_shipment/remove_event")]
+async fn remove_manual_event_options() -> impl Responder {
     HttpResponse::NoContent()
         .insert_header((
             "Access-Control-Allow-Origin",
@@ -1441,6 +4439,7 @@ async fn get_user_tracked_numbers_details_options() -> impl Responder {
         ))
         .finish()
 }
+
 #[options("/pull_data_from_API")]
 async fn pull_data_from_API_options() -> impl Responder {
     HttpResponse::NoContent()
@@ -1478,6 +4477,31 @@ async fn main() -> std::io::Result<()> {
     ));
     // TRACKING SERVICE
     let tracking_client = Arc::new(tracking_client::new());
+    let carrier_directory =
+        Arc::new(carrier_directory::load().expect("bundled carrier directory is invalid"));
+    let provider_quota = Arc::new(provider_quota_guard::from_env());
+    // USER IDENTITY
+    let user_id_hasher = Arc::new(
+        user_id_hasher::new(env::var("USER_ID_HASH_SECRET").expect("USER_ID_HASH_SECRET not set"))
+            .expect("USER_ID_HASH_SECRET is invalid"),
+    );
+    // MIGRATIONS
+    migrations::run_migrations(&mongo_client, &user_id_hasher, &tracking_client)
+        .await
+        .expect("database migrations failed");
+    // LIVE UPDATES
+    let live_updates = live_update_broadcaster::new();
+    // BACKGROUND JOBS
+    jobs::start_archive_job(
+        mongo_client.clone(),
+        tracking_client.clone(),
+        jobs::archive_settings::from_env(),
+    );
+    jobs::start_reconciliation_job(
+        mongo_client.clone(),
+        tracking_client.clone(),
+        jobs::reconcile_settings::from_env(),
+    );
     // SERVER
     let port: u16 = env::var("PORT")
         .unwrap_or_else(|_| "8080".to_string())
@@ -1495,7 +4519,15 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(AppState {
                 notification_service: notification_service.clone(),
                 tracking_client: tracking_client.clone(),
+                user_id_hasher: user_id_hasher.clone(),
+                live_updates: live_updates.clone(),
+                carrier_directory: carrier_directory.clone(),
+                provider_quota: provider_quota.clone(),
                 webhook_secret: env::var("WEBHOOK_SECRET").expect("WEBHOOK_SECRET must be set"),
+                telegram_webhook_secret: env::var("TELEGRAM_WEBHOOK_SECRET")
+                    .expect("TELEGRAM_WEBHOOK_SECRET must be set"),
+                telegram_bot_token: env::var("TELEGRAM_BOT_TOKEN").expect("BOT_TOKEN must be set"),
+                admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
             }))
             /*
                 CORS
@@ -1508,8 +4540,13 @@ async fn main() -> std::io::Result<()> {
                     .allowed_origin("https://teletrack-twa-1b3480c228a6.herokuapp.com") // Heroku origin
                     .allowed_origin("https://telegramtrack.lemoncardboard.uk") // DNS origin
                     .allowed_methods(vec!["GET", "POST", "OPTIONS"])
-                    .allowed_headers(vec!["X-User-ID-Hash", "Content-Type", "Authorization"])
-                    .expose_headers(vec!["X-User-ID-Hash"])
+                    .allowed_headers(vec![
+                        "X-User-ID-Hash",
+                        "Content-Type",
+                        "Authorization",
+                        "If-None-Match",
+                    ])
+                    .expose_headers(vec!["X-User-ID-Hash", "ETag"])
                     .supports_credentials()
                     .max_age(3600),
             )
@@ -1518,9 +4555,12 @@ async fn main() -> std::io::Result<()> {
             */
             // HTTPS webhook for receiving updates
             .service(webhook::handle_webhook)
+            // HTTPS webhook for receiving the bot updates from telegram (group commands)
+            .service(telegram_webhook::handle_telegram_update)
             // HTTPS receive
             // prod
             .route("/create_user", web::post().to(create_user_handler))
+            .route("/recover_user", web::post().to(recover_user_handler))
             .route(
                 "/register_tracking_number",
                 web::post().to(register_tracking_number),
@@ -1545,17 +4585,99 @@ async fn main() -> std::io::Result<()> {
                 "get_user_tracked_numbers_details",
                 web::post().to(get_user_tracked_numbers_details),
             )
+            .route(
+                "/get_user_tracked_numbers_summary",
+                web::post().to(get_user_tracked_numbers_summary),
+            )
             .route("/pull_data_from_API", web::post().to(pull_data_from_API))
+            .route(
+                "/edit_tracking_number_details",
+                web::post().to(edit_tracking_number_details),
+            )
+            .route("/create_share_invite", web::post().to(create_share_invite))
+            .route("/accept_share_invite", web::post().to(accept_share_invite))
+            .route("/list_viewers", web::post().to(list_viewers))
+            .route("/revoke_viewer", web::post().to(revoke_viewer))
+            .route("/change_carrier", web::post().to(change_carrier))
+            .route(
+                "/change_carrier_param",
+                web::post().to(change_carrier_param),
+            )
+            // whole emails are bigger than the default json limit
+            .service(
+                web::resource("/extract_tracking_numbers")
+                    .app_data(
+                        web::JsonConfig::default().limit(2 * number_extraction::MAX_INPUT_LENGTH),
+                    )
+                    .route(web::post().to(extract_tracking_numbers)),
+            )
+            .route(
+                "/register_tracking_numbers_bulk",
+                web::post().to(register_tracking_numbers_bulk),
+            )
+            .route("/manual_shipment", web::post().to(create_manual_shipment))
+            .route(
+                "/manual_shipment/add_event",
+                web::post().to(add_manual_event),
+            )
+            .route(
+                "/manual_shipment/edit_event",
+                web::post().to(edit_manual_event),
+            )
+            .route(
+                "/manual_shipment/remove_event",
+                web::post().to(remove_manual_event),
+            )
+            .route("/admin/reconcile", web::get().to(get_reconciliation_report))
+            .route("/admin/reconcile", web::post().to(run_reconciliation))
+            .route("/admin/quota", web::get().to(get_provider_quota))
+            .route("/metrics", web::get().to(metrics))
+            .route("/archived", web::post().to(get_archived_tracking_numbers))
+            .route("/sync", web::get().to(sync_tracked_numbers))
+            .route("/carriers", web::get().to(search_carriers))
+            .route("/tracking_data/{number}", web::get().to(get_tracking_data))
+            .route("/history", web::post().to(get_tracking_history))
+            .route(
+                "/live_updates_ticket",
+                web::post().to(create_live_updates_ticket),
+            )
+            .route("/live_updates", web::get().to(open_live_updates))
+            .route(
+                "/unarchive_tracking_number",
+                web::post().to(unarchive_tracking_number),
+            )
             // HTTPS preflight OPTIONS for test_write
             .service(write_options)
             .service(create_user_options)
+            .service(recover_user_options)
             .service(register_tracking_number_options)
             .service(stop_tracking_number_options)
             .service(retrack_stopped_number_options)
             .service(delete_tracking_number_options)
             .service(get_tracking_data_options)
             .service(get_user_tracked_numbers_details_options)
+            .service(get_user_tracked_numbers_summary_options)
             .service(pull_data_from_API_options)
+            .service(edit_tracking_number_details_options)
+            .service(create_share_invite_options)
+            .service(accept_share_invite_options)
+            .service(list_viewers_options)
+            .service(revoke_viewer_options)
+            .service(archived_options)
+            .service(sync_options)
+            .service(carriers_options)
+            .service(tracking_data_options)
+            .service(history_options)
+            .service(live_updates_ticket_options)
+            .service(unarchive_tracking_number_options)
+            .service(change_carrier_options)
+            .service(change_carrier_param_options)
+            .service(extract_tracking_numbers_options)
+            .service(register_tracking_numbers_bulk_options)
+            .service(create_manual_shipment_options)
+            .service(add_manual_event_options)
+            .service(edit_manual_event_options)
+            .service(remove_manual_event_options)
     })
     // .bind(("127.0.0.1", 8080))?
     .bind(("0.0.0.0", port))? // bxind to all interfaces and the dynamic port
@@ -0,0 +1,380 @@
+/*
+    Cargo stuff
+*/
+
+use crate::my_structs::tracking_data_formats::{
+    status_values::{main_status, sub_status},
+    tracking_data_base::{
+        carrier_info, delivery_estimate, event, milestone, misc_info, provider, time_metrics,
+        time_raw, tracking_details, Address, Coordinates, ShippingInfo, Status, TrackInfo,
+    },
+    tracking_data_database_form::{PackageData, TrackingData_DBF},
+};
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// start of the numbers made for manual shipments, the rest is an object ID so they can't clash with each other
+const MANUAL_NUMBER_PREFIX: &str = "MAN";
+// provider name shown when the user didn't say which courier has the parcel
+const DEFAULT_COURIER_NAME: &str = "Manual";
+// limits for what the user types in
+pub const MAX_MANUAL_EVENTS: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+const MAX_LOCATION_LENGTH: usize = 200;
+const MAX_REFERENCE_LENGTH: usize = 100;
+const MAX_COURIER_NAME_LENGTH: usize = 100;
+// the key stages in the order a parcel goes through them, the milestones are listed in this order like 17TRACK does
+const STAGE_ORDER: [manual_stage; 9] = [
+    manual_stage::InfoReceived,
+    manual_stage::PickedUp,
+    manual_stage::Departure,
+    manual_stage::Arrival,
+    manual_stage::AvailableForPickup,
+    manual_stage::OutForDelivery,
+    manual_stage::Delivered,
+    manual_stage::Returning,
+    manual_stage::Returned,
+];
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// an event of a manual shipment as the user enters it, the time is RFC 3339 with the offset of where it happened
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct manual_event {
+    pub time: String,
+    pub description: String,
+    pub location: Option<String>,
+    pub stage: Option<manual_stage>,
+}
+
+/// the 17TRACK key stages the user can mark an event with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum manual_stage {
+    InfoReceived,
+    PickedUp,
+    Departure,
+    Arrival,
+    AvailableForPickup,
+    OutForDelivery,
+    Delivered,
+    Returning,
+    Returned,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// make a new number for a manual shipment
+pub fn new_manual_number() -> String {
+    format!(
+        "{}{}",
+        MANUAL_NUMBER_PREFIX,
+        ObjectId::new().to_hex().to_uppercase()
+    )
+}
+
+/// Check the reference and the courier name the user gave a new manual shipment, returns the reason if they're not acceptable
+pub fn validate_shipment_details(
+    reference: Option<&str>,
+    courier_name: Option<&str>,
+) -> Result<(), &'static str> {
+    if reference.is_some_and(|reference| reference.chars().count() > MAX_REFERENCE_LENGTH) {
+        return Err("reference is too long");
+    }
+    if courier_name.is_some_and(|name| name.chars().count() > MAX_COURIER_NAME_LENGTH) {
+        return Err("courier name is too long");
+    }
+    Ok(())
+}
+
+impl manual_stage {
+    /// name of the stage like 17TRACK writes it
+    fn name(&self) -> &'static str {
+        match self {
+            manual_stage::InfoReceived => "InfoReceived",
+            manual_stage::PickedUp => "PickedUp",
+            manual_stage::Departure => "Departure",
+            manual_stage::Arrival => "Arrival",
+            manual_stage::AvailableForPickup => "AvailableForPickup",
+            manual_stage::OutForDelivery => "OutForDelivery",
+            manual_stage::Delivered => "Delivered",
+            manual_stage::Returning => "Returning",
+            manual_stage::Returned => "Returned",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<manual_stage> {
+        STAGE_ORDER.into_iter().find(|stage| stage.name() == name)
+    }
+
+    /// the latest status and sub status 17TRACK gives a parcel whose newest event is at this stage
+    fn status(&self) -> (main_status, sub_status) {
+        match self {
+            manual_stage::InfoReceived => (main_status::InfoReceived, sub_status::InfoReceived),
+            manual_stage::PickedUp => (main_status::InTransit, sub_status::InTransitPickedUp),
+            manual_stage::Departure => (main_status::InTransit, sub_status::InTransitDeparture),
+            manual_stage::Arrival => (main_status::InTransit, sub_status::InTransitArrival),
+            manual_stage::AvailableForPickup => (
+                main_status::AvailableForPickup,
+                sub_status::AvailableForPickupOther,
+            ),
+            manual_stage::OutForDelivery => {
+                (main_status::OutForDelivery, sub_status::OutForDeliveryOther)
+            }
+            manual_stage::Delivered => (main_status::Delivered, sub_status::DeliveredOther),
+            manual_stage::Returning => (main_status::Exception, sub_status::ExceptionReturning),
+            manual_stage::Returned => (main_status::Exception, sub_status::ExceptionReturned),
+        }
+    }
+}
+
+impl manual_event {
+    /// Check the event the user entered, returns the reason if it's not acceptable
+    fn validate(&self) -> Result<(), &'static str> {
+        if DateTime::parse_from_rfc3339(&self.time).is_err() {
+            return Err("time has to be RFC 3339 like 2024-05-01T14:30:00+02:00");
+        }
+        if self.description.trim().is_empty() {
+            return Err("description can't be empty");
+        }
+        if self.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err("description is too long");
+        }
+        if self
+            .location
+            .as_ref()
+            .is_some_and(|location| location.chars().count() > MAX_LOCATION_LENGTH)
+        {
+            return Err("location is too long");
+        }
+        Ok(())
+    }
+
+    /// convert to the event format 17TRACK sends, returns the reason if the event is not acceptable
+    pub fn convert_to_event(&self) -> Result<event, &'static str> {
+        self.validate()?;
+        let time: DateTime<FixedOffset> = DateTime::parse_from_rfc3339(&self.time)
+            .map_err(|_| "time has to be RFC 3339 like 2024-05-01T14:30:00+02:00")?;
+        let stage = self.stage.map(|stage| stage.name().to_string());
+        let sub_status = self.stage.map(|stage| stage.status().1);
+        Ok(event {
+            time_iso: Some(time.to_rfc3339_opts(SecondsFormat::Secs, false)),
+            time_utc: Some(
+                time.with_timezone(&Utc)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+            time_raw: time_raw {
+                date: Some(time.format("%Y-%m-%d").to_string()),
+                time: Some(time.format("%H:%M:%S").to_string()),
+                timezone: Some(time.format("%:z").to_string()),
+            },
+            description: Some(self.description.trim().to_string()),
+            location: self
+                .location
+                .as_ref()
+                .map(|location| location.trim().to_string())
+                .filter(|location| !location.is_empty()),
+            stage,
+            sub_status,
+            address: empty_address(),
+        })
+    }
+}
+
+/// Make the tracking data of a new manual shipment, the reference the user gave is the tag and the courier name is the
+/// name of the only provider, the events go in with set_manual_events
+pub fn new_manual_tracking_data(
+    number: String,
+    reference: Option<String>,
+    courier_name: Option<String>,
+) -> TrackingData_DBF {
+    TrackingData_DBF {
+        data: PackageData {
+            number,
+            carrier: 0,
+            param: None,
+            tag: reference.filter(|reference| !reference.trim().is_empty()),
+            track_info: TrackInfo {
+                lastGatherTime: None,
+                shipping_info: ShippingInfo {
+                    shipper_address: empty_address(),
+                    recipient_address: empty_address(),
+                },
+                latest_status: Status {
+                    status: None,
+                    sub_status: None,
+                    sub_status_descr: None,
+                },
+                latest_event: empty_event(),
+                time_metrics: time_metrics {
+                    days_after_order: None,
+                    days_of_transit: None,
+                    days_of_transit_done: None,
+                    days_after_last_update: None,
+                    estimated_delivery_date: delivery_estimate {
+                        source: None,
+                        from: None,
+                        to: None,
+                    },
+                },
+                milestone: Vec::new(),
+                misc_info: misc_info {
+                    risk_factor: 0,
+                    service_type: None,
+                    weight_raw: None,
+                    weight_kg: None,
+                    pieces: None,
+                    dimensions: None,
+                    customer_number: None,
+                    reference_number: None,
+                    local_number: None,
+                    local_provider: None,
+                    local_key: None,
+                },
+                tracking: tracking_details {
+                    providers_hash: None,
+                    providers: vec![provider {
+                        provider: carrier_info {
+                            key: None,
+                            name: Some(
+                                courier_name
+                                    .map(|name| name.trim().to_string())
+                                    .filter(|name| !name.is_empty())
+                                    .unwrap_or_else(|| DEFAULT_COURIER_NAME.to_string()),
+                            ),
+                            alias: None,
+                            tel: None,
+                            homepage: None,
+                        },
+                        provider_lang: None,
+                        service_type: None,
+                        latest_sync_status: None,
+                        latest_sync_time: None,
+                        events_hash: None,
+                        events: Vec::new(),
+                    }],
+                },
+            },
+        },
+        updated_at: Some(mongodb::bson::DateTime::now()),
+        manual: true,
+    }
+}
+
+/// the events of the manual shipment, newest first, the index the user edits or removes an event by is the place in here
+pub fn manual_events(tracking_data: &TrackingData_DBF) -> Vec<event> {
+    tracking_data
+        .data
+        .track_info
+        .tracking
+        .providers
+        .first()
+        .map(|provider| provider.events.clone())
+        .unwrap_or_default()
+}
+
+/// Save the events in the tracking data of the manual shipment and work out everything 17TRACK would from them, the latest
+/// event and status and the milestones
+pub fn set_manual_events(tracking_data: &mut TrackingData_DBF, mut events: Vec<event>) {
+    // newest first like 17TRACK, the times are all RFC 3339 in UTC so they sort as strings
+    events.sort_by(|a, b| b.time_utc.cmp(&a.time_utc));
+    let track_info = &mut tracking_data.data.track_info;
+
+    // the status comes from the newest event with a stage
+    let latest_stage = events
+        .iter()
+        .find_map(|event| event.stage.as_deref().and_then(manual_stage::from_name));
+    let (status, sub_status) = match (latest_stage, events.is_empty()) {
+        (Some(stage), _) => stage.status(),
+        (None, false) => (main_status::InTransit, sub_status::InTransitOther),
+        (None, true) => (main_status::InfoReceived, sub_status::InfoReceived),
+    };
+    track_info.latest_status = Status {
+        status: Some(status),
+        sub_status: Some(sub_status),
+        sub_status_descr: None,
+    };
+    track_info.latest_event = events.first().cloned().unwrap_or_else(empty_event);
+    //
+
+    // every key stage with the time it was first reached
+    track_info.milestone = STAGE_ORDER
+        .iter()
+        .map(|stage| {
+            let reached = events
+                .iter()
+                .rev()
+                .find(|event| event.stage.as_deref() == Some(stage.name()));
+            milestone {
+                key_stage: Some(stage.name().to_string()),
+                time_iso: reached.and_then(|event| event.time_iso.clone()),
+                time_utc: reached.and_then(|event| event.time_utc.clone()),
+                time_raw: reached
+                    .map(|event| event.time_raw.clone())
+                    .unwrap_or(time_raw {
+                        date: None,
+                        time: None,
+                        timezone: None,
+                    }),
+            }
+        })
+        .collect();
+    //
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    track_info.lastGatherTime = Some(now.clone());
+    if let Some(provider) = track_info.tracking.providers.first_mut() {
+        provider.latest_sync_status = Some("Success".to_string());
+        provider.latest_sync_time = Some(now);
+        provider.events = events;
+    }
+    tracking_data.updated_at = Some(mongodb::bson::DateTime::now());
+}
+
+fn empty_address() -> Address {
+    Address {
+        country: None,
+        state: None,
+        city: None,
+        street: None,
+        postal_code: None,
+        coordinates: Coordinates {
+            longitude: None,
+            latitude: None,
+        },
+    }
+}
+
+fn empty_event() -> event {
+    event {
+        time_iso: None,
+        time_utc: None,
+        time_raw: time_raw {
+            date: None,
+            time: None,
+            timezone: None,
+        },
+        description: None,
+        location: None,
+        stage: None,
+        sub_status: None,
+        address: empty_address(),
+    }
+}
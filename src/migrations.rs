@@ -0,0 +1,425 @@
+/*
+    Cargo stuff
+*/
+
+use crate::{
+    number_detection::normalize_tracking_number, trackingapi::tracking_client,
+    user_identity::user_id_hasher,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::IndexOptions,
+    Client, IndexModel,
+};
+use std::{collections::HashMap, time::Duration};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    MIGRATIONS
+
+    every migration has to be safe to run on each start, they are called before the server starts accepting requests, the
+    ones that only have to run once record it in the migrations collection
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Run all the database migrations in order
+pub async fn run_migrations(
+    client: &Client,
+    user_id_hasher: &user_id_hasher,
+    tracking_client: &tracking_client,
+) -> Result<(), mongodb::error::Error> {
+    migrate_user_id_hashes(client, user_id_hasher).await?;
+    migrate_normalized_tracking_numbers(client, tracking_client).await?;
+    create_indexes(client).await?;
+    Ok(())
+}
+
+/// Re-hash every user whose identifier was made with the old unkeyed SHA-256 or with a rotated secret, and rewrite the
+/// relation records, share invites and tombstones that point to the old identifier, every old identifier is kept on the user in previous_user_id_hashes so
+/// a client still holding one is told to recover the new one (see @CHECK_USER_EXISTS)
+async fn migrate_user_id_hashes(
+    client: &Client,
+    user_id_hasher: &user_id_hasher,
+) -> Result<(), mongodb::error::Error> {
+    // set database and collections
+    let db = client.database("teletrack");
+    let collection_users: mongodb::Collection<Document> = db.collection("users");
+    let collection_relations: mongodb::Collection<Document> =
+        db.collection("tracking_number_user_relation");
+    let collection_invites: mongodb::Collection<Document> = db.collection("share_invites");
+    let collection_tombstones: mongodb::Collection<Document> = db.collection("relation_tombstones");
+
+    // every user that wasn't hashed with the current secret, missing key id means the legacy format
+    let filter = doc! {"user_id_hash_key_id": {"$ne": user_id_hasher.key_id()}};
+    let outdated_users = collection_users
+        .find(filter, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    //
+
+    let mut migrated_count = 0;
+    for user in outdated_users {
+        let (user_id, old_user_id_hash) =
+            match (user.get_i64("user_id"), user.get_str("user_id_hash")) {
+                (Ok(user_id), Ok(old_user_id_hash)) => (user_id, old_user_id_hash.to_string()),
+                _ => {
+                    println!("@MIGRATE_USER_ID_HASHES: skipping malformed user document");
+                    continue;
+                }
+            };
+        let new_user_id_hash = user_id_hasher.hash_user_id(user_id);
+
+        // relations first, if the process dies in between the user still has the old key id and gets picked up again, the
+        // viewers the user shared numbers with, the user's invites and the tombstones of the sync point to the user too
+        collection_relations
+            .update_many(
+                doc! {"user_id_hash": &old_user_id_hash},
+                doc! {"$set": {"user_id_hash": &new_user_id_hash}},
+                None,
+            )
+            .await?;
+        collection_relations
+            .update_many(
+                doc! {"shared_by": &old_user_id_hash},
+                doc! {"$set": {"shared_by": &new_user_id_hash}},
+                None,
+            )
+            .await?;
+        collection_invites
+            .update_many(
+                doc! {"owner_user_id_hash": &old_user_id_hash},
+                doc! {"$set": {"owner_user_id_hash": &new_user_id_hash}},
+                None,
+            )
+            .await?;
+        collection_invites
+            .update_many(
+                doc! {"accepted_by": &old_user_id_hash},
+                doc! {"$set": {"accepted_by": &new_user_id_hash}},
+                None,
+            )
+            .await?;
+        collection_tombstones
+            .update_many(
+                doc! {"user_id_hash": &old_user_id_hash},
+                doc! {"$set": {"user_id_hash": &new_user_id_hash}},
+                None,
+            )
+            .await?;
+
+        // the single previous identifier the users had before the list is folded into it
+        let mut previous_user_id_hashes = vec![old_user_id_hash];
+        if let Ok(previous_user_id_hash) = user.get_str("previous_user_id_hash") {
+            previous_user_id_hashes.push(previous_user_id_hash.to_string());
+        }
+        collection_users
+            .update_one(
+                doc! {"user_id": user_id},
+                doc! {
+                    "$set": {
+                        "user_id_hash": &new_user_id_hash,
+                        "user_id_hash_key_id": user_id_hasher.key_id(),
+                    },
+                    "$addToSet": {"previous_user_id_hashes": {"$each": previous_user_id_hashes}},
+                    "$unset": {"previous_user_id_hash": ""},
+                },
+                None,
+            )
+            .await?;
+        migrated_count += 1;
+    }
+
+    if migrated_count > 0 {
+        println!(
+            "@MIGRATE_USER_ID_HASHES: re-hashed {} users with key {}",
+            migrated_count,
+            user_id_hasher.key_id()
+        );
+    }
+    Ok(())
+}
+
+/// Rewrite the tracking numbers saved before they were normalised (see number_detection.rs) and merge the records that
+/// turn out to be the same number, for the relations the owner's subscribed and oldest one is kept with the details of the
+/// others filled in where it has none, for the tracking data the most recently updated one is kept and the other spellings
+/// are deleted from 17TRACK, it only runs once since the numbers have been normalised when they're saved ever since
+async fn migrate_normalized_tracking_numbers(
+    client: &Client,
+    tracking_client: &tracking_client,
+) -> Result<(), mongodb::error::Error> {
+    // set database and collections
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<Document> =
+        db.collection("tracking_number_user_relation");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let collection_tombstones: mongodb::Collection<Document> = db.collection("relation_tombstones");
+    let collection_migrations: mongodb::Collection<Document> = db.collection("migrations");
+
+    // skip it when an earlier start already ran it
+    let migration_id = "normalized_tracking_numbers";
+    if collection_migrations
+        .find_one(doc! {"_id": migration_id}, None)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+    //
+
+    // group the relations by user, chat and normalised number
+    let relations = collection_relations
+        .find(doc! {}, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let mut relation_groups: HashMap<(String, Option<i64>, String), Vec<Document>> = HashMap::new();
+    for relation in relations {
+        let (tracking_number, user_id_hash) = match (
+            relation.get_str("tracking_number"),
+            relation.get_str("user_id_hash"),
+        ) {
+            (Ok(tracking_number), Ok(user_id_hash)) => (
+                normalize_tracking_number(tracking_number),
+                user_id_hash.to_string(),
+            ),
+            _ => continue,
+        };
+        let chat_id = relation.get_i64("chat_id").ok();
+        relation_groups
+            .entry((user_id_hash, chat_id, tracking_number))
+            .or_default()
+            .push(relation);
+    }
+    //
+
+    let mut merged_relations = 0;
+    for ((user_id_hash, chat_id, tracking_number), mut relations) in relation_groups {
+        if relations.len() == 1
+            && relations[0].get_str("tracking_number") == Ok(tracking_number.as_str())
+        {
+            continue;
+        }
+        relations.sort_by_key(|relation| {
+            (
+                relation.get_str("role") == Ok("viewer"),
+                !relation.get_bool("is_subscribed").unwrap_or(false),
+                relation.get_object_id("_id").ok(),
+            )
+        });
+        let (kept, duplicates) = match relations.split_first() {
+            Some(split) => split,
+            None => continue,
+        };
+
+        // the kept relation gets the normalised number and the details only the duplicates have
+        let mut update = doc! {"tracking_number": &tracking_number, "updated_at": DateTime::now()};
+        for field in ["label", "note", "color"] {
+            if kept.get_str(field).is_err() {
+                if let Some(value) = duplicates.iter().find_map(|d| d.get_str(field).ok()) {
+                    update.insert(field, value);
+                }
+            }
+        }
+        if duplicates
+            .iter()
+            .any(|d| d.get_bool("pinned").unwrap_or(false))
+        {
+            update.insert("pinned", true);
+        }
+        collection_relations
+            .update_one(
+                doc! {"_id": kept.get_object_id("_id").ok()},
+                doc! {"$set": update},
+                None,
+            )
+            .await?;
+        let duplicate_ids: Vec<_> = duplicates
+            .iter()
+            .filter_map(|d| d.get_object_id("_id").ok())
+            .collect();
+        if !duplicate_ids.is_empty() {
+            collection_relations
+                .delete_many(doc! {"_id": {"$in": duplicate_ids}}, None)
+                .await?;
+        }
+        //
+
+        // the synced clients drop the old spellings of the number (see @SYNC_TRACKED_NUMBERS), group relations aren't synced
+        if chat_id.is_none() {
+            let mut old_numbers: Vec<&str> = relations
+                .iter()
+                .filter_map(|relation| relation.get_str("tracking_number").ok())
+                .filter(|old_number| *old_number != tracking_number)
+                .collect();
+            old_numbers.sort_unstable();
+            old_numbers.dedup();
+            let tombstones: Vec<Document> = old_numbers
+                .into_iter()
+                .map(|old_number| {
+                    doc! {
+                        "tracking_number": old_number,
+                        "user_id_hash": &user_id_hash,
+                        "deleted_at": DateTime::now(),
+                    }
+                })
+                .collect();
+            if !tombstones.is_empty() {
+                collection_tombstones.insert_many(tombstones, None).await?;
+            }
+        }
+        //
+        merged_relations += 1;
+    }
+
+    // same for the tracking data, grouped by normalised number
+    let tracking_data = collection_tracking_data
+        .find(doc! {}, None)
+        .await?
+        .try_collect::<Vec<Document>>()
+        .await?;
+    let mut tracking_data_groups: HashMap<String, Vec<Document>> = HashMap::new();
+    for document in tracking_data {
+        let tracking_number = match document
+            .get_document("data")
+            .and_then(|data| data.get_str("number"))
+        {
+            Ok(tracking_number) => normalize_tracking_number(tracking_number),
+            Err(_) => continue,
+        };
+        tracking_data_groups
+            .entry(tracking_number)
+            .or_default()
+            .push(document);
+    }
+    //
+
+    let mut merged_tracking_data = 0;
+    for (tracking_number, mut documents) in tracking_data_groups {
+        let stored_number = |document: &Document| {
+            document
+                .get_document("data")
+                .and_then(|data| data.get_str("number"))
+                .map(str::to_string)
+        };
+        if documents.len() == 1 && stored_number(&documents[0]).as_ref() == Ok(&tracking_number) {
+            continue;
+        }
+        // newest first
+        documents.sort_by_key(|document| {
+            std::cmp::Reverse(document.get_datetime("updated_at").ok().copied())
+        });
+        let (kept, duplicates) = match documents.split_first() {
+            Some(split) => split,
+            None => continue,
+        };
+        collection_tracking_data
+            .update_one(
+                doc! {"_id": kept.get_object_id("_id").ok()},
+                doc! {"$set": {"data.number": &tracking_number}},
+                None,
+            )
+            .await?;
+        let duplicate_ids: Vec<_> = duplicates
+            .iter()
+            .filter_map(|d| d.get_object_id("_id").ok())
+            .collect();
+        if !duplicate_ids.is_empty() {
+            collection_tracking_data
+                .delete_many(doc! {"_id": {"$in": duplicate_ids}}, None)
+                .await?;
+        }
+
+        // the duplicates were registered on 17TRACK under their own spelling, the kept spelling stays registered, the ones
+        // already deleted from it by the archive job and the manual shipments were never there
+        let kept_number = stored_number(kept).ok();
+        let mut extra_registrations: Vec<String> = duplicates
+            .iter()
+            .filter(|d| !d.get_bool("manual").unwrap_or(false))
+            .filter(|d| !d.get_bool("deleted_from_provider").unwrap_or(false))
+            .filter_map(|d| stored_number(d).ok())
+            .filter(|number| Some(number) != kept_number.as_ref() && *number != tracking_number)
+            .collect();
+        extra_registrations.sort_unstable();
+        extra_registrations.dedup();
+        for number in extra_registrations {
+            if let Err(e) = tracking_client.delete_number(&number).await {
+                println!(
+                    "@MIGRATE_NORMALIZED_TRACKING_NUMBERS: error deleting {} from the provider: {}",
+                    number, e
+                );
+            }
+        }
+        //
+        merged_tracking_data += 1;
+    }
+
+    if merged_relations > 0 || merged_tracking_data > 0 {
+        println!(
+            "@MIGRATE_NORMALIZED_TRACKING_NUMBERS: normalised {} relations and {} tracking data records",
+            merged_relations, merged_tracking_data
+        );
+    }
+    collection_migrations
+        .insert_one(
+            doc! {"_id": migration_id, "completed_at": DateTime::now()},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Create the indexes the lookups of the aggregation pipelines join on (see database_pipelines.rs) and the ones of the
+/// tombstones and the history, creating an index that already exists does nothing
+async fn create_indexes(client: &Client) -> Result<(), mongodb::error::Error> {
+    // set database
+    let db = client.database("teletrack");
+
+    let indexes = [
+        ("users", doc! {"user_id_hash": 1}),
+        ("tracking_number_user_relation", doc! {"user_id_hash": 1}),
+        ("tracking_number_user_relation", doc! {"tracking_number": 1}),
+        (
+            "tracking_data_history",
+            doc! {"tracking_number": 1, "recorded_at": 1},
+        ),
+    ];
+    for (collection, keys) in indexes {
+        db.collection::<Document>(collection)
+            .create_index(IndexModel::builder().keys(keys).build(), None)
+            .await?;
+    }
+
+    // one tracking data document per number, the saves upsert on it (see @SAVE_TRACKING_DATA) and the normalisation above
+    // already merged the duplicates
+    db.collection::<Document>("tracking_data")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"data.number": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // the tombstones of deleted relations expire on their own (see @SYNC_TRACKED_NUMBERS)
+    let tombstone_ttl = Duration::from_secs(crate::SYNC_TOMBSTONE_TTL_DAYS as u64 * 24 * 60 * 60);
+    db.collection::<Document>("relation_tombstones")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! {"deleted_at": 1})
+                .options(IndexOptions::builder().expire_after(tombstone_ttl).build())
+                .build(),
+            None,
+        )
+        .await?;
+    db.collection::<Document>("relation_tombstones")
+        .create_index(
+            IndexModel::builder().keys(doc! {"user_id_hash": 1}).build(),
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -228,6 +228,135 @@ pub mod delete_tracking_number_response {
     }
 }
 
+/// Change Carrier
+/*
+    {
+        "code": 0,
+        "data": {
+            "accepted": [
+            {
+                "number": "RR123456789CN",
+                "carrier": 3011
+            }
+            ],
+            "rejected": [
+            {
+                "number": "21213123123230",
+                "error": {
+                "code": -18019902,
+                "message": "The tracking number '21213123123230' does not register, please register first."
+                }
+            }
+            ]
+        }
+    }
+*/
+pub mod change_carrier_response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct ChangeCarrierResponse {
+        pub code: i32,
+        pub data: Data,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Data {
+        pub accepted: Vec<Accepted>,
+        pub rejected: Vec<Rejected>,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Accepted {
+        pub number: String,
+        pub carrier: Option<i32>,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Rejected {
+        pub number: String,
+        pub error: RejectedError,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RejectedError {
+        pub code: i32,
+        pub message: String,
+    }
+}
+
+/// Change Info
+/*
+    {
+        "code": 0,
+        "data": {
+            "accepted": [
+            {
+                "number": "RR123456789CN",
+                "carrier": 3011
+            }
+            ],
+            "rejected": []
+        }
+    }
+*/
+pub mod change_info_response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct ChangeInfoResponse {
+        pub code: i32,
+        pub data: Data,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Data {
+        pub accepted: Vec<Accepted>,
+        pub rejected: Vec<Rejected>,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Accepted {
+        pub number: String,
+        pub carrier: Option<i32>,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Rejected {
+        pub number: String,
+        pub error: RejectedError,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct RejectedError {
+        pub code: i32,
+        pub message: String,
+    }
+}
+
+/// Get Quota
+/*
+    {
+        "code": 0,
+        "data": {
+            "quota_total": 200,
+            "quota_used": 4,
+            "quota_remain": 196,
+            "today_used": 1,
+            "max_track_daily": 10000
+        }
+    }
+*/
+pub mod quota_response {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct QuotaResponse {
+        pub code: i32,
+        pub data: QuotaData,
+    }
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct QuotaData {
+        pub quota_total: Option<i64>,
+        pub quota_used: Option<i64>,
+        pub quota_remain: Option<i64>,
+        pub today_used: Option<i64>,
+        pub max_track_daily: Option<i64>,
+    }
+}
+
 /// Gettrackinfo
 /*
     refer to:
@@ -258,6 +387,8 @@ pub mod tracking_data_get_info {
                     tag: accepted_package.tag.clone(),
                     track_info: accepted_package.track_info.clone(),
                 },
+                updated_at: Some(mongodb::bson::DateTime::now()),
+                manual: false,
             }
         }
     }
@@ -272,7 +403,7 @@ pub mod tracking_data_get_info {
     pub struct AcceptedPackage {
         pub number: String,
         pub carrier: i32,
-        pub param: Option<()>,
+        pub param: Option<String>,
         pub tag: Option<String>,
         pub track_info: TrackInfo,
     }
@@ -345,6 +476,7 @@ pub mod tracking_data_get_info {
 
 */
 pub mod tracking_number_meta_data {
+    use super::status_values::{main_status, tracking_status};
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -368,7 +500,7 @@ pub mod tracking_number_meta_data {
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct AcceptedPage {
         pub number: Option<String>,
-        pub param: Option<()>,
+        pub param: Option<String>,
         pub param_type: Option<String>,
         pub data_origin: Option<String>,
         pub carrier: Option<i32>,
@@ -376,8 +508,8 @@ pub mod tracking_number_meta_data {
         pub final_carrier: Option<i32>,
         pub recipient_country: Option<String>,
         pub register_time: Option<String>,
-        pub tracking_status: String,
-        pub package_status: String,
+        pub tracking_status: tracking_status,
+        pub package_status: main_status,
         pub track_time: Option<String>,
         pub push_time: Option<String>,
         pub push_status: Option<String>,
@@ -394,10 +526,10 @@ pub mod tracking_number_meta_data {
         pub remark: Option<String>,
         pub latest_event_time: Option<String>,
         pub latest_event_info: Option<String>,
-        pub days_after_order: Option<String>,
-        pub days_after_last_update: Option<String>,
-        pub days_of_transit: Option<String>,
-        pub days_of_transit_done: Option<String>,
+        pub days_after_order: Option<i32>,
+        pub days_after_last_update: Option<i32>,
+        pub days_of_transit: Option<i32>,
+        pub days_of_transit_done: Option<i32>,
         pub delievery_time: Option<String>,
         pub pickup_time: Option<String>,
     }
@@ -431,6 +563,8 @@ pub mod tracking_data_webhook_update {
                         tag: accepted_package.tag.clone(),
                         track_info: accepted_package.track_info.clone(),
                     },
+                    updated_at: Some(mongodb::bson::DateTime::now()),
+                    manual: false,
                 })
             } else {
                 None
@@ -450,7 +584,7 @@ pub mod tracking_data_webhook_update {
     pub struct TrackingStopped {
         pub number: String,
         pub carrier: i32,
-        pub param: Option<()>,
+        pub param: Option<String>,
         pub tag: Option<String>,
     }
 
@@ -458,7 +592,7 @@ pub mod tracking_data_webhook_update {
     pub struct PackageDataWebhook {
         pub number: String,
         pub carrier: i32,
-        pub param: Option<()>,
+        pub param: Option<String>,
         pub tag: Option<String>,
         pub track_info: super::tracking_data_base::TrackInfo,
     }
@@ -474,6 +608,8 @@ pub mod tracking_data_webhook_update {
                     tag: self.tag.clone(),
                     track_info: self.track_info.clone(),
                 },
+                updated_at: Some(mongodb::bson::DateTime::now()),
+                manual: false,
             })
         }
         // to HTMLf
@@ -491,13 +627,156 @@ pub mod tracking_data_webhook_update {
                     .iter()
                     .map(|provider| provider.convert_to_HTML_provider())
                     .collect(),
+                timeline: self.track_info.merged_timeline(),
+                progress: Some(self.track_info.delivery_progress()),
                 time_metrics: Some(self.track_info.time_metrics.clone()),
+                shipping_info: Some(self.track_info.shipping_info.clone()),
                 is_user_tracked: None,
+                label: None,
+                note: None,
+                pinned: None,
+                color: None,
+                is_owner: None,
+                archived: None,
             }
         }
     }
 }
 
+/// Status values
+/*
+    refer to:
+    https://api.17track.net/en/doc?version=v2.2&anchor=main-status-and-sub-status
+*/
+pub mod status_values {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // enum of the strings 17TRACK sends, one it adds later is kept in Unknown so reading it never fails and saving it
+    // again gives back the same string
+    macro_rules! string_enum {
+        ($name:ident { $($variant:ident => $value:literal,)* }) => {
+            #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+            pub enum $name {
+                $($variant,)*
+                Unknown(String),
+            }
+
+            impl $name {
+                pub fn as_str(&self) -> &str {
+                    match self {
+                        $($name::$variant => $value,)*
+                        $name::Unknown(value) => value,
+                    }
+                }
+            }
+
+            impl From<&str> for $name {
+                fn from(value: &str) -> Self {
+                    match value {
+                        $($value => $name::$variant,)*
+                        _ => $name::Unknown(value.to_string()),
+                    }
+                }
+            }
+
+            impl std::fmt::Display for $name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.write_str(self.as_str())
+                }
+            }
+
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_str(self.as_str())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    Ok($name::from(String::deserialize(deserializer)?.as_str()))
+                }
+            }
+        };
+    }
+
+    // latest_status.status of the tracking data and package_status of the number list
+    string_enum!(main_status {
+        NotFound => "NotFound",
+        InfoReceived => "InfoReceived",
+        InTransit => "InTransit",
+        Expired => "Expired",
+        AvailableForPickup => "AvailableForPickup",
+        OutForDelivery => "OutForDelivery",
+        DeliveryFailure => "DeliveryFailure",
+        Delivered => "Delivered",
+        Exception => "Exception",
+    });
+
+    // sub_status of the latest status and of the events, each one belongs to the main status it starts with
+    string_enum!(sub_status {
+        NotFoundOther => "NotFound_Other",
+        NotFoundInvalidCode => "NotFound_InvalidCode",
+        InfoReceived => "InfoReceived",
+        InTransitPickedUp => "InTransit_PickedUp",
+        InTransitOther => "InTransit_Other",
+        InTransitDeparture => "InTransit_Departure",
+        InTransitArrival => "InTransit_Arrival",
+        InTransitCustomsProcessing => "InTransit_CustomsProcessing",
+        InTransitCustomsReleased => "InTransit_CustomsReleased",
+        InTransitCustomsRequiringInformation => "InTransit_CustomsRequiringInformation",
+        ExpiredOther => "Expired_Other",
+        AvailableForPickupOther => "AvailableForPickup_Other",
+        OutForDeliveryOther => "OutForDelivery_Other",
+        DeliveryFailureOther => "DeliveryFailure_Other",
+        DeliveryFailureNoBody => "DeliveryFailure_NoBody",
+        DeliveryFailureSecurity => "DeliveryFailure_Security",
+        DeliveryFailureRejected => "DeliveryFailure_Rejected",
+        DeliveryFailureInvalidAddress => "DeliveryFailure_InvalidAddress",
+        DeliveredOther => "Delivered_Other",
+        ExceptionOther => "Exception_Other",
+        ExceptionReturning => "Exception_Returning",
+        ExceptionReturned => "Exception_Returned",
+        ExceptionNoBody => "Exception_NoBody",
+        ExceptionSecurity => "Exception_Security",
+        ExceptionDamage => "Exception_Damage",
+        ExceptionRejected => "Exception_Rejected",
+        ExceptionDelayed => "Exception_Delayed",
+        ExceptionLost => "Exception_Lost",
+        ExceptionDestroyed => "Exception_Destroyed",
+        ExceptionCancel => "Exception_Cancel",
+    });
+
+    // tracking_status of the number list, whether 17TRACK still follows the number
+    string_enum!(tracking_status {
+        Tracking => "Tracking",
+        Stopped => "Stopped",
+    });
+
+    impl main_status {
+        // no updates come after it, the number can't be re-tracked
+        pub fn is_delivered(&self) -> bool {
+            *self == main_status::Delivered
+        }
+    }
+
+    impl sub_status {
+        pub fn is_returning(&self) -> bool {
+            *self == sub_status::ExceptionReturning
+        }
+
+        pub fn is_returned(&self) -> bool {
+            *self == sub_status::ExceptionReturned
+        }
+    }
+
+    impl tracking_status {
+        // 17TRACK doesn't follow the number anymore, it has to be re-tracked to get updates
+        pub fn is_stopped(&self) -> bool {
+            *self == tracking_status::Stopped
+        }
+    }
+}
+
 /*
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
     CUSTOM FORMATS
@@ -507,6 +786,12 @@ pub mod tracking_data_webhook_update {
 
 ///  Base form structs that repeat
 pub mod tracking_data_base {
+    use super::status_values::{main_status, sub_status};
+    use super::tracking_data_html_form::{
+        completed_stage, delivery_progress, progress_stage, timeline_event, timeline_provider,
+        PROGRESS_STAGES,
+    };
+    use chrono::{DateTime, SecondsFormat, Utc};
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -522,12 +807,234 @@ pub mod tracking_data_base {
         pub tracking: tracking_details,
     }
 
+    // one timeline from the events of all the providers, oldest first, the same scan reported by more providers is in it
+    // once with all of them, events without a readable time go at the end in the order they came
+    impl TrackInfo {
+        pub fn merged_timeline(&self) -> Vec<timeline_event> {
+            let mut timeline: Vec<(Option<DateTime<Utc>>, timeline_event)> = Vec::new();
+            for provider in self.tracking.providers.iter() {
+                let timeline_provider = timeline_provider {
+                    provider_name: provider.provider.name.clone(),
+                    provider_key: provider.provider.key,
+                };
+                for event in provider.events.iter() {
+                    let instant = event.instant();
+                    let duplicate = timeline.iter_mut().find(|(other_instant, other)| {
+                        *other_instant == instant
+                            && (instant.is_some() || other.time.as_ref() == Some(&event.time_raw))
+                            && normalized_text(&other.location) == normalized_text(&event.location)
+                            && normalized_text(&other.description)
+                                == normalized_text(&event.description)
+                    });
+                    match duplicate {
+                        Some((_, merged)) => {
+                            // keep what the first provider said, fill in what it left out
+                            merged.stage = merged.stage.take().or_else(|| event.stage.clone());
+                            merged.sub_status = merged
+                                .sub_status
+                                .take()
+                                .or_else(|| event.sub_status.clone());
+                            if !merged.providers.contains(&timeline_provider) {
+                                merged.providers.push(timeline_provider.clone());
+                            }
+                        }
+                        None => timeline.push((
+                            instant,
+                            timeline_event {
+                                time: Some(event.time_raw.clone()),
+                                time_utc: instant.map(|instant| {
+                                    instant.to_rfc3339_opts(SecondsFormat::Secs, true)
+                                }),
+                                description: event.description.clone(),
+                                location: event.location.clone(),
+                                stage: event.stage.clone(),
+                                sub_status: event.sub_status.clone(),
+                                address: Some(event.address.clone()),
+                                providers: vec![timeline_provider.clone()],
+                                milestone: None,
+                            },
+                        )),
+                    }
+                }
+            }
+
+            // oldest first, the sort is stable so the ones without a time stay in order
+            timeline.sort_by_key(|(instant, _)| (instant.is_none(), *instant));
+
+            // the event that reached a milestone is the one at its time, the one with its stage if there's more
+            for milestone in self.milestone.iter() {
+                let (Some(key_stage), Some(instant)) = (&milestone.key_stage, milestone.instant())
+                else {
+                    continue;
+                };
+                let at_milestone_time =
+                    |(event_instant, event): &(Option<DateTime<Utc>>, timeline_event)| {
+                        *event_instant == Some(instant) && event.milestone.is_none()
+                    };
+                let index = timeline
+                    .iter()
+                    .position(|entry| {
+                        at_milestone_time(entry) && entry.1.stage.as_ref() == Some(key_stage)
+                    })
+                    .or_else(|| timeline.iter().position(at_milestone_time));
+                if let Some(index) = index {
+                    timeline[index].1.milestone = Some(key_stage.clone());
+                }
+            }
+
+            timeline.into_iter().map(|(_, event)| event).collect()
+        }
+    }
+
+    // where the parcel is on the way from the order to the door, from the milestones it reached and the latest status,
+    // whichever is further, the stages before the current one count as done even when the carrier skipped their scan
+    impl TrackInfo {
+        pub fn delivery_progress(&self) -> delivery_progress {
+            let status = self.latest_status.status.as_ref();
+            let sub_status = self.latest_status.sub_status.as_ref();
+
+            // the earliest time each stage was reached at
+            let mut reached: Vec<(progress_stage, DateTime<Utc>)> = Vec::new();
+            for milestone in self.milestone.iter() {
+                let Some(instant) = milestone.instant() else {
+                    continue;
+                };
+                let Some(stage) = milestone
+                    .key_stage
+                    .as_deref()
+                    .and_then(progress_stage::from_key_stage)
+                else {
+                    continue;
+                };
+                match reached.iter_mut().find(|(other, _)| *other == stage) {
+                    Some((_, time)) => *time = (*time).min(instant),
+                    None => reached.push((stage, instant)),
+                }
+            }
+            //
+
+            // the furthest stage, from the milestones or the status
+            let stage_from_status = match (status, sub_status) {
+                (_, Some(sub_status::InTransitPickedUp)) => Some(progress_stage::PickedUp),
+                (Some(main_status::InfoReceived), _) => Some(progress_stage::InfoReceived),
+                (Some(main_status::InTransit), _) => Some(progress_stage::InTransit),
+                (
+                    Some(
+                        main_status::AvailableForPickup
+                        | main_status::OutForDelivery
+                        | main_status::DeliveryFailure,
+                    ),
+                    _,
+                ) => Some(progress_stage::OutForDelivery),
+                (Some(main_status::Delivered), _) => Some(progress_stage::Delivered),
+                _ => None,
+            };
+            let stage = reached
+                .iter()
+                .map(|(stage, _)| *stage)
+                .chain(stage_from_status)
+                .max();
+            //
+
+            let completed_stages = PROGRESS_STAGES
+                .into_iter()
+                .filter(|completed| stage.is_some_and(|stage| *completed <= stage))
+                .map(|completed| completed_stage {
+                    stage: completed,
+                    time_utc: reached
+                        .iter()
+                        .find(|(reached_stage, _)| *reached_stage == completed)
+                        .map(|(_, time)| *time)
+                        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                })
+                .collect();
+            let reached_key_stage = |key_stage: &str| {
+                self.milestone.iter().any(|milestone| {
+                    milestone.key_stage.as_deref() == Some(key_stage)
+                        && milestone.instant().is_some()
+                })
+            };
+            let returned =
+                sub_status.is_some_and(sub_status::is_returned) || reached_key_stage("Returned");
+
+            delivery_progress {
+                stage,
+                completed_stages,
+                percent: stage.map_or(0, |stage| stage.percent()),
+                exception: status == Some(&main_status::Exception),
+                delivery_failure: status == Some(&main_status::DeliveryFailure),
+                expired: status == Some(&main_status::Expired),
+                returning: !returned
+                    && (sub_status.is_some_and(sub_status::is_returning)
+                        || reached_key_stage("Returning")),
+                returned,
+            }
+        }
+    }
+
+    impl progress_stage {
+        // the stage of a 17TRACK milestone, returns aren't on the way to the door and have their own flags
+        fn from_key_stage(key_stage: &str) -> Option<progress_stage> {
+            match key_stage {
+                "InfoReceived" => Some(progress_stage::InfoReceived),
+                "PickedUp" => Some(progress_stage::PickedUp),
+                "Departure" | "Arrival" => Some(progress_stage::InTransit),
+                "AvailableForPickup" | "OutForDelivery" => Some(progress_stage::OutForDelivery),
+                "Delivered" => Some(progress_stage::Delivered),
+                _ => None,
+            }
+        }
+
+        fn percent(&self) -> u8 {
+            match self {
+                progress_stage::InfoReceived => 0,
+                progress_stage::PickedUp => 25,
+                progress_stage::InTransit => 50,
+                progress_stage::OutForDelivery => 75,
+                progress_stage::Delivered => 100,
+            }
+        }
+    }
+
+    // the UTC time of the event or the milestone, from time_utc or else time_iso
+    fn parse_instant(
+        time_utc: &Option<String>,
+        time_iso: &Option<String>,
+    ) -> Option<DateTime<Utc>> {
+        time_utc
+            .iter()
+            .chain(time_iso.iter())
+            .find_map(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    // lowercase words without the punctuation, so "Arrived at facility." and "ARRIVED AT FACILITY" are the same
+    fn normalized_text(text: &Option<String>) -> String {
+        text.as_deref()
+            .unwrap_or_default()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct ShippingInfo {
         pub shipper_address: Address,
         pub recipient_address: Address,
     }
 
+    // copy without the street, postal code and coordinates, for users that can see the parcel but aren't its owner
+    impl ShippingInfo {
+        pub fn redacted(&self) -> ShippingInfo {
+            ShippingInfo {
+                shipper_address: self.shipper_address.redacted(),
+                recipient_address: self.recipient_address.redacted(),
+            }
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Address {
         pub country: Option<String>,
@@ -538,6 +1045,20 @@ pub mod tracking_data_base {
         pub coordinates: Coordinates,
     }
 
+    impl Address {
+        pub fn redacted(&self) -> Address {
+            Address {
+                street: None,
+                postal_code: None,
+                coordinates: Coordinates {
+                    longitude: None,
+                    latitude: None,
+                },
+                ..self.clone()
+            }
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Coordinates {
         pub longitude: Option<f64>,
@@ -546,11 +1067,17 @@ pub mod tracking_data_base {
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Status {
-        pub status: Option<String>,
-        pub sub_status: Option<String>,
+        pub status: Option<main_status>,
+        pub sub_status: Option<sub_status>,
         pub sub_status_descr: Option<String>,
     }
 
+    impl Status {
+        pub fn is_delivered(&self) -> bool {
+            self.status.as_ref().is_some_and(main_status::is_delivered)
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct event {
         pub time_iso: Option<String>,
@@ -559,12 +1086,16 @@ pub mod tracking_data_base {
         pub description: Option<String>,
         pub location: Option<String>,
         pub stage: Option<String>,
-        pub sub_status: Option<String>,
+        pub sub_status: Option<sub_status>,
         pub address: Address,
     }
 
     // convert event to HTML (smaller) event format
     impl event {
+        pub fn instant(&self) -> Option<DateTime<Utc>> {
+            parse_instant(&self.time_utc, &self.time_iso)
+        }
+
         pub fn convert_to_HTML_event(&self) -> super::tracking_data_html_form::event {
             super::tracking_data_html_form::event {
                 description: self.description.clone(),
@@ -577,7 +1108,7 @@ pub mod tracking_data_base {
         }
     }
 
-    #[derive(Debug, Serialize, Deserialize, Clone)]
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
     pub struct time_raw {
         pub date: Option<String>,
         pub time: Option<String>,
@@ -608,6 +1139,12 @@ pub mod tracking_data_base {
         pub time_raw: time_raw,
     }
 
+    impl milestone {
+        pub fn instant(&self) -> Option<DateTime<Utc>> {
+            parse_instant(&self.time_utc, &self.time_iso)
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct misc_info {
         pub risk_factor: i32,
@@ -637,6 +1174,8 @@ pub mod tracking_data_base {
         pub latest_sync_status: Option<String>,
         pub latest_sync_time: Option<String>,
         pub events_hash: Option<i32>,
+        // left out of the database query for the list views
+        #[serde(default)]
         pub events: Vec<event>,
     }
 
@@ -669,6 +1208,7 @@ pub mod tracking_data_base {
 
 /// form used to create html objects and send to the user
 pub mod tracking_data_html_form {
+    use crate::my_structs::tracking_data_formats::status_values::sub_status;
     use crate::my_structs::tracking_data_formats::tracking_data_base;
     use serde::{Deserialize, Serialize};
 
@@ -678,8 +1218,22 @@ pub mod tracking_data_html_form {
         pub tag: Option<String>,
         pub latest_event: event,
         pub providers_data: Vec<tracking_provider_provided_events>,
+        // the events of all the providers in one list, see TrackInfo::merged_timeline
+        #[serde(default)]
+        pub timeline: Vec<timeline_event>,
+        // for the progress bar, see TrackInfo::delivery_progress
+        pub progress: Option<delivery_progress>,
         pub time_metrics: Option<tracking_data_base::time_metrics>,
+        pub shipping_info: Option<tracking_data_base::ShippingInfo>,
         pub is_user_tracked: Option<bool>,
+        // user's own details from the relation record, different for every user tracking the number
+        pub label: Option<String>,
+        pub note: Option<String>,
+        pub pinned: Option<bool>,
+        pub color: Option<String>,
+        // false for users the number was shared with
+        pub is_owner: Option<bool>,
+        pub archived: Option<bool>,
     }
 
     // case where multiple providers kms
@@ -695,10 +1249,95 @@ pub mod tracking_data_html_form {
         pub description: Option<String>,
         pub location: Option<String>,
         pub stage: Option<String>,
-        pub sub_status: Option<String>,
+        pub sub_status: Option<sub_status>,
         pub address: Option<tracking_data_base::Address>,
         pub time: Option<tracking_data_base::time_raw>,
     }
+
+    // event of the merged timeline, with every provider that reported it
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct timeline_event {
+        pub time: Option<tracking_data_base::time_raw>,
+        pub time_utc: Option<String>,
+        pub description: Option<String>,
+        pub location: Option<String>,
+        pub stage: Option<String>,
+        pub sub_status: Option<sub_status>,
+        pub address: Option<tracking_data_base::Address>,
+        pub providers: Vec<timeline_provider>,
+        // key stage of the milestone this event reached, if it did
+        pub milestone: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    pub struct timeline_provider {
+        pub provider_name: Option<String>,
+        pub provider_key: Option<i32>,
+    }
+
+    // the steps of the progress bar in order, every 17TRACK key stage on the way to the door is one of them
+    pub const PROGRESS_STAGES: [progress_stage; 5] = [
+        progress_stage::InfoReceived,
+        progress_stage::PickedUp,
+        progress_stage::InTransit,
+        progress_stage::OutForDelivery,
+        progress_stage::Delivered,
+    ];
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum progress_stage {
+        InfoReceived,
+        PickedUp,
+        // departures and arrivals
+        InTransit,
+        // also waiting at a pickup point
+        OutForDelivery,
+        Delivered,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct delivery_progress {
+        // none before 17TRACK has any info
+        pub stage: Option<progress_stage>,
+        pub completed_stages: Vec<completed_stage>,
+        pub percent: u8,
+        pub exception: bool,
+        pub delivery_failure: bool,
+        pub expired: bool,
+        pub returning: bool,
+        pub returned: bool,
+    }
+
+    // the time is when the milestone was reached, none when the carrier skipped its scan
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct completed_stage {
+        pub stage: progress_stage,
+        pub time_utc: Option<String>,
+    }
+}
+
+/// small form for the list views, just what's needed to draw one row of the user's list
+pub mod tracking_data_summary_form {
+    use crate::my_structs::tracking_data_formats::status_values::{main_status, sub_status};
+    use crate::my_structs::tracking_data_formats::tracking_data_base;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct tracking_data_summary {
+        pub tracking_number: String,
+        pub tag: Option<String>,
+        pub status: Option<main_status>,
+        pub sub_status: Option<sub_status>,
+        pub latest_event_description: Option<String>,
+        pub latest_event_time: Option<tracking_data_base::time_raw>,
+        pub carrier_name: Option<String>,
+        pub estimated_delivery_date: Option<tracking_data_base::delivery_estimate>,
+        pub is_user_tracked: Option<bool>,
+        // user's own details from the relation record
+        pub label: Option<String>,
+        pub pinned: Option<bool>,
+        pub color: Option<String>,
+    }
 }
 
 /// Custom format for storing in the database as a single tracking info
@@ -708,6 +1347,12 @@ pub mod tracking_data_database_form {
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct TrackingData_DBF {
         pub data: PackageData,
+        // when the tracking data was last saved, for the delta sync of the clients
+        #[serde(default)]
+        pub updated_at: Option<mongodb::bson::DateTime>,
+        // made by the user with their own events instead of by 17TRACK (see manual_shipments.rs), the API never hears of it
+        #[serde(default)]
+        pub manual: bool,
     }
 
     // convert to HTML format
@@ -725,8 +1370,43 @@ pub mod tracking_data_database_form {
                     .iter()
                     .map(|provider| provider.convert_to_HTML_provider())
                     .collect(),
+                timeline: self.data.track_info.merged_timeline(),
+                progress: Some(self.data.track_info.delivery_progress()),
                 time_metrics: Some(self.data.track_info.time_metrics.clone()),
+                shipping_info: Some(self.data.track_info.shipping_info.clone()),
                 is_user_tracked: None,
+                label: None,
+                note: None,
+                pinned: None,
+                color: None,
+                is_owner: None,
+                archived: None,
+            }
+        }
+
+        pub fn convert_to_summary_form(
+            &self,
+        ) -> super::tracking_data_summary_form::tracking_data_summary {
+            let track_info = &self.data.track_info;
+            super::tracking_data_summary_form::tracking_data_summary {
+                tracking_number: self.data.number.clone(),
+                tag: self.data.tag.clone(),
+                status: track_info.latest_status.status.clone(),
+                sub_status: track_info.latest_status.sub_status.clone(),
+                latest_event_description: track_info.latest_event.description.clone(),
+                latest_event_time: Some(track_info.latest_event.time_raw.clone()),
+                carrier_name: track_info
+                    .tracking
+                    .providers
+                    .first()
+                    .and_then(|provider| provider.provider.name.clone()),
+                estimated_delivery_date: Some(
+                    track_info.time_metrics.estimated_delivery_date.clone(),
+                ),
+                is_user_tracked: None,
+                label: None,
+                pinned: None,
+                color: None,
             }
         }
     }
@@ -735,8 +1415,789 @@ pub mod tracking_data_database_form {
     pub struct PackageData {
         pub number: String,
         pub carrier: i32,
-        pub param: Option<()>,
+        pub param: Option<String>,
         pub tag: Option<String>,
         pub track_info: super::tracking_data_base::TrackInfo,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::status_values::{main_status, sub_status, tracking_status};
+    use super::tracking_data_get_info::TrackingResponse as tracking_data_get_info;
+    use super::tracking_data_html_form::{
+        delivery_progress, progress_stage, timeline_event, timeline_provider,
+    };
+    use super::tracking_data_webhook_update::TrackingResponse as webhook_update;
+    use serde_json::{json, Value};
+
+    const CHINA_POST: i32 = 3011;
+    const POSTNL: i32 = 14041;
+
+    fn sample_event(
+        time_iso: Option<&str>,
+        time_utc: Option<&str>,
+        description: &str,
+        location: Option<&str>,
+        stage: Option<&str>,
+    ) -> Value {
+        json!({
+            "time_iso": time_iso,
+            "time_utc": time_utc,
+            "time_raw": {
+                "date": time_iso.map(|time| &time[..10]),
+                "time": time_iso.map(|time| &time[11..19]),
+                "timezone": time_iso.map(|time| &time[19..]),
+            },
+            "description": description,
+            "location": location,
+            "stage": stage,
+            "sub_status": null,
+            "address": {
+                "country": null,
+                "state": null,
+                "city": null,
+                "street": null,
+                "postal_code": null,
+                "coordinates": {"longitude": null, "latitude": null},
+            },
+        })
+    }
+
+    fn sample_provider(key: i32, name: &str, events: Vec<Value>) -> Value {
+        json!({
+            "provider": {"key": key, "name": name, "alias": name, "tel": null, "homepage": null},
+            "provider_lang": null,
+            "service_type": null,
+            "latest_sync_status": "Success",
+            "latest_sync_time": "2024-03-09T10:35:00Z",
+            "events_hash": 1,
+            "events": events,
+        })
+    }
+
+    fn sample_milestone(key_stage: &str, time_utc: Option<&str>) -> Value {
+        json!({
+            "key_stage": key_stage,
+            "time_iso": null,
+            "time_utc": time_utc,
+            "time_raw": {"date": null, "time": null, "timezone": null},
+        })
+    }
+
+    // the number and carrier of the register example at the top of the file, with the gettrackinfo body 17TRACK answers
+    fn sample_gettrackinfo(providers: Vec<Value>, milestones: Vec<Value>) -> Value {
+        let empty_address = json!({
+            "country": null,
+            "state": null,
+            "city": null,
+            "street": null,
+            "postal_code": null,
+            "coordinates": {"longitude": null, "latitude": null},
+        });
+        json!({
+            "code": 0,
+            "data": {
+                "accepted": [{
+                    "number": "RR123456789CN",
+                    "carrier": CHINA_POST,
+                    "param": null,
+                    "tag": "MyOrderID",
+                    "track_info": {
+                        "lastGatherTime": "2024-03-09T10:35:00Z",
+                        "shipping_info": {
+                            "shipper_address": empty_address,
+                            "recipient_address": empty_address,
+                        },
+                        "latest_status": {
+                            "status": "Delivered",
+                            "sub_status": "Delivered_Other",
+                            "sub_status_descr": null,
+                        },
+                        "latest_event": sample_event(None, None, "", None, None),
+                        "time_metrics": {
+                            "days_after_order": 8,
+                            "days_of_transit": 8,
+                            "days_of_transit_done": 8,
+                            "days_after_last_update": 0,
+                            "estimated_delivery_date": {"source": null, "from": null, "to": null},
+                        },
+                        "milestone": milestones,
+                        "misc_info": {
+                            "risk_factor": 0,
+                            "service_type": null,
+                            "weight_raw": null,
+                            "weight_kg": null,
+                            "pieces": null,
+                            "dimensions": null,
+                            "customer_number": null,
+                            "reference_number": null,
+                            "local_number": null,
+                            "local_provider": null,
+                            "local_key": null,
+                        },
+                        "tracking": {"providers_hash": 1, "providers": providers},
+                    },
+                }],
+                "rejected": [],
+            },
+        })
+    }
+
+    // a parcel from China to the Netherlands, the destination carrier repeats two of the scans of the origin one in its
+    // own time zone, wording and letter case, events newest first like 17TRACK sends them
+    fn cross_border_payload() -> Value {
+        let china_post = sample_provider(
+            CHINA_POST,
+            "China Post",
+            vec![
+                sample_event(
+                    Some("2024-03-05T09:10:00+08:00"),
+                    Some("2024-03-05T01:10:00Z"),
+                    "Departed from the office of exchange",
+                    Some("GUANGZHOU"),
+                    Some("Departure"),
+                ),
+                sample_event(
+                    Some("2024-03-02T14:00:00+08:00"),
+                    Some("2024-03-02T06:00:00Z"),
+                    "Posting/Collection",
+                    Some("SHENZHEN"),
+                    Some("PickedUp"),
+                ),
+                sample_event(
+                    Some("2024-03-01T10:00:00+08:00"),
+                    Some("2024-03-01T02:00:00Z"),
+                    "Electronic information received",
+                    None,
+                    Some("InfoReceived"),
+                ),
+            ],
+        );
+        let postnl = sample_provider(
+            POSTNL,
+            "PostNL",
+            vec![
+                sample_event(
+                    Some("2024-03-09T11:30:00+01:00"),
+                    Some("2024-03-09T10:30:00Z"),
+                    "Delivered",
+                    Some("Amsterdam"),
+                    Some("Delivered"),
+                ),
+                sample_event(
+                    Some("2024-03-08T07:00:00+01:00"),
+                    Some("2024-03-08T06:00:00Z"),
+                    "Out for delivery",
+                    Some("Amsterdam"),
+                    Some("OutForDelivery"),
+                ),
+                sample_event(
+                    Some("2024-03-05T02:10:00+01:00"),
+                    Some("2024-03-05T01:10:00Z"),
+                    "departed from the office of exchange.",
+                    Some("Guangzhou"),
+                    None,
+                ),
+                sample_event(
+                    Some("2024-03-02T07:00:00+01:00"),
+                    Some("2024-03-02T06:00:00Z"),
+                    "Posting / Collection",
+                    Some("Shenzhen"),
+                    Some("PickedUp"),
+                ),
+            ],
+        );
+        let milestones = vec![
+            sample_milestone("InfoReceived", Some("2024-03-01T02:00:00Z")),
+            sample_milestone("PickedUp", Some("2024-03-02T06:00:00Z")),
+            sample_milestone("Departure", Some("2024-03-05T01:10:00Z")),
+            sample_milestone("Arrival", None),
+            sample_milestone("OutForDelivery", Some("2024-03-08T06:00:00Z")),
+            sample_milestone("Delivered", Some("2024-03-09T10:30:00Z")),
+        ];
+        sample_gettrackinfo(vec![china_post, postnl], milestones)
+    }
+
+    fn timeline_of(payload: Value) -> Vec<timeline_event> {
+        let response: tracking_data_get_info =
+            serde_json::from_value(payload).expect("sample payload doesn't deserialize");
+        response
+            .convert_to_tracking_data_dbf()
+            .convert_to_HTML_form()
+            .timeline
+    }
+
+    fn progress_of(mut payload: Value, status: &str, sub_status: &str) -> delivery_progress {
+        payload["data"]["accepted"][0]["track_info"]["latest_status"] =
+            json!({"status": status, "sub_status": sub_status, "sub_status_descr": null});
+        let response: tracking_data_get_info =
+            serde_json::from_value(payload).expect("sample payload doesn't deserialize");
+        response
+            .convert_to_tracking_data_dbf()
+            .convert_to_HTML_form()
+            .progress
+            .expect("the HTML form always has the progress")
+    }
+
+    fn descriptions(timeline: &[timeline_event]) -> Vec<&str> {
+        timeline
+            .iter()
+            .map(|event| event.description.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    fn provider(key: i32, name: &str) -> timeline_provider {
+        timeline_provider {
+            provider_name: Some(name.to_string()),
+            provider_key: Some(key),
+        }
+    }
+
+    #[test]
+    fn repeated_scans_are_merged_once_with_both_providers() {
+        let timeline = timeline_of(cross_border_payload());
+
+        assert_eq!(timeline.len(), 5);
+        let departure = &timeline[2];
+        assert_eq!(
+            departure.description.as_deref(),
+            Some("Departed from the office of exchange")
+        );
+        assert_eq!(
+            departure.providers,
+            vec![
+                provider(CHINA_POST, "China Post"),
+                provider(POSTNL, "PostNL")
+            ]
+        );
+        assert_eq!(
+            timeline[4].providers,
+            vec![provider(POSTNL, "PostNL")],
+            "only the destination carrier reported the delivery"
+        );
+    }
+
+    #[test]
+    fn timeline_is_oldest_first_across_time_zones() {
+        let timeline = timeline_of(cross_border_payload());
+
+        assert_eq!(
+            descriptions(&timeline),
+            vec![
+                "Electronic information received",
+                "Posting/Collection",
+                "Departed from the office of exchange",
+                "Out for delivery",
+                "Delivered",
+            ]
+        );
+        assert_eq!(
+            timeline[2].time_utc.as_deref(),
+            Some("2024-03-05T01:10:00Z")
+        );
+        // the local time of the provider that reported it first
+        let time = timeline[2].time.as_ref().unwrap();
+        assert_eq!(time.time.as_deref(), Some("09:10:00"));
+        assert_eq!(time.timezone.as_deref(), Some("+08:00"));
+    }
+
+    #[test]
+    fn events_are_tagged_with_their_stage() {
+        let timeline = timeline_of(cross_border_payload());
+
+        let stages: Vec<Option<&str>> = timeline
+            .iter()
+            .map(|event| event.stage.as_deref())
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                Some("InfoReceived"),
+                Some("PickedUp"),
+                Some("Departure"),
+                Some("OutForDelivery"),
+                Some("Delivered"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stage_missing_from_the_first_provider_comes_from_the_duplicate() {
+        let payload = sample_gettrackinfo(
+            vec![
+                sample_provider(
+                    POSTNL,
+                    "PostNL",
+                    vec![sample_event(
+                        Some("2024-03-05T02:10:00+01:00"),
+                        Some("2024-03-05T01:10:00Z"),
+                        "Departed from the office of exchange",
+                        Some("Guangzhou"),
+                        None,
+                    )],
+                ),
+                sample_provider(
+                    CHINA_POST,
+                    "China Post",
+                    vec![sample_event(
+                        Some("2024-03-05T09:10:00+08:00"),
+                        Some("2024-03-05T01:10:00Z"),
+                        "DEPARTED FROM THE OFFICE OF EXCHANGE",
+                        Some("GUANGZHOU"),
+                        Some("Departure"),
+                    )],
+                ),
+            ],
+            vec![],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].stage.as_deref(), Some("Departure"));
+        // the rest is what the first provider said
+        assert_eq!(timeline[0].location.as_deref(), Some("Guangzhou"));
+        assert_eq!(timeline[0].providers[0], provider(POSTNL, "PostNL"));
+    }
+
+    #[test]
+    fn scans_differing_in_time_location_or_description_are_kept_apart() {
+        let scan = |time_utc: &str, description: &str, location: &str| {
+            sample_event(
+                Some(&format!("{}+00:00", &time_utc[..19])),
+                Some(time_utc),
+                description,
+                Some(location),
+                None,
+            )
+        };
+        let payload = sample_gettrackinfo(
+            vec![
+                sample_provider(
+                    CHINA_POST,
+                    "China Post",
+                    vec![scan(
+                        "2024-03-05T01:10:00Z",
+                        "Arrived at facility",
+                        "Guangzhou",
+                    )],
+                ),
+                sample_provider(
+                    POSTNL,
+                    "PostNL",
+                    vec![
+                        scan("2024-03-05T01:11:00Z", "Arrived at facility", "Guangzhou"),
+                        scan("2024-03-05T01:10:00Z", "Arrived at facility", "Shenzhen"),
+                        scan("2024-03-05T01:10:00Z", "Left facility", "Guangzhou"),
+                    ],
+                ),
+            ],
+            vec![],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(timeline.len(), 4);
+        assert!(timeline.iter().all(|event| event.providers.len() == 1));
+    }
+
+    #[test]
+    fn same_scan_twice_from_one_provider_is_listed_once() {
+        let event = sample_event(
+            Some("2024-03-02T14:00:00+08:00"),
+            Some("2024-03-02T06:00:00Z"),
+            "Posting/Collection",
+            Some("SHENZHEN"),
+            Some("PickedUp"),
+        );
+        let payload = sample_gettrackinfo(
+            vec![sample_provider(
+                CHINA_POST,
+                "China Post",
+                vec![event.clone(), event],
+            )],
+            vec![],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(
+            timeline[0].providers,
+            vec![provider(CHINA_POST, "China Post")]
+        );
+    }
+
+    #[test]
+    fn milestones_are_marked_on_the_event_that_reached_them() {
+        let timeline = timeline_of(cross_border_payload());
+
+        let milestones: Vec<Option<&str>> = timeline
+            .iter()
+            .map(|event| event.milestone.as_deref())
+            .collect();
+        assert_eq!(
+            milestones,
+            vec![
+                Some("InfoReceived"),
+                Some("PickedUp"),
+                Some("Departure"),
+                Some("OutForDelivery"),
+                Some("Delivered"),
+            ]
+        );
+    }
+
+    #[test]
+    fn milestone_goes_to_the_event_with_its_stage_when_more_share_the_time() {
+        let payload = sample_gettrackinfo(
+            vec![sample_provider(
+                POSTNL,
+                "PostNL",
+                vec![
+                    sample_event(
+                        Some("2024-03-08T07:00:00+01:00"),
+                        Some("2024-03-08T06:00:00Z"),
+                        "Sorted at the depot",
+                        Some("Amsterdam"),
+                        None,
+                    ),
+                    sample_event(
+                        Some("2024-03-08T07:00:00+01:00"),
+                        Some("2024-03-08T06:00:00Z"),
+                        "Out for delivery",
+                        Some("Amsterdam"),
+                        Some("OutForDelivery"),
+                    ),
+                ],
+            )],
+            vec![
+                sample_milestone("OutForDelivery", Some("2024-03-08T06:00:00Z")),
+                // no event at that time, nothing to mark
+                sample_milestone("Delivered", Some("2024-03-09T10:30:00Z")),
+            ],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(timeline[0].milestone, None);
+        assert_eq!(timeline[1].milestone.as_deref(), Some("OutForDelivery"));
+    }
+
+    #[test]
+    fn milestone_without_a_stage_match_goes_to_the_event_at_its_time() {
+        let payload = sample_gettrackinfo(
+            vec![sample_provider(
+                POSTNL,
+                "PostNL",
+                vec![sample_event(
+                    Some("2024-03-06T12:00:00+01:00"),
+                    Some("2024-03-06T11:00:00Z"),
+                    "Arrived in the Netherlands",
+                    Some("Schiphol"),
+                    None,
+                )],
+            )],
+            vec![sample_milestone("Arrival", Some("2024-03-06T11:00:00Z"))],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(timeline[0].milestone.as_deref(), Some("Arrival"));
+    }
+
+    #[test]
+    fn time_iso_is_used_when_time_utc_is_missing() {
+        let payload = sample_gettrackinfo(
+            vec![
+                sample_provider(
+                    CHINA_POST,
+                    "China Post",
+                    vec![sample_event(
+                        Some("2024-03-05T09:10:00+08:00"),
+                        None,
+                        "Departed from the office of exchange",
+                        Some("Guangzhou"),
+                        None,
+                    )],
+                ),
+                sample_provider(
+                    POSTNL,
+                    "PostNL",
+                    vec![
+                        sample_event(
+                            Some("2024-03-05T02:10:00+01:00"),
+                            Some("2024-03-05T01:10:00Z"),
+                            "Departed from the office of exchange",
+                            Some("Guangzhou"),
+                            None,
+                        ),
+                        sample_event(
+                            Some("2024-03-04T12:00:00+01:00"),
+                            None,
+                            "Handed over to the airline",
+                            Some("Guangzhou"),
+                            None,
+                        ),
+                    ],
+                ),
+            ],
+            vec![],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(
+            descriptions(&timeline),
+            vec![
+                "Handed over to the airline",
+                "Departed from the office of exchange",
+            ]
+        );
+        assert_eq!(timeline[1].providers.len(), 2);
+        assert_eq!(
+            timeline[1].time_utc.as_deref(),
+            Some("2024-03-05T01:10:00Z")
+        );
+    }
+
+    #[test]
+    fn events_without_a_time_go_last_in_their_order() {
+        let payload = sample_gettrackinfo(
+            vec![sample_provider(
+                CHINA_POST,
+                "China Post",
+                vec![
+                    sample_event(None, None, "Customs clearance", None, None),
+                    sample_event(
+                        Some("2024-03-02T14:00:00+08:00"),
+                        Some("2024-03-02T06:00:00Z"),
+                        "Posting/Collection",
+                        Some("SHENZHEN"),
+                        Some("PickedUp"),
+                    ),
+                    sample_event(None, None, "Export declaration", None, None),
+                    sample_event(None, None, "customs clearance.", None, None),
+                ],
+            )],
+            vec![],
+        );
+        let timeline = timeline_of(payload);
+
+        assert_eq!(
+            descriptions(&timeline),
+            vec![
+                "Posting/Collection",
+                "Customs clearance",
+                "Export declaration"
+            ]
+        );
+        assert_eq!(timeline[1].time_utc, None);
+    }
+
+    #[test]
+    fn no_providers_means_an_empty_timeline() {
+        let timeline = timeline_of(sample_gettrackinfo(
+            vec![],
+            vec![sample_milestone(
+                "InfoReceived",
+                Some("2024-03-01T02:00:00Z"),
+            )],
+        ));
+
+        assert!(timeline.is_empty());
+    }
+
+    #[test]
+    fn webhook_update_gives_the_same_timeline() {
+        let payload = cross_border_payload();
+        let webhook_payload = json!({
+            "event": "TRACKING_UPDATED",
+            "data": payload["data"]["accepted"][0],
+        });
+        let update: webhook_update =
+            serde_json::from_value(webhook_payload).expect("webhook payload doesn't deserialize");
+
+        let from_webhook = update
+            .convert_to_tracking_data_dbf()
+            .expect("tracking update should convert")
+            .convert_to_HTML_form()
+            .timeline;
+        let from_gettrackinfo = timeline_of(payload);
+
+        assert_eq!(
+            serde_json::to_value(from_webhook).unwrap(),
+            serde_json::to_value(from_gettrackinfo).unwrap()
+        );
+    }
+
+    #[test]
+    fn delivered_parcel_has_every_stage_with_its_time() {
+        let progress = progress_of(cross_border_payload(), "Delivered", "Delivered_Other");
+
+        assert_eq!(progress.stage, Some(progress_stage::Delivered));
+        assert_eq!(progress.percent, 100);
+        let completed: Vec<(progress_stage, Option<&str>)> = progress
+            .completed_stages
+            .iter()
+            .map(|completed| (completed.stage, completed.time_utc.as_deref()))
+            .collect();
+        assert_eq!(
+            completed,
+            vec![
+                (progress_stage::InfoReceived, Some("2024-03-01T02:00:00Z")),
+                (progress_stage::PickedUp, Some("2024-03-02T06:00:00Z")),
+                (progress_stage::InTransit, Some("2024-03-05T01:10:00Z")),
+                (progress_stage::OutForDelivery, Some("2024-03-08T06:00:00Z")),
+                (progress_stage::Delivered, Some("2024-03-09T10:30:00Z")),
+            ]
+        );
+        assert!(!progress.exception && !progress.delivery_failure && !progress.returned);
+    }
+
+    #[test]
+    fn status_ahead_of_the_milestones_moves_the_stage_without_a_time() {
+        let payload = sample_gettrackinfo(
+            vec![],
+            vec![
+                sample_milestone("InfoReceived", Some("2024-03-01T02:00:00Z")),
+                sample_milestone("PickedUp", None),
+                sample_milestone("Arrival", Some("2024-03-06T11:00:00Z")),
+                sample_milestone("Departure", Some("2024-03-05T01:10:00Z")),
+                sample_milestone("OutForDelivery", None),
+            ],
+        );
+        let progress = progress_of(payload, "OutForDelivery", "OutForDelivery_Other");
+
+        assert_eq!(progress.stage, Some(progress_stage::OutForDelivery));
+        assert_eq!(progress.percent, 75);
+        let completed: Vec<(progress_stage, Option<&str>)> = progress
+            .completed_stages
+            .iter()
+            .map(|completed| (completed.stage, completed.time_utc.as_deref()))
+            .collect();
+        assert_eq!(
+            completed,
+            vec![
+                (progress_stage::InfoReceived, Some("2024-03-01T02:00:00Z")),
+                // skipped by the carrier
+                (progress_stage::PickedUp, None),
+                // the departure came before the arrival
+                (progress_stage::InTransit, Some("2024-03-05T01:10:00Z")),
+                (progress_stage::OutForDelivery, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn picked_up_sub_status_is_its_own_stage() {
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], vec![]),
+            "InTransit",
+            "InTransit_PickedUp",
+        );
+
+        assert_eq!(progress.stage, Some(progress_stage::PickedUp));
+        assert_eq!(progress.percent, 25);
+        assert_eq!(progress.completed_stages.len(), 2);
+    }
+
+    #[test]
+    fn parcel_without_info_has_no_stage() {
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], vec![]),
+            "NotFound",
+            "NotFound_Other",
+        );
+
+        assert_eq!(progress.stage, None);
+        assert_eq!(progress.percent, 0);
+        assert!(progress.completed_stages.is_empty());
+    }
+
+    #[test]
+    fn exceptions_keep_the_stage_reached_and_set_their_flags() {
+        let milestones = || {
+            vec![
+                sample_milestone("InfoReceived", Some("2024-03-01T02:00:00Z")),
+                sample_milestone("Departure", Some("2024-03-05T01:10:00Z")),
+            ]
+        };
+
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], milestones()),
+            "Exception",
+            "Exception_Other",
+        );
+        assert_eq!(progress.stage, Some(progress_stage::InTransit));
+        assert!(progress.exception);
+
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], milestones()),
+            "DeliveryFailure",
+            "DeliveryFailure_NoBody",
+        );
+        assert_eq!(progress.stage, Some(progress_stage::OutForDelivery));
+        assert!(progress.delivery_failure && !progress.exception);
+
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], milestones()),
+            "Expired",
+            "Expired_Other",
+        );
+        assert_eq!(progress.stage, Some(progress_stage::InTransit));
+        assert!(progress.expired);
+    }
+
+    #[test]
+    fn returns_are_flagged_and_returned_wins_over_returning() {
+        let mut milestones = vec![
+            sample_milestone("InfoReceived", Some("2024-03-01T02:00:00Z")),
+            sample_milestone("Returning", Some("2024-03-10T08:00:00Z")),
+        ];
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], milestones.clone()),
+            "Exception",
+            "Exception_Returning",
+        );
+        assert!(progress.returning && !progress.returned);
+
+        milestones.push(sample_milestone("Returned", Some("2024-03-20T08:00:00Z")));
+        let progress = progress_of(
+            sample_gettrackinfo(vec![], milestones),
+            "Exception",
+            "Exception_Other",
+        );
+        assert!(progress.returned && !progress.returning);
+        assert_eq!(progress.stage, Some(progress_stage::InfoReceived));
+    }
+
+    #[test]
+    fn status_values_read_and_write_the_strings_17track_uses() {
+        let status: main_status = serde_json::from_value(json!("DeliveryFailure")).unwrap();
+        assert_eq!(status, main_status::DeliveryFailure);
+        let sub: sub_status = serde_json::from_value(json!("Exception_Returning")).unwrap();
+        assert!(sub.is_returning());
+        assert_eq!(
+            serde_json::to_value(&sub).unwrap(),
+            json!("Exception_Returning")
+        );
+        let tracking: tracking_status = serde_json::from_value(json!("Stopped")).unwrap();
+        assert!(tracking.is_stopped());
+    }
+
+    #[test]
+    fn status_values_17track_adds_later_are_kept_as_they_came() {
+        let status: main_status = serde_json::from_value(json!("Lost")).unwrap();
+        assert_eq!(status, main_status::Unknown("Lost".to_string()));
+        assert!(!status.is_delivered());
+        assert_eq!(serde_json::to_value(&status).unwrap(), json!("Lost"));
+
+        // the rest of the tracking data still reads
+        let mut payload = cross_border_payload();
+        payload["data"]["accepted"][0]["track_info"]["latest_status"]["sub_status"] =
+            json!("InTransit_Teleported");
+        let response: tracking_data_get_info = serde_json::from_value(payload).unwrap();
+        let tracking_data = response.convert_to_tracking_data_dbf();
+        assert_eq!(
+            tracking_data.data.track_info.latest_status.sub_status,
+            Some(sub_status::Unknown("InTransit_Teleported".to_string()))
+        );
+        assert!(tracking_data.data.track_info.latest_status.is_delivered());
+    }
+}
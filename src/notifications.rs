@@ -17,6 +17,14 @@ pub struct notification_service {
     mini_app_name: String,
 }
 
+/// high priority notifications are the alerts (see tracking_alerts.rs), they're marked with a warning sign so they stand
+/// out from the routine updates in the chat list and the notification banner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum notification_priority {
+    Normal,
+    High,
+}
+
 #[derive(Error, Debug)]
 pub enum notification_service_error {
     #[error("telegram API error")]
@@ -31,6 +39,13 @@ pub enum notification_service_error {
     Functions
 */
 
+/// escape text that goes into a message sent with the HTML parse mode, labels and event descriptions come from users and carriers
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 impl notification_service {
     /// initializer
     pub fn new(bot_token: String, mini_app_name: &str) -> Result<Self, notification_service_error> {
@@ -59,17 +74,33 @@ impl notification_service {
         ]]))
     }
 
-    /// notification that opens the mini app
+    /// build the deep link that opens the mini app with the parameters
+    /// telegram rejects all parameters other than the start parameter so in order to send the other parameters they
+    /// need to be all put in a json and encoded and put as a string as the start parameter
+    async fn build_deep_link(
+        &self,
+        parameter_map: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<String, notification_service_error> {
+        let startapp_value = base64::engine::general_purpose::URL_SAFE
+            .encode(serde_json::Value::Object(parameter_map).to_string());
+
+        // deep link to open the app from the notification message button, includes the startparam
+        Ok(format!(
+            "https://t.me/{}/{}?startapp={}",
+            self.bot.get_me().await?.username(),
+            self.mini_app_name,
+            startapp_value
+        ))
+    }
+
+    /// notification that opens the mini app, the chat ID is the user ID for private chats or the ID of a group chat
     pub async fn send_ma_notification(
         &self,
-        user_id: i64,
+        chat_id: i64,
         message: &str,
         tracking_number_that_was_updated: &str,
+        priority: notification_priority,
     ) -> Result<(), notification_service_error> {
-        // build the deep link with the struct parameters
-        // telegram rejects all parameters other than the start parameter so in order to send the other parameters they
-        // need to be all put in a json and encoded and put as a string as the start parameter
-
         // prepare the startparam
         let mut parameter_map = serde_json::Map::new();
         parameter_map.insert(
@@ -80,29 +111,83 @@ impl notification_service {
             "package_update".to_string(),
             serde_json::json!(tracking_number_that_was_updated),
         );
-        let startapp_value = base64::engine::general_purpose::URL_SAFE
-            .encode(serde_json::Value::Object(parameter_map).to_string());
-
-        // deep link to open the app from the notification message button, includes the startparam
-        let deep_link = format!(
-            "https://t.me/{}/{}?startapp={}",
-            self.bot.get_me().await?.username(),
-            self.mini_app_name,
-            startapp_value
-        );
+        let deep_link = self.build_deep_link(parameter_map).await?;
 
         // println!("{}", deep_link);
 
+        let message = match priority {
+            notification_priority::High => format!("\u{26A0}\u{FE0F} {}", message),
+            notification_priority::Normal => message.to_string(),
+        };
+
+        let keyboard = self.create_inline_keyboard(&deep_link)?;
+        match self
+            .bot
+            .send_message(ChatId(chat_id), message)
+            .reply_markup(keyboard)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(notification_service_error::TelegramError(e)),
+        }
+    }
+
+    /// message to the owner of a tracking number with the invite link they can forward to the person they want to share it
+    /// with, the link opens the mini app with the signed share token
+    pub async fn send_share_invite(
+        &self,
+        owner_user_id: i64,
+        tracking_number: &str,
+        share_token: &str,
+    ) -> Result<String, notification_service_error> {
+        // prepare the startparam
+        let mut parameter_map = serde_json::Map::new();
+        parameter_map.insert("share_token".to_string(), serde_json::json!(share_token));
+        let deep_link = self.build_deep_link(parameter_map).await?;
+
+        let message = format!(
+            "Forward this message to the person you want to share the tracking of {} with.\n{}",
+            escape_html(tracking_number),
+            deep_link
+        );
         let keyboard = self.create_inline_keyboard(&deep_link)?;
         match self
             .bot
-            .send_message(ChatId(user_id), message)
+            .send_message(ChatId(owner_user_id), message)
             .reply_markup(keyboard)
             .parse_mode(teloxide::types::ParseMode::Html)
             .await
+        {
+            Ok(_) => Ok(deep_link),
+            Err(e) => Err(notification_service_error::TelegramError(e)),
+        }
+    }
+
+    /// plain message without the mini app button, for replying to the bot commands
+    pub async fn send_plain_message(
+        &self,
+        chat_id: i64,
+        message: &str,
+    ) -> Result<(), notification_service_error> {
+        match self
+            .bot
+            .send_message(ChatId(chat_id), message)
+            .parse_mode(teloxide::types::ParseMode::Html)
+            .await
         {
             Ok(_) => Ok(()),
             Err(e) => Err(notification_service_error::TelegramError(e)),
         }
     }
+
+    /// check if the user is the creator or an administrator of the chat
+    pub async fn is_chat_admin(
+        &self,
+        chat_id: i64,
+        user_id: UserId,
+    ) -> Result<bool, notification_service_error> {
+        let member = self.bot.get_chat_member(ChatId(chat_id), user_id).await?;
+        Ok(member.is_privileged())
+    }
 }
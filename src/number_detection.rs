@@ -0,0 +1,479 @@
+/*
+    Cargo stuff
+*/
+
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// 17TRACK carrier keys of the carriers the formats belong to, the same keys as data/carriers.json
+const CARRIER_CHINA_POST: i32 = 3011;
+const CARRIER_CHINA_EMS: i32 = 3013;
+pub const CARRIER_USPS: i32 = 21051;
+pub const CARRIER_DHL_EXPRESS: i32 = 100001;
+pub const CARRIER_UPS: i32 = 100002;
+pub const CARRIER_FEDEX: i32 = 100003;
+pub const CARRIER_YUNEXPRESS: i32 = 190008;
+pub const CARRIER_CAINIAO: i32 = 190271;
+pub const CARRIER_SF_EXPRESS: i32 = 100012;
+
+// national posts by the country code at the end of S10 numbers
+const POSTS_BY_COUNTRY: [(&str, i32); 21] = [
+    ("CN", CARRIER_CHINA_POST),
+    ("US", CARRIER_USPS),
+    ("GB", 11031),
+    ("DE", 7041),
+    ("FR", 6051),
+    ("NL", 14041),
+    ("CA", 3041),
+    ("AU", 1151),
+    ("JP", 10021),
+    ("KR", 11051),
+    ("HK", 8011),
+    ("SG", 19241),
+    ("IT", 9071),
+    ("ES", 19081),
+    ("PL", 16041),
+    ("RU", 18031),
+    ("BR", 2151),
+    ("IN", 9011),
+    ("CH", 20101),
+    ("AT", 1031),
+    ("BE", 2061),
+];
+
+// tracking numbers shorter or longer than this don't exist
+const MIN_NUMBER_LENGTH: usize = 8;
+pub const MAX_NUMBER_LENGTH: usize = 40;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// formats of tracking numbers that can be recognised without asking the API
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum number_format {
+    // international postal items, 2 letters + 8 digits + check digit + country code
+    UpuS10,
+    Ups1Z,
+    FedExExpress,
+    FedExGround,
+    DhlExpress,
+    // USPS Intelligent Mail package barcode
+    UspsImpb,
+    YunExpress,
+    Cainiao,
+    SfExpress,
+}
+
+/// carrier that could have made the number, the lower the rank the more likely
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct carrier_candidate {
+    pub carrier: i32,
+    pub format: number_format,
+    pub rank: u8,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum number_detection_error {
+    #[error("tracking numbers are {MIN_NUMBER_LENGTH} to {MAX_NUMBER_LENGTH} characters long")]
+    Length,
+    #[error("tracking numbers only have letters and digits")]
+    Characters,
+    #[error("the check digit of the {0:?} number is wrong")]
+    CheckDigit(number_format),
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Normalise a tracking number the way it's stored and looked up, full-width characters (typed with CJK keyboards) become
+/// their ASCII versions, whitespace and dashes are dropped and letters are uppercase, so "rr 123-456-785 gb" and
+/// "ＲＲ１２３４５６７８５ＧＢ" are both "RR123456785GB"
+pub fn normalize_tracking_number(tracking_number: &str) -> String {
+    tracking_number
+        .chars()
+        .map(|c| match c {
+            // the full-width forms of ASCII are 0xFEE0 above it
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            // ideographic space
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .filter(|c| !c.is_whitespace() && !is_dash(*c))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// hyphen-minus and the unicode hyphens and dashes people paste from documents
+fn is_dash(c: char) -> bool {
+    matches!(
+        c,
+        '-' | '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE58}' | '\u{FE63}'
+    )
+}
+
+/// For #[serde(deserialize_with)] on the tracking numbers the clients send, so every handler gets the normalised number
+pub fn deserialize_tracking_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let tracking_number = String::deserialize(deserializer)?;
+    Ok(normalize_tracking_number(&tracking_number))
+}
+
+/// Check the tracking number and find the carriers it could belong to, sorted by rank, an empty list means the format isn't
+/// known and only the API can tell, the error means the number can't be real and shouldn't be sent to the API
+pub fn detect_carriers(
+    tracking_number: &str,
+) -> Result<Vec<carrier_candidate>, number_detection_error> {
+    let number = tracking_number.as_bytes();
+    if number.len() < MIN_NUMBER_LENGTH || number.len() > MAX_NUMBER_LENGTH {
+        return Err(number_detection_error::Length);
+    }
+    if !number.iter().all(u8::is_ascii_alphanumeric) {
+        return Err(number_detection_error::Characters);
+    }
+    let number = tracking_number.to_ascii_uppercase();
+
+    let mut candidates = Vec::new();
+
+    // formats with a check digit, a wrong one means a typo
+    if let Some(country) = upu_s10_country(&number)? {
+        let post = POSTS_BY_COUNTRY
+            .iter()
+            .find(|(code, _)| *code == country)
+            .map(|(_, carrier)| *carrier);
+        // EMS items start with E
+        if country == "CN" && number.starts_with('E') {
+            candidates.push(candidate(CARRIER_CHINA_EMS, number_format::UpuS10, 0));
+        }
+        if let Some(post) = post {
+            candidates.push(candidate(post, number_format::UpuS10, 0));
+        }
+    }
+    if is_ups_1z(&number)? {
+        candidates.push(candidate(CARRIER_UPS, number_format::Ups1Z, 0));
+    }
+    //
+
+    // formats made of digits only, they overlap so the check digit only ranks them
+    if number.bytes().all(|c| c.is_ascii_digit()) {
+        let digits: Vec<u32> = number.bytes().map(|c| (c - b'0') as u32).collect();
+        match digits.len() {
+            10 => candidates.push(candidate(
+                CARRIER_DHL_EXPRESS,
+                number_format::DhlExpress,
+                rank_by_check(dhl_express_check(&digits)),
+            )),
+            12 => candidates.push(candidate(
+                CARRIER_FEDEX,
+                number_format::FedExExpress,
+                rank_by_check(fedex_express_check(&digits)),
+            )),
+            15 => candidates.push(candidate(
+                CARRIER_FEDEX,
+                number_format::FedExGround,
+                rank_by_check(mod10_check(&digits)),
+            )),
+            _ => (),
+        }
+        if let Some(impb) = usps_impb(&digits) {
+            candidates.push(candidate(
+                CARRIER_USPS,
+                number_format::UspsImpb,
+                rank_by_check(mod10_check(impb)),
+            ));
+        }
+    }
+    //
+
+    // chinese e-commerce formats, known prefixes without check digits
+    let rest_is_digits = |prefix: &str, lengths: &[usize]| {
+        number.strip_prefix(prefix).is_some_and(|rest| {
+            lengths.contains(&rest.len()) && rest.bytes().all(|c| c.is_ascii_digit())
+        })
+    };
+    if rest_is_digits("YT", &[16]) {
+        candidates.push(candidate(CARRIER_YUNEXPRESS, number_format::YunExpress, 0));
+    }
+    if rest_is_digits("LP", &[14, 16]) {
+        candidates.push(candidate(CARRIER_CAINIAO, number_format::Cainiao, 0));
+    }
+    if rest_is_digits("SF", &[12, 13]) {
+        candidates.push(candidate(CARRIER_SF_EXPRESS, number_format::SfExpress, 0));
+    }
+    //
+
+    candidates.sort_by_key(|candidate| candidate.rank);
+    Ok(candidates)
+}
+
+fn candidate(carrier: i32, format: number_format, rank: u8) -> carrier_candidate {
+    carrier_candidate {
+        carrier,
+        format,
+        rank,
+    }
+}
+
+/// numbers that pass the check digit of a format are more likely to be that format
+fn rank_by_check(check_passed: bool) -> u8 {
+    match check_passed {
+        true => 1,
+        false => 2,
+    }
+}
+
+/// country code of an S10 number, 2 letters + 8 digits + check digit + 2 letters, the check digit uses the weights
+/// 8 6 4 2 3 5 9 7 and 11 - (sum mod 11) where 10 becomes 0 and 11 becomes 5
+fn upu_s10_country(number: &str) -> Result<Option<&str>, number_detection_error> {
+    let bytes = number.as_bytes();
+    if bytes.len() != 13
+        || !bytes[..2].iter().all(u8::is_ascii_alphabetic)
+        || !bytes[2..11].iter().all(u8::is_ascii_digit)
+        || !bytes[11..].iter().all(u8::is_ascii_alphabetic)
+    {
+        return Ok(None);
+    }
+
+    const WEIGHTS: [u32; 8] = [8, 6, 4, 2, 3, 5, 9, 7];
+    let sum: u32 = bytes[2..10]
+        .iter()
+        .zip(WEIGHTS)
+        .map(|(digit, weight)| (digit - b'0') as u32 * weight)
+        .sum();
+    let check = match 11 - sum % 11 {
+        10 => 0,
+        11 => 5,
+        check => check,
+    };
+    if check != (bytes[10] - b'0') as u32 {
+        return Err(number_detection_error::CheckDigit(number_format::UpuS10));
+    }
+    Ok(Some(&number[11..]))
+}
+
+/// UPS numbers are 1Z + 16 letters and digits, the last one is the check digit, letters count as (letter - 'A' + 2) mod 10
+/// and every second character is doubled
+fn is_ups_1z(number: &str) -> Result<bool, number_detection_error> {
+    let body = match number.strip_prefix("1Z") {
+        Some(body) if body.len() == 16 => body.as_bytes(),
+        _ => return Ok(false),
+    };
+    let value = |c: u8| match c {
+        b'0'..=b'9' => (c - b'0') as u32,
+        _ => ((c - b'A') as u32 + 2) % 10,
+    };
+    let sum: u32 = body[..15]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| match i % 2 {
+            1 => value(*c) * 2,
+            _ => value(*c),
+        })
+        .sum();
+    let check = (10 - sum % 10) % 10;
+    if !body[15].is_ascii_digit() || check != (body[15] - b'0') as u32 {
+        return Err(number_detection_error::CheckDigit(number_format::Ups1Z));
+    }
+    Ok(true)
+}
+
+/// DHL Express waybills are 10 digits, the last one is the first 9 mod 7
+fn dhl_express_check(digits: &[u32]) -> bool {
+    let body = digits[..9]
+        .iter()
+        .fold(0u64, |number, digit| number * 10 + *digit as u64);
+    body % 7 == digits[9] as u64
+}
+
+/// FedEx Express numbers are 12 digits, the first 11 weighted 1 3 7 from the right, sum mod 11 mod 10
+fn fedex_express_check(digits: &[u32]) -> bool {
+    const WEIGHTS: [u32; 3] = [1, 3, 7];
+    let sum: u32 = digits[..11]
+        .iter()
+        .rev()
+        .zip(WEIGHTS.iter().cycle())
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    sum % 11 % 10 == digits[11]
+}
+
+/// the mod 10 check digit of USPS and FedEx Ground, the digits before the check digit weighted 3 1 from the right
+fn mod10_check(digits: &[u32]) -> bool {
+    let (check, body) = match digits.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| match i % 2 {
+            0 => digit * 3,
+            _ => *digit,
+        })
+        .sum();
+    (10 - sum % 10) % 10 == *check
+}
+
+/// USPS IMpb numbers are 20 to 22 digits starting with 9, or the same with the routing code 420 + ZIP in front, returns the
+/// number without the routing code since the check digit only covers the IMpb
+fn usps_impb(digits: &[u32]) -> Option<&[u32]> {
+    let is_impb = |number: &[u32]| (20..=22).contains(&number.len()) && number[0] == 9;
+    match digits {
+        // 420 + 5 digit ZIP or 420 + 9 digit ZIP+4
+        [4, 2, 0, rest @ ..] => [5, 9]
+            .into_iter()
+            .filter_map(|zip_length| rest.get(zip_length..))
+            .find(|number| is_impb(number)),
+        _ => Some(digits).filter(|number| is_impb(number)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(tracking_number: &str) -> Vec<(number_format, u8)> {
+        detect_carriers(tracking_number)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| (candidate.format, candidate.rank))
+            .collect()
+    }
+
+    #[test]
+    fn upu_s10_check_digit() {
+        let candidates = detect_carriers("RR123456785GB").unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].carrier, 11031);
+        assert_eq!(candidates[0].format, number_format::UpuS10);
+        assert_eq!(
+            detect_carriers("RR123456784GB"),
+            Err(number_detection_error::CheckDigit(number_format::UpuS10))
+        );
+    }
+
+    #[test]
+    fn china_ems_before_china_post() {
+        // 8 6 4 2 3 5 9 7 over 12345678 is 204, 11 - 204 mod 11 is 5
+        let carriers: Vec<i32> = detect_carriers("EA123456785CN")
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.carrier)
+            .collect();
+        assert_eq!(carriers, vec![CARRIER_CHINA_EMS, CARRIER_CHINA_POST]);
+    }
+
+    #[test]
+    fn ups_1z_check_digit() {
+        assert_eq!(
+            formats("1Z999AA10123456784"),
+            vec![(number_format::Ups1Z, 0)]
+        );
+        assert_eq!(
+            formats("1Z12345E6605272234"),
+            vec![(number_format::Ups1Z, 0)]
+        );
+        assert_eq!(
+            detect_carriers("1Z999AA10123456785"),
+            Err(number_detection_error::CheckDigit(number_format::Ups1Z))
+        );
+    }
+
+    #[test]
+    fn fedex_check_digits_rank() {
+        assert_eq!(
+            formats("123456789012"),
+            vec![(number_format::FedExExpress, 1)]
+        );
+        assert_eq!(
+            formats("123456789013"),
+            vec![(number_format::FedExExpress, 2)]
+        );
+        assert_eq!(
+            formats("449044304137821"),
+            vec![(number_format::FedExGround, 1)]
+        );
+        assert_eq!(
+            formats("449044304137822"),
+            vec![(number_format::FedExGround, 2)]
+        );
+    }
+
+    #[test]
+    fn dhl_express_check_digit_rank() {
+        assert_eq!(formats("3318810025"), vec![(number_format::DhlExpress, 1)]);
+        assert_eq!(formats("3318810024"), vec![(number_format::DhlExpress, 2)]);
+    }
+
+    #[test]
+    fn usps_impb_check_digit_rank() {
+        assert_eq!(
+            formats("9205590164917312751089"),
+            vec![(number_format::UspsImpb, 1)]
+        );
+        assert_eq!(
+            formats("9205590164917312751088"),
+            vec![(number_format::UspsImpb, 2)]
+        );
+    }
+
+    #[test]
+    fn usps_impb_routing_code_is_not_checked() {
+        // 420 + ZIP 90210 and 420 + ZIP+4 902101234 in front of the same number
+        assert_eq!(
+            formats("420902109205590164917312751089"),
+            vec![(number_format::UspsImpb, 1)]
+        );
+        assert_eq!(
+            formats("4209021012349205590164917312751089"),
+            vec![(number_format::UspsImpb, 1)]
+        );
+        assert_eq!(
+            formats("420902109205590164917312751088"),
+            vec![(number_format::UspsImpb, 2)]
+        );
+    }
+
+    #[test]
+    fn unknown_and_invalid_numbers() {
+        assert_eq!(formats("ABCDEFGH1234"), vec![]);
+        assert_eq!(
+            detect_carriers("1234567"),
+            Err(number_detection_error::Length)
+        );
+        assert_eq!(
+            detect_carriers("RR12345678/GB"),
+            Err(number_detection_error::Characters)
+        );
+    }
+
+    #[test]
+    fn normalizes_full_width_spaces_and_dashes() {
+        assert_eq!(
+            normalize_tracking_number("rr 123-456-785 gb"),
+            "RR123456785GB"
+        );
+        assert_eq!(
+            normalize_tracking_number("ＲＲ１２３４５６７８５ＧＢ"),
+            "RR123456785GB"
+        );
+    }
+}
@@ -0,0 +1,733 @@
+/*
+    Cargo stuff
+*/
+
+use crate::number_detection::{
+    self, carrier_candidate, CARRIER_CAINIAO, CARRIER_DHL_EXPRESS, CARRIER_FEDEX,
+    CARRIER_SF_EXPRESS, CARRIER_UPS, CARRIER_USPS, CARRIER_YUNEXPRESS, MAX_NUMBER_LENGTH,
+};
+use base64::Engine as _;
+use serde::Serialize;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// longest text or email the client can send, what's over this in an email is attachments anyway
+pub const MAX_INPUT_LENGTH: usize = 512 * 1024;
+// most numbers taken out of one text
+const MAX_EXTRACTED_NUMBERS: usize = 50;
+// most groups a number can be written in, "1Z 999 AA1 01 2345 678 4" is 7
+const MAX_WINDOW_TOKENS: usize = 8;
+// words before a number that are searched for a tracking word
+const KEYWORD_LOOKBEHIND_TOKENS: usize = 6;
+// numbers of only digits shorter than this need a tracking word in front, order, phone and invoice numbers pass the check
+// digits of the short formats too often
+const MIN_TRUSTED_DIGITS_LENGTH: usize = 20;
+// numbers in a format nobody knows are taken from this long if there's a tracking word in front
+const MIN_UNKNOWN_FORMAT_LENGTH: usize = 10;
+// words this long with a digit in them are numbers of their own when looking for a tracking word before a number
+const MIN_NUMBER_LENGTH: usize = 8;
+// multipart emails nested deeper than this aren't opened
+const MAX_MIME_DEPTH: usize = 5;
+// words that come before tracking numbers, lowercase, a word starting with one counts ("tracking", "sendungsnummer")
+const TRACKING_KEYWORDS: [&str; 9] = [
+    "track",
+    "shipment",
+    "waybill",
+    "awb",
+    "parcel",
+    "consignment",
+    "sendung",
+    "colis",
+    "envio",
+];
+// headers one of which every email has, lowercase
+const EMAIL_HEADERS: [&str; 8] = [
+    "from",
+    "to",
+    "subject",
+    "date",
+    "received",
+    "content-type",
+    "mime-version",
+    "message-id",
+];
+// tracking pages of the carriers by a piece of their host, with the query parameters or the path right before the number,
+// the carrier is None for the tracking sites that do every carrier
+const CARRIER_URL_FORMATS: [(&str, Option<i32>, &[&str]); 11] = [
+    ("ups.com", Some(CARRIER_UPS), &["tracknum=", "tracknums="]),
+    (
+        "fedex.com",
+        Some(CARRIER_FEDEX),
+        &["trknbr=", "tracknumbers="],
+    ),
+    (
+        "usps.com",
+        Some(CARRIER_USPS),
+        &["tlabels=", "qtc_tlabels1="],
+    ),
+    (
+        "dhl.com",
+        Some(CARRIER_DHL_EXPRESS),
+        &["tracking-id=", "awb="],
+    ),
+    ("dhl.de", Some(7041), &["piececode=", "idc="]),
+    ("royalmail.com", Some(11031), &["tracking-results/"]),
+    (
+        "cainiao.com",
+        Some(CARRIER_CAINIAO),
+        &["mailnolist=", "mailno="],
+    ),
+    ("yuntrack.com", Some(CARRIER_YUNEXPRESS), &["id="]),
+    (
+        "sf-express.com",
+        Some(CARRIER_SF_EXPRESS),
+        &["waybill-detail/"],
+    ),
+    ("17track.net", None, &["nums="]),
+    ("parcelsapp.com", None, &["tracking/"]),
+];
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// a tracking number found in the text, with the carriers it could belong to
+#[derive(Debug, Serialize, Clone)]
+pub struct extracted_number {
+    pub number: String,
+    // carrier of the tracking page the number was linked to, the surest guess there is
+    pub carrier_from_url: Option<i32>,
+    pub candidates: Vec<carrier_candidate>,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Find the tracking numbers in text the user pasted, plain text or a whole email, without asking the API, the numbers
+/// linked to carrier tracking pages come first and then the ones written in the text in the order they're in, numbers
+/// written in groups like "1Z 999 AA1 01 2345 678 4" are put back together
+pub fn extract_tracking_numbers(input: &str) -> Vec<extracted_number> {
+    let text = readable_text(input);
+    let mut found: Vec<extracted_number> = Vec::new();
+
+    // links to the tracking pages, they tell the carrier
+    for url in find_urls(&text) {
+        for (number, carrier) in numbers_in_url(url) {
+            add_number(&mut found, number, carrier);
+        }
+    }
+    //
+
+    // numbers in the text, the longest run of words that makes a number wins
+    let tokens = tokenize(&text);
+    let mut start = 0;
+    while start < tokens.len() && found.len() < MAX_EXTRACTED_NUMBERS {
+        match longest_number_at(&text, &tokens, start) {
+            Some((number, used_tokens)) => {
+                add_number(&mut found, number, None);
+                start += used_tokens;
+            }
+            None => start += 1,
+        }
+    }
+    //
+
+    found.truncate(MAX_EXTRACTED_NUMBERS);
+    found
+}
+
+fn add_number(found: &mut Vec<extracted_number>, number: String, carrier_from_url: Option<i32>) {
+    match found
+        .iter_mut()
+        .find(|extracted| extracted.number == number)
+    {
+        Some(extracted) => {
+            extracted.carrier_from_url = extracted.carrier_from_url.or(carrier_from_url)
+        }
+        None => found.push(extracted_number {
+            candidates: number_detection::detect_carriers(&number).unwrap_or_default(),
+            number,
+            carrier_from_url,
+        }),
+    }
+}
+
+// TEXT
+
+/// Get the text out of what the user pasted, an email is opened up (headers, multipart, quoted-printable, base64, HTML)
+/// and anything else is left as it is
+fn readable_text(input: &str) -> String {
+    let input = input.replace("\r\n", "\n");
+    match split_headers(&input) {
+        Some((headers, body)) if is_email(&headers) => {
+            let subject = header_value(&headers, "subject").unwrap_or_default();
+            format!("{}\n{}", subject, part_text(&headers, body, 0))
+        }
+        // just the body of an email
+        _ => match bare_multipart_boundary(&input) {
+            Some(boundary) => multipart_text(&input, boundary, 0),
+            None if is_html(&input) => html_to_text(&input),
+            None => input,
+        },
+    }
+}
+
+/// split the headers from the body, None if it doesn't start with headers, folded header lines are put back together
+fn split_headers(input: &str) -> Option<(Vec<(String, String)>, &str)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end_matches('\n');
+        if line.trim().is_empty() {
+            return Some((headers, &input[offset..]));
+        }
+        if line.starts_with([' ', '\t']) {
+            let (_, value) = headers.last_mut()?;
+            value.push(' ');
+            value.push_str(line.trim());
+            continue;
+        }
+        let (name, value) = line.split_once(':')?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_graphic()) {
+            return None;
+        }
+        headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+    }
+    None
+}
+
+fn is_email(headers: &[(String, String)]) -> bool {
+    headers
+        .iter()
+        .any(|(name, _)| EMAIL_HEADERS.contains(&name.as_str()))
+}
+
+fn header_value(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.clone())
+}
+
+/// a parameter of a header value like the boundary in `multipart/alternative; boundary="abc"`
+fn header_parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.trim().split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Text of one part of an email, multipart parts are opened, text parts decoded and attachments left out
+fn part_text(headers: &[(String, String)], body: &str, depth: usize) -> String {
+    let content_type =
+        header_value(headers, "content-type").unwrap_or_else(|| "text/plain".to_string());
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    if media_type.starts_with("multipart/") {
+        return match header_parameter(&content_type, "boundary") {
+            Some(boundary) => multipart_text(body, &boundary, depth),
+            None => String::new(),
+        };
+    }
+    if media_type == "message/rfc822" && depth < MAX_MIME_DEPTH {
+        return match split_headers(body) {
+            Some((headers, body)) => part_text(&headers, body, depth + 1),
+            None => String::new(),
+        };
+    }
+    if !media_type.starts_with("text/") {
+        return String::new();
+    }
+
+    let encoding = header_value(headers, "content-transfer-encoding").unwrap_or_default();
+    let text = match encoding.to_ascii_lowercase().as_str() {
+        "quoted-printable" => decode_quoted_printable(body),
+        "base64" => decode_base64(body),
+        _ => body.to_string(),
+    };
+    match media_type == "text/html" {
+        true => html_to_text(&text),
+        false => text,
+    }
+}
+
+/// text of every part of a multipart body
+fn multipart_text(body: &str, boundary: &str, depth: usize) -> String {
+    if depth >= MAX_MIME_DEPTH {
+        return String::new();
+    }
+    let delimiter = format!("--{}", boundary);
+    body.split(delimiter.as_str())
+        // before the first delimiter is the preamble
+        .skip(1)
+        // the last delimiter ends with --
+        .take_while(|part| !part.starts_with("--"))
+        .filter_map(|part| {
+            let part = part.split_once('\n').map(|(_, part)| part)?;
+            let (headers, body) = split_headers(part)?;
+            Some(part_text(&headers, body, depth + 1))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// the boundary when the user pasted a multipart body without the headers, it's on the first line and again later
+fn bare_multipart_boundary(input: &str) -> Option<&str> {
+    let first_line = input.lines().find(|line| !line.trim().is_empty())?.trim();
+    let boundary = first_line.strip_prefix("--")?;
+    (boundary.len() >= 8 && input.matches(first_line).count() > 1).then_some(boundary)
+}
+
+fn decode_quoted_printable(body: &str) -> String {
+    let bytes = body.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'=' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        // soft line break
+        if bytes.get(i + 1) == Some(&b'\n') {
+            i += 2;
+            continue;
+        }
+        let hex_byte = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex_byte {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(b'=');
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn decode_base64(body: &str) -> String {
+    let encoded: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    match base64::engine::general_purpose::STANDARD.decode(encoded) {
+        Ok(decoded) => String::from_utf8_lossy(&decoded).into_owned(),
+        Err(_) => body.to_string(),
+    }
+}
+
+fn is_html(input: &str) -> bool {
+    let lowercase = input.to_ascii_lowercase();
+    lowercase.contains("<html") || lowercase.contains("<body") || lowercase.contains("<a href")
+}
+
+/// Turn HTML into text, scripts, styles and comments are dropped and the links are kept next to their text because the
+/// tracking page links are the best thing in a shipping email
+fn html_to_text(html: &str) -> String {
+    // ASCII lowercase keeps the byte offsets the same as in the HTML
+    let lowercase = html.to_ascii_lowercase();
+    let mut text = String::with_capacity(html.len());
+    let mut i = 0;
+    while let Some(tag_start) = html[i..].find('<').map(|offset| i + offset) {
+        text.push_str(&decode_entities(&html[i..tag_start]));
+        let skip_to = |closing: &str| {
+            lowercase[tag_start..]
+                .find(closing)
+                .map(|offset| tag_start + offset + closing.len())
+                .unwrap_or(html.len())
+        };
+        let tag = &lowercase[tag_start + 1..];
+        i = if tag.starts_with("!--") {
+            skip_to("-->")
+        } else if tag.starts_with("script") {
+            skip_to("</script>")
+        } else if tag.starts_with("style") {
+            skip_to("</style>")
+        } else {
+            let tag_end = skip_to(">");
+            if let Some(href) = attribute_value(&html[tag_start..tag_end], "href") {
+                text.push(' ');
+                text.push_str(&decode_entities(href));
+            }
+            tag_end
+        };
+        text.push('\n');
+    }
+    text.push_str(&decode_entities(&html[i..]));
+    text
+}
+
+/// value of an attribute in a tag, quoted or not
+fn attribute_value<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lowercase = tag.to_ascii_lowercase();
+    let start = lowercase.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split([' ', '>']).next(),
+    }
+}
+
+/// the HTML entities that show up in emails
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .get(1..rest.len().min(12))
+            .and_then(|entity| entity.split_once(';'))
+            .map(|(entity, _)| entity);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| {
+                    entity
+                        .strip_prefix('#')
+                        .and_then(|digits| digits.parse().ok())
+                })
+                .and_then(char::from_u32),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// URLS
+
+/// the http and https links in the text
+fn find_urls(text: &str) -> Vec<&str> {
+    let lowercase = text.to_ascii_lowercase();
+    let mut urls = Vec::new();
+    let mut i = 0;
+    while let Some(start) = lowercase[i..].find("http").map(|offset| i + offset) {
+        let rest = &lowercase[start..];
+        if !rest.starts_with("http://") && !rest.starts_with("https://") {
+            i = start + 4;
+            continue;
+        }
+        let end = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | ')' | ']'))
+            .map(|offset| start + offset)
+            .unwrap_or(text.len());
+        urls.push(text[start..end].trim_end_matches(['.', ',', ';']));
+        i = end;
+    }
+    urls
+}
+
+/// Numbers in a link to a carrier tracking page, with the carrier of the page, some pages take more than one number
+fn numbers_in_url(url: &str) -> Vec<(String, Option<i32>)> {
+    let lowercase = url.to_ascii_lowercase();
+    let host_start = lowercase.find("://").map(|i| i + 3).unwrap_or(0);
+    let host_end = lowercase[host_start..]
+        .find(['/', '?', '#'])
+        .map(|i| host_start + i)
+        .unwrap_or(lowercase.len());
+    let host = &lowercase[host_start..host_end];
+    let (carrier, markers) = match CARRIER_URL_FORMATS
+        .iter()
+        .find(|(host_part, _, _)| host == *host_part || host.ends_with(&format!(".{}", host_part)))
+    {
+        Some((_, carrier, markers)) => (*carrier, *markers),
+        None => return Vec::new(),
+    };
+
+    let mut numbers = Vec::new();
+    for marker in markers {
+        // the marker has to start a parameter or a path piece, "id=" isn't in "trackid="
+        let mut search_from = host_end;
+        while let Some(found) = lowercase[search_from..]
+            .find(marker)
+            .map(|i| search_from + i)
+        {
+            search_from = found + marker.len();
+            let before = lowercase[..found].chars().last();
+            if !matches!(before, Some('?' | '&' | '#' | ';' | '/')) {
+                continue;
+            }
+            let value = &url[search_from..];
+            let value = &value[..value.find(['&', '#', '?', '/']).unwrap_or(value.len())];
+            let value = urlencoding::decode(value)
+                .map(|value| value.into_owned())
+                .unwrap_or_else(|_| value.to_string());
+            for number in value.split([',', '|', ';', ' ', '\n']) {
+                let number = number_detection::normalize_tracking_number(number);
+                if number.chars().any(|c| c.is_ascii_digit())
+                    && number_detection::detect_carriers(&number).is_ok()
+                {
+                    numbers.push((number, carrier));
+                }
+            }
+        }
+    }
+    numbers
+}
+
+// WORDS
+
+/// byte ranges of the words, runs of letters and digits
+fn tokenize(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(token_start)) => {
+                tokens.push((token_start, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(token_start) = start {
+        tokens.push((token_start, text.len()));
+    }
+    tokens
+}
+
+/// words of a number written in groups are apart by a space or a dash on the same line
+fn joinable(gap: &str) -> bool {
+    gap.chars().count() <= 3
+        && !gap.contains('\n')
+        && number_detection::normalize_tracking_number(gap).is_empty()
+}
+
+/// a tracking word in the words before, up to the number before it, "Tracking number: 1Z... Order 123..." is about the 1Z
+fn keyword_before(text: &str, tokens: &[(usize, usize)], start: usize) -> bool {
+    tokens[start.saturating_sub(KEYWORD_LOOKBEHIND_TOKENS)..start]
+        .iter()
+        .rev()
+        .map(|(token_start, token_end)| text[*token_start..*token_end].to_lowercase())
+        .take_while(|word| {
+            word.len() < MIN_NUMBER_LENGTH || !word.chars().any(|c| c.is_ascii_digit())
+        })
+        .any(|word| {
+            TRACKING_KEYWORDS
+                .iter()
+                .any(|keyword| word.starts_with(keyword))
+        })
+}
+
+/// The longest tracking number made of the words from start on, with how many words it took, digits written in groups
+/// only count as a whole, a piece of a longer run of digits passes some check digit too easily
+fn longest_number_at(
+    text: &str,
+    tokens: &[(usize, usize)],
+    start: usize,
+) -> Option<(String, usize)> {
+    let is_digits = |token: usize| {
+        let (token_start, token_end) = tokens[token];
+        number_detection::normalize_tracking_number(&text[token_start..token_end])
+            .bytes()
+            .all(|c| c.is_ascii_digit())
+    };
+    let joins_next = |token: usize| {
+        token + 1 < tokens.len() && joinable(&text[tokens[token].1..tokens[token + 1].0])
+    };
+    if start > 0 && joins_next(start - 1) && is_digits(start - 1) && is_digits(start) {
+        return None;
+    }
+
+    let after_keyword = keyword_before(text, tokens, start);
+    let mut longest = None;
+    let mut joined = String::new();
+    for end in start..tokens.len().min(start + MAX_WINDOW_TOKENS) {
+        if end > start && !joins_next(end - 1) {
+            break;
+        }
+        joined.push_str(&text[tokens[end].0..tokens[end].1]);
+        let number = number_detection::normalize_tracking_number(&joined);
+        if number.len() > MAX_NUMBER_LENGTH {
+            break;
+        }
+        let digits_go_on =
+            number.bytes().all(|c| c.is_ascii_digit()) && joins_next(end) && is_digits(end + 1);
+        if !digits_go_on && is_likely_tracking_number(&number, end == start, after_keyword) {
+            longest = Some((number, end - start + 1));
+        }
+    }
+    longest
+}
+
+/// Whether a normalised run of words is a tracking number, the formats with letters and a check are taken anywhere, the
+/// others need a tracking word in front of them or enough digits
+fn is_likely_tracking_number(number: &str, single_word: bool, after_keyword: bool) -> bool {
+    if !number.chars().all(|c| c.is_ascii_alphanumeric())
+        || !number.chars().any(|c| c.is_ascii_digit())
+    {
+        return false;
+    }
+    let candidates = match number_detection::detect_carriers(number) {
+        Ok(candidates) => candidates,
+        Err(_) => return false,
+    };
+    match candidates.first().map(|candidate| candidate.rank) {
+        // S10, 1Z and the formats with a carrier prefix
+        Some(0) => true,
+        // digits that pass the check digit of a format
+        Some(1) => after_keyword || number.len() >= MIN_TRUSTED_DIGITS_LENGTH,
+        // digits that fail every check
+        Some(_) => false,
+        // nobody knows the format, taken only as it was written right after a tracking word
+        None => single_word && after_keyword && number.len() >= MIN_UNKNOWN_FORMAT_LENGTH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(input: &str) -> Vec<String> {
+        extract_tracking_numbers(input)
+            .into_iter()
+            .map(|extracted| extracted.number)
+            .collect()
+    }
+
+    #[test]
+    fn plain_order_confirmation() {
+        let email = "Thank you for your order #123456789012 placed on 3 May!\n\
+            \n\
+            Your tracking number is 1Z 999 AA1 01 2345 678 4, you can follow it on the UPS website.\n\
+            Questions? Call us at +1 800 463 3339 or reply to this email.\n\
+            Invoice 3318810025 is attached.\n";
+        assert_eq!(numbers(email), vec!["1Z999AA10123456784"]);
+    }
+
+    #[test]
+    fn html_email_links_give_the_carrier() {
+        let email = "From: Shop <orders@shop.example>\n\
+            To: alex@example.com\n\
+            Subject: Your order has shipped\n\
+            Content-Type: text/html; charset=utf-8\n\
+            \n\
+            <html><body><p>Good news, your order is on the way!</p>\
+            <p><a href=\"https://www.ups.com/track?loc=en_US&amp;tracknum=1Z12345E6605272234\">Track your parcel</a></p>\
+            <p>Tracking number: 1Z12345E6605272234</p>\
+            <p style=\"color:#999\">Order 4490443041378 &middot; Customer service 0800 123 4567</p>\
+            </body></html>\n";
+        let extracted = extract_tracking_numbers(email);
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(extracted[0].number, "1Z12345E6605272234");
+        assert_eq!(extracted[0].carrier_from_url, Some(CARRIER_UPS));
+    }
+
+    #[test]
+    fn multipart_email_with_base64_and_quoted_printable_parts() {
+        let email = "Received: from mail.shop.example\r\n\
+            From: Shop <orders@shop.example>\r\n\
+            Subject: Shipping confirmation\r\n\
+            MIME-Version: 1.0\r\n\
+            Content-Type: multipart/alternative; boundary=\"b1_shipping\"\r\n\
+            \r\n\
+            This is a multi-part message in MIME format.\r\n\
+            --b1_shipping\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            SGkgQWxleCwKeW91ciBwYXJjZWwgUlIxMjM0NTY3ODVHQiBpcyBvbiBpdHMgd2F5IHdpdGggUm95YWwgTWFpbC4KT3JkZXIgMTIzNDU2Nzg5MDEyLCBjYWxsIHVzIGF0IDA4MDAgMTIzIDQ1NjcuCg==\r\n\
+            --b1_shipping\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\
+            \r\n\
+            <html><body><p>Your other parcel ships with USPS:</p><a href=3D\"https://tools=\r\n\
+            .usps.com/go/TrackConfirmAction?tLabels=3D9205590164917312751089\">Track it</a>=\r\n\
+            </body></html>\r\n\
+            --b1_shipping--\r\n";
+        let extracted = extract_tracking_numbers(email);
+        let found: Vec<(&str, Option<i32>)> = extracted
+            .iter()
+            .map(|extracted| (extracted.number.as_str(), extracted.carrier_from_url))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("9205590164917312751089", Some(CARRIER_USPS)),
+                ("RR123456785GB", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn order_phone_and_invoice_numbers_are_left_out() {
+        let text = "Order number: 4490443041378\n\
+            Order date: 2024-05-03\n\
+            Invoice no. 3318810025\n\
+            Customer ID 123456789012\n\
+            Phone: +49 228 28609898\n\
+            Total: 1.234,56 EUR\n";
+        assert!(numbers(text).is_empty());
+    }
+
+    #[test]
+    fn short_digits_need_a_tracking_word() {
+        assert_eq!(numbers("Sendungsnummer: 3318810025"), vec!["3318810025"]);
+        assert_eq!(numbers("AWB 3318810025 (DHL Express)"), vec!["3318810025"]);
+        assert!(numbers("Reference 3318810025").is_empty());
+    }
+
+    #[test]
+    fn unknown_formats_only_right_after_a_tracking_word() {
+        assert_eq!(
+            numbers("Tracking: JD014600006281472"),
+            vec!["JD014600006281472"]
+        );
+        assert!(numbers("Coupon JD014600006281472").is_empty());
+    }
+
+    #[test]
+    fn same_number_in_link_and_text_is_found_once() {
+        let text =
+            "Track here https://www.17track.net/en/track?nums=RR123456785GB,LP00123456789012 \
+            or use RR123456785GB on the Royal Mail site";
+        let extracted = extract_tracking_numbers(text);
+        let found: Vec<&str> = extracted
+            .iter()
+            .map(|extracted| extracted.number.as_str())
+            .collect();
+        assert_eq!(found, vec!["RR123456785GB", "LP00123456789012"]);
+    }
+}
@@ -0,0 +1,133 @@
+/*
+    Cargo stuff
+*/
+
+use crate::{
+    notifications::{notification_service, notification_service_error},
+    trackingapi::tracking_client,
+};
+use std::{
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use thiserror::Error;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// remaining provider quota under which the bot owner is told, when PROVIDER_QUOTA_ALERT_BELOW isn't set
+const DEFAULT_ALERT_BELOW: i64 = 100;
+// remaining provider quota under which new registrations are refused, when PROVIDER_QUOTA_REFUSE_BELOW isn't set, what's
+// left is kept for re-registrations by the reconciliation job
+const DEFAULT_REFUSE_BELOW: i64 = 10;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// checks the 17TRACK account quota before registrations, refuses them when it's almost used up and tells the bot owner
+/// once when it gets low
+pub struct provider_quota_guard {
+    pub alert_below: i64,
+    pub refuse_below: i64,
+    // BOT_OWNER_CHAT_ID, no alerts without it
+    owner_chat_id: Option<i64>,
+    // set after the alert so it's sent once, cleared when the quota is topped up
+    alerted: AtomicBool,
+}
+
+#[derive(Error, Debug)]
+pub enum provider_quota_error {
+    #[error("provider quota is too low for new registrations, {0} left")]
+    QuotaTooLow(i64),
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+impl provider_quota_guard {
+    /// PROVIDER_QUOTA_ALERT_BELOW, PROVIDER_QUOTA_REFUSE_BELOW and BOT_OWNER_CHAT_ID, all optional
+    pub fn from_env() -> Self {
+        let threshold = |name: &str, default: i64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        provider_quota_guard {
+            alert_below: threshold("PROVIDER_QUOTA_ALERT_BELOW", DEFAULT_ALERT_BELOW),
+            refuse_below: threshold("PROVIDER_QUOTA_REFUSE_BELOW", DEFAULT_REFUSE_BELOW),
+            owner_chat_id: env::var("BOT_OWNER_CHAT_ID")
+                .ok()
+                .and_then(|chat_id| chat_id.parse().ok()),
+            alerted: AtomicBool::new(false),
+        }
+    }
+
+    /// Check there's enough provider quota left to register a new number, if the quota can't be fetched the registration
+    /// goes ahead and the API decides
+    pub async fn check_registration(
+        &self,
+        tracking_client: &tracking_client,
+        notification_service: &Result<notification_service, notification_service_error>,
+    ) -> Result<(), provider_quota_error> {
+        let remaining = match tracking_client.get_quota().await {
+            Ok(quota) => match quota.quota_remain {
+                Some(remaining) => remaining,
+                None => return Ok(()),
+            },
+            Err(e) => {
+                println!(
+                    "@PROVIDER_QUOTA: error getting the quota, not checked: {}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+
+        if remaining >= self.alert_below {
+            self.alerted.store(false, Ordering::Relaxed);
+        } else if !self.alerted.swap(true, Ordering::Relaxed) {
+            self.alert_owner(notification_service, remaining).await;
+        }
+
+        match remaining < self.refuse_below {
+            true => {
+                println!("@PROVIDER_QUOTA: refusing registration, {} left", remaining);
+                Err(provider_quota_error::QuotaTooLow(remaining))
+            }
+            false => Ok(()),
+        }
+    }
+
+    /// tell the bot owner on telegram that the quota is running out
+    async fn alert_owner(
+        &self,
+        notification_service: &Result<notification_service, notification_service_error>,
+        remaining: i64,
+    ) {
+        println!("@PROVIDER_QUOTA: only {} left", remaining);
+        let (owner_chat_id, service) = match (self.owner_chat_id, notification_service) {
+            (Some(owner_chat_id), Ok(service)) => (owner_chat_id, service),
+            _ => return,
+        };
+        let message = format!(
+            "<b>17TRACK quota is running low</b>\n{} registrations left, new numbers are refused below {}.",
+            remaining, self.refuse_below
+        );
+        if let Err(e) = service.send_plain_message(owner_chat_id, &message).await {
+            println!("@PROVIDER_QUOTA: error alerting the owner: {}", e);
+        }
+    }
+}
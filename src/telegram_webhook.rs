@@ -0,0 +1,346 @@
+use crate::{
+    check_relation_and_get_record, insert_relation, notifications::escape_html, number_detection,
+    AppState, RelationRole, TrackingNumberUserRelation, MAX_LABEL_LENGTH,
+};
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use futures::TryStreamExt;
+use mongodb::{bson::doc, Client};
+use teloxide::types::{ChatMemberUpdated, Message, Update, UpdateKind};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    HELPER FUNCTIONS
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// split a message like "/link@teletrack_bot RR123456789CN Office printer" into the command and the arguments
+fn parse_command(text: &str) -> Option<(&str, Vec<&str>)> {
+    let mut parts = text.split_whitespace();
+    let command = parts.next()?.strip_prefix('/')?;
+    // commands in groups get the bot name attached to them
+    let command = command.split('@').next()?;
+    Some((command, parts.collect()))
+}
+
+/// reply in the chat, failures are only logged since telegram doesn't care about the answer to the update
+async fn reply(data: &web::Data<AppState>, chat_id: i64, message: &str) {
+    match &*data.notification_service {
+        Ok(service) => {
+            if let Err(e) = service.send_plain_message(chat_id, message).await {
+                println!("@TELEGRAM_WEBHOOK: failed to reply in {}: {}", chat_id, e);
+            }
+        }
+        Err(e) => println!("@TELEGRAM_WEBHOOK: notification service error: {}", e),
+    }
+}
+
+/// check the sender of the message is the creator or an administrator of the group
+async fn is_sender_admin(data: &web::Data<AppState>, message: &Message) -> bool {
+    let sender = match message.from() {
+        Some(sender) => sender,
+        None => return false,
+    };
+    match &*data.notification_service {
+        Ok(service) => match service.is_chat_admin(message.chat.id.0, sender.id).await {
+            Ok(is_admin) => is_admin,
+            Err(e) => {
+                println!("@TELEGRAM_WEBHOOK: failed to get the chat member: {}", e);
+                false
+            }
+        },
+        Err(e) => {
+            println!("@TELEGRAM_WEBHOOK: notification service error: {}", e);
+            false
+        }
+    }
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    GROUP COMMANDS
+
+    /link <tracking number> [label]     - admins only, post the updates of one of the numbers they own in the group
+    /unlink <tracking number>           - admins only, stop posting the updates of the number in the group
+    /linked                             - list the numbers linked to the group
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// Function for handling the commands sent in a group chat the bot was added to
+async fn handle_group_command(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    message: Message,
+) {
+    let (command, arguments) = match message.text().and_then(parse_command) {
+        Some(command) => command,
+        None => return,
+    };
+    let chat_id = message.chat.id.0;
+
+    // set database and collection
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+        db.collection("tracking_number_user_relation");
+
+    match command {
+        "link" | "unlink" => {
+            let tracking_number = match arguments.first() {
+                Some(tracking_number) => {
+                    number_detection::normalize_tracking_number(tracking_number)
+                }
+                None => {
+                    reply(
+                        &data,
+                        chat_id,
+                        &format!("Usage: /{} &lt;tracking number&gt;", command),
+                    )
+                    .await;
+                    return;
+                }
+            };
+
+            // only the admins can manage the numbers posted in the group
+            if !is_sender_admin(&data, &message).await {
+                reply(&data, chat_id, "Only the group admins can do that.").await;
+                return;
+            }
+            //
+
+            let filter = doc! {"tracking_number": &tracking_number, "chat_id": chat_id};
+            if command == "unlink" {
+                match collection_relations.delete_one(filter, None).await {
+                    Ok(result) if result.deleted_count > 0 => {
+                        reply(
+                            &data,
+                            chat_id,
+                            &format!(
+                                "Stopped posting updates for {}.",
+                                escape_html(&tracking_number)
+                            ),
+                        )
+                        .await
+                    }
+                    Ok(_) => {
+                        reply(
+                            &data,
+                            chat_id,
+                            &format!(
+                                "{} is not linked to this group.",
+                                escape_html(&tracking_number)
+                            ),
+                        )
+                        .await
+                    }
+                    Err(e) => println!("@TELEGRAM_WEBHOOK: error unlinking number: {}", e),
+                }
+                return;
+            }
+
+            // the admin has to own the number in the mini app, the relation is saved with their user ID hash
+            let sender_id = match message.from() {
+                Some(sender) => sender.id.0 as i64,
+                None => return,
+            };
+            let user_id_hash = data.user_id_hasher.hash_user_id(sender_id);
+            match check_relation_and_get_record(client.clone(), &tracking_number, &user_id_hash)
+                .await
+            {
+                Ok(relation) if relation.role == RelationRole::Owner => (),
+                Ok(_) => {
+                    reply(
+                        &data,
+                        chat_id,
+                        "Only the owner of a number can link it, not the people it was shared with.",
+                    )
+                    .await;
+                    return;
+                }
+                Err(_) => {
+                    reply(
+                        &data,
+                        chat_id,
+                        "You can only link numbers you track in the mini app.",
+                    )
+                    .await;
+                    return;
+                }
+            }
+            let label = arguments[1..].join(" ");
+            if label.chars().count() > MAX_LABEL_LENGTH {
+                reply(
+                    &data,
+                    chat_id,
+                    &format!("The label can be at most {} characters.", MAX_LABEL_LENGTH),
+                )
+                .await;
+                return;
+            }
+            //
+
+            // check if the number is already linked to the group
+            match collection_relations.find_one(filter, None).await {
+                Ok(Some(_)) => {
+                    reply(
+                        &data,
+                        chat_id,
+                        &format!(
+                            "{} is already linked to this group.",
+                            escape_html(&tracking_number)
+                        ),
+                    )
+                    .await;
+                    return;
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    println!("@TELEGRAM_WEBHOOK: {}", e);
+                    return;
+                }
+            }
+            //
+
+            // create the group relation record
+            if insert_relation(
+                client.clone(),
+                tracking_number.clone(),
+                user_id_hash,
+                None,
+                Some(chat_id),
+            )
+            .await
+            .is_err()
+            {
+                return;
+            }
+            if !label.is_empty() {
+                let filter = doc! {"tracking_number": &tracking_number, "chat_id": chat_id};
+                let update = doc! {"$set": {"label": &label}};
+                if let Err(e) = collection_relations.update_one(filter, update, None).await {
+                    println!("@TELEGRAM_WEBHOOK: error saving the label: {}", e);
+                }
+            }
+            reply(
+                &data,
+                chat_id,
+                &format!(
+                    "Updates for {} will be posted here.",
+                    escape_html(&tracking_number)
+                ),
+            )
+            .await;
+        }
+        "linked" => {
+            let filter = doc! {"chat_id": chat_id};
+            let relations = match collection_relations.find(filter, None).await {
+                Ok(cursor) => cursor
+                    .try_collect::<Vec<TrackingNumberUserRelation>>()
+                    .await
+                    .unwrap_or_default(),
+                Err(e) => {
+                    println!("@TELEGRAM_WEBHOOK: {}", e);
+                    return;
+                }
+            };
+            let message = match relations.is_empty() {
+                true => "No tracking numbers are linked to this group.".to_string(),
+                false => relations
+                    .iter()
+                    .map(|r| match &r.label {
+                        Some(label) => format!(
+                            "<b>{}</b> ({})",
+                            escape_html(label),
+                            escape_html(&r.tracking_number)
+                        ),
+                        None => escape_html(&r.tracking_number),
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            };
+            reply(&data, chat_id, &message).await;
+        }
+        _ => (),
+    }
+}
+
+/// Function for handling the bot being added to or removed from a group chat
+async fn handle_bot_membership_change(
+    client: web::Data<Client>,
+    data: web::Data<AppState>,
+    membership: ChatMemberUpdated,
+) {
+    if !(membership.chat.is_group() || membership.chat.is_supergroup()) {
+        return;
+    }
+    let chat_id = membership.chat.id.0;
+
+    if membership.new_chat_member.is_present() && !membership.old_chat_member.is_present() {
+        // added to the group
+        reply(
+            &data,
+            chat_id,
+            "Hi! Group admins can post the updates of a parcel here with /link &lt;tracking number&gt; [label]. \
+            Use /unlink &lt;tracking number&gt; to stop and /linked to see the parcels.",
+        )
+        .await;
+    } else if !membership.new_chat_member.is_present() {
+        // removed from the group, nothing can be posted there anymore
+        let db = client.database("teletrack");
+        let collection_relations: mongodb::Collection<TrackingNumberUserRelation> =
+            db.collection("tracking_number_user_relation");
+        match collection_relations
+            .delete_many(doc! {"chat_id": chat_id}, None)
+            .await
+        {
+            Ok(result) => println!(
+                "@TELEGRAM_WEBHOOK: removed from {}, deleted {} group relations",
+                chat_id, result.deleted_count
+            ),
+            Err(e) => println!("@TELEGRAM_WEBHOOK: error deleting group relations: {}", e),
+        }
+    }
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    WEBHOOK
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/*
+    Telegram sends the bot updates here after the webhook is set with the same secret token as TELEGRAM_WEBHOOK_SECRET,
+    always answer 200 when the update is from telegram otherwise it keeps sending the same update again
+*/
+
+#[post("/webhook_telegram")]
+pub async fn handle_telegram_update(
+    data: web::Data<AppState>,
+    client: web::Data<Client>,
+    request: HttpRequest,
+    update: web::Json<Update>,
+) -> impl Responder {
+    // check the secret token to verify it's from telegram
+    let secret_token = request
+        .headers()
+        .get("X-Telegram-Bot-Api-Secret-Token")
+        .and_then(|header| header.to_str().ok());
+    if secret_token != Some(data.telegram_webhook_secret.as_str()) {
+        println!("@TELEGRAM_WEBHOOK: secret token missing or wrong");
+        return HttpResponse::Unauthorized().finish();
+    }
+    //
+
+    match update.into_inner().kind {
+        UpdateKind::Message(message) if message.chat.is_group() || message.chat.is_supergroup() => {
+            handle_group_command(client, data, message).await
+        }
+        UpdateKind::MyChatMember(membership) => {
+            handle_bot_membership_change(client, data, membership).await
+        }
+        _ => (),
+    }
+
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,348 @@
+/*
+    Cargo stuff
+*/
+
+use crate::{
+    carriers::carrier_directory,
+    my_structs::tracking_data_formats::{
+        status_values::{main_status, sub_status},
+        tracking_data_base::{Status, TrackInfo},
+    },
+    notifications::escape_html,
+};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// updates the user has to know about right away and usually do something about, they're sent as high priority
+/// notifications with their own wording instead of the plain update message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum alert_kind {
+    // also customs holding the parcel until it gets information, which 17TRACK counts as in transit
+    Exception,
+    DeliveryFailure,
+    AvailableForPickup,
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct tracking_alert {
+    pub kind: alert_kind,
+    pub sub_status: Option<sub_status>,
+}
+
+/// how to reach the carrier that has the parcel, from the provider 17TRACK gave or else the carrier directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct carrier_contact {
+    pub name: Option<String>,
+    pub tel: Option<String>,
+    pub homepage: Option<String>,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+impl tracking_alert {
+    /// The alert for an update that moved the number into one of the alert kinds or to another sub status of it, none for
+    /// the routine updates and for new scans that leave the status as it was, the previous status is none when the number
+    /// wasn't saved before
+    pub fn from_transition(previous: Option<&Status>, current: &Status) -> Option<tracking_alert> {
+        let kind = match (current.status.as_ref()?, current.sub_status.as_ref()) {
+            (_, Some(sub_status::InTransitCustomsRequiringInformation)) => alert_kind::Exception,
+            (main_status::Exception, _) => alert_kind::Exception,
+            (main_status::DeliveryFailure, _) => alert_kind::DeliveryFailure,
+            (main_status::AvailableForPickup, _) => alert_kind::AvailableForPickup,
+            (main_status::Expired, _) => alert_kind::Expired,
+            _ => return None,
+        };
+        let unchanged = previous.is_some_and(|previous| {
+            previous.status == current.status && previous.sub_status == current.sub_status
+        });
+        if unchanged {
+            return None;
+        }
+        Some(tracking_alert {
+            kind,
+            sub_status: current.sub_status.clone(),
+        })
+    }
+
+    fn headline(&self) -> &'static str {
+        match (self.kind, self.sub_status.as_ref()) {
+            (_, Some(sub_status::InTransitCustomsRequiringInformation)) => {
+                "Customs is holding your parcel"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionReturning)) => {
+                "Your parcel is going back to the sender"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionReturned)) => {
+                "Your parcel was returned to the sender"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionNoBody)) => {
+                "The carrier couldn't find anyone to hand your parcel to"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionSecurity)) => {
+                "Your parcel is held by customs or a security check"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionDamage)) => "Your parcel was damaged",
+            (alert_kind::Exception, Some(sub_status::ExceptionRejected)) => {
+                "Your parcel was refused"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionDelayed)) => "Your parcel is delayed",
+            (alert_kind::Exception, Some(sub_status::ExceptionLost)) => "Your parcel is lost",
+            (alert_kind::Exception, Some(sub_status::ExceptionDestroyed)) => {
+                "Your parcel was destroyed"
+            }
+            (alert_kind::Exception, Some(sub_status::ExceptionCancel)) => {
+                "Your shipment was cancelled"
+            }
+            (alert_kind::Exception, _) => "There's a problem with your parcel",
+            (alert_kind::DeliveryFailure, Some(sub_status::DeliveryFailureNoBody)) => {
+                "Delivery failed, nobody was there to receive your parcel"
+            }
+            (alert_kind::DeliveryFailure, Some(sub_status::DeliveryFailureSecurity)) => {
+                "Delivery failed because of customs or a security check"
+            }
+            (alert_kind::DeliveryFailure, Some(sub_status::DeliveryFailureRejected)) => {
+                "Delivery failed, the parcel was refused"
+            }
+            (alert_kind::DeliveryFailure, Some(sub_status::DeliveryFailureInvalidAddress)) => {
+                "Delivery failed because of the address"
+            }
+            (alert_kind::DeliveryFailure, _) => "Delivery failed",
+            (alert_kind::AvailableForPickup, _) => "Your parcel is ready for pickup",
+            (alert_kind::Expired, _) => "Your parcel has had no updates for a long time",
+        }
+    }
+
+    fn suggested_action(&self) -> &'static str {
+        match (self.kind, self.sub_status.as_ref()) {
+            (
+                _,
+                Some(
+                    sub_status::InTransitCustomsRequiringInformation
+                    | sub_status::ExceptionSecurity
+                    | sub_status::DeliveryFailureSecurity,
+                ),
+            ) => "Customs may need documents or a payment from you, look for a message from the carrier or contact them.",
+            (
+                alert_kind::Exception,
+                Some(
+                    sub_status::ExceptionReturning
+                    | sub_status::ExceptionReturned
+                    | sub_status::ExceptionLost
+                    | sub_status::ExceptionDestroyed
+                    | sub_status::ExceptionCancel,
+                ),
+            ) => "Contact the seller about a refund or sending it again.",
+            (alert_kind::Exception, Some(sub_status::ExceptionDamage)) => {
+                "Report the damage to the seller and the carrier, keep the packaging if it still reaches you."
+            }
+            (
+                _,
+                Some(sub_status::ExceptionRejected | sub_status::DeliveryFailureRejected),
+            ) => "If you didn't refuse it, contact the carrier to have it delivered again.",
+            (alert_kind::Exception, Some(sub_status::ExceptionDelayed)) => {
+                "Nothing to do yet, contact the carrier if it doesn't move in the next days."
+            }
+            (alert_kind::DeliveryFailure, Some(sub_status::DeliveryFailureInvalidAddress)) => {
+                "Check the address and give the carrier the right one before it goes back to the sender."
+            }
+            (alert_kind::Exception | alert_kind::DeliveryFailure, _) => {
+                "Contact the carrier to arrange a new delivery or a pickup."
+            }
+            (alert_kind::AvailableForPickup, _) => {
+                "Pick it up before the carrier sends it back, bring an ID and the tracking number."
+            }
+            (alert_kind::Expired, _) => {
+                "Ask the seller or the carrier where the parcel is, it may be lost."
+            }
+        }
+    }
+
+    /// Build the alert message for the chat window and notification banner, the parcel is the number or the user's label
+    /// already in the message format, the description is the latest event
+    pub fn message(&self, parcel: &str, description: &str, contact: &carrier_contact) -> String {
+        let mut message = format!("<b>{}</b>: {}", self.headline(), parcel);
+        if !description.is_empty() {
+            message += &format!("\n{}", escape_html(description));
+        }
+        message += &format!("\n\n{}", self.suggested_action());
+        if let Some(contact) = contact.message_line() {
+            message += &format!("\n{}", contact);
+        }
+        message
+    }
+}
+
+impl carrier_contact {
+    /// The contact of the carrier 17TRACK put first in the update, the fields it left out come from the carrier directory
+    /// under the provider's key or the key the number was registered with
+    pub fn from_track_info(
+        track_info: &TrackInfo,
+        carrier_key: i32,
+        carriers: &carrier_directory,
+    ) -> carrier_contact {
+        let provider = track_info
+            .tracking
+            .providers
+            .first()
+            .map(|provider| &provider.provider);
+        let directory_entry = carriers.get(
+            provider
+                .and_then(|provider| provider.key)
+                .unwrap_or(carrier_key),
+        );
+        let non_empty = |value: Option<&String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        carrier_contact {
+            name: non_empty(provider.and_then(|provider| provider.name.as_ref()))
+                .or_else(|| directory_entry.map(|carrier| carrier.name.clone())),
+            tel: non_empty(provider.and_then(|provider| provider.tel.as_ref()))
+                .or_else(|| non_empty(directory_entry.and_then(|carrier| carrier.tel.as_ref()))),
+            homepage: non_empty(provider.and_then(|provider| provider.homepage.as_ref())).or_else(
+                || non_empty(directory_entry.and_then(|carrier| carrier.homepage.as_ref())),
+            ),
+        }
+    }
+
+    // "Contact DHL: +49 228 28609898, https://www.dhl.de", none when there's neither a phone nor a homepage
+    fn message_line(&self) -> Option<String> {
+        let ways = [&self.tel, &self.homepage]
+            .into_iter()
+            .flatten()
+            .map(|way| escape_html(way))
+            .collect::<Vec<_>>();
+        if ways.is_empty() {
+            return None;
+        }
+        let name = self.name.as_deref().unwrap_or("the carrier");
+        Some(format!(
+            "Contact {}: {}",
+            escape_html(name),
+            ways.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(status: main_status, sub_status: sub_status) -> Status {
+        Status {
+            status: Some(status),
+            sub_status: Some(sub_status),
+            sub_status_descr: None,
+        }
+    }
+
+    fn kind(previous: Option<&Status>, current: &Status) -> Option<alert_kind> {
+        tracking_alert::from_transition(previous, current).map(|alert| alert.kind)
+    }
+
+    #[test]
+    fn alert_statuses_give_their_kind() {
+        let in_transit = status(main_status::InTransit, sub_status::InTransitArrival);
+        let cases = [
+            (
+                status(main_status::Exception, sub_status::ExceptionReturning),
+                alert_kind::Exception,
+            ),
+            (
+                status(
+                    main_status::DeliveryFailure,
+                    sub_status::DeliveryFailureNoBody,
+                ),
+                alert_kind::DeliveryFailure,
+            ),
+            (
+                status(
+                    main_status::AvailableForPickup,
+                    sub_status::AvailableForPickupOther,
+                ),
+                alert_kind::AvailableForPickup,
+            ),
+            (
+                status(main_status::Expired, sub_status::ExpiredOther),
+                alert_kind::Expired,
+            ),
+        ];
+        for (current, expected) in cases {
+            assert_eq!(kind(Some(&in_transit), &current), Some(expected));
+        }
+    }
+
+    #[test]
+    fn customs_requiring_information_is_an_exception() {
+        let customs = status(
+            main_status::InTransit,
+            sub_status::InTransitCustomsRequiringInformation,
+        );
+        let alert = tracking_alert::from_transition(None, &customs).unwrap();
+        assert_eq!(alert.kind, alert_kind::Exception);
+        assert_eq!(
+            alert.sub_status,
+            Some(sub_status::InTransitCustomsRequiringInformation)
+        );
+    }
+
+    #[test]
+    fn routine_updates_give_no_alert() {
+        let picked_up = status(main_status::InTransit, sub_status::InTransitPickedUp);
+        for current in [
+            status(
+                main_status::InTransit,
+                sub_status::InTransitCustomsProcessing,
+            ),
+            status(main_status::OutForDelivery, sub_status::OutForDeliveryOther),
+            status(main_status::Delivered, sub_status::DeliveredOther),
+        ] {
+            assert_eq!(kind(Some(&picked_up), &current), None);
+        }
+        let no_status = Status {
+            status: None,
+            sub_status: None,
+            sub_status_descr: None,
+        };
+        assert_eq!(kind(None, &no_status), None);
+    }
+
+    #[test]
+    fn new_scans_with_the_same_status_give_no_alert() {
+        let failed = status(
+            main_status::DeliveryFailure,
+            sub_status::DeliveryFailureNoBody,
+        );
+        assert_eq!(kind(Some(&failed), &failed), None);
+    }
+
+    #[test]
+    fn another_sub_status_of_the_same_kind_alerts_again() {
+        let returning = status(main_status::Exception, sub_status::ExceptionReturning);
+        let returned = status(main_status::Exception, sub_status::ExceptionReturned);
+        let alert = tracking_alert::from_transition(Some(&returning), &returned).unwrap();
+        assert_eq!(alert.kind, alert_kind::Exception);
+        assert_eq!(alert.sub_status, Some(sub_status::ExceptionReturned));
+    }
+
+    #[test]
+    fn first_save_of_an_alert_status_alerts() {
+        let pickup = status(
+            main_status::AvailableForPickup,
+            sub_status::AvailableForPickupOther,
+        );
+        assert_eq!(kind(None, &pickup), Some(alert_kind::AvailableForPickup));
+    }
+}
@@ -0,0 +1,248 @@
+/*
+    Cargo stuff
+*/
+
+use crate::my_structs::tracking_data_formats::{
+    status_values::{main_status, sub_status},
+    tracking_data_base::delivery_estimate,
+    tracking_data_database_form::TrackingData_DBF,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOneAndReplaceOptions, FindOptions, ReturnDocument},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Constants
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+// most snapshots the history of one parcel responds with, the newest ones
+const MAX_HISTORY_SNAPSHOTS: i64 = 500;
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Structs
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+/// the state of a parcel at one point, the tracking_data_history collection gets one every time the status, the ETA or the
+/// latest event changes and nothing in it is ever changed or deleted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct tracking_snapshot {
+    pub tracking_number: String,
+    pub recorded_at: bson::DateTime,
+    pub source: snapshot_source,
+    pub carrier: i32,
+    pub status: Option<main_status>,
+    pub sub_status: Option<sub_status>,
+    pub sub_status_descr: Option<String>,
+    pub estimated_delivery_date: delivery_estimate,
+    pub latest_event_time: Option<String>,
+    pub latest_event_description: Option<String>,
+    pub latest_event_location: Option<String>,
+}
+
+/// where the change came from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum snapshot_source {
+    Webhook,
+    // pulled from the API by a handler
+    Pull,
+    // a manual shipment the user changed
+    Manual,
+    // the state saved before the history was kept, recorded when it first changed
+    Backfill,
+}
+
+/*
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+    Functions
+
+-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
+*/
+
+impl tracking_snapshot {
+    fn from_tracking_data(
+        tracking_data: &TrackingData_DBF,
+        source: snapshot_source,
+        recorded_at: bson::DateTime,
+    ) -> Self {
+        let track_info = &tracking_data.data.track_info;
+        tracking_snapshot {
+            tracking_number: tracking_data.data.number.clone(),
+            recorded_at,
+            source,
+            carrier: tracking_data.data.carrier,
+            status: track_info.latest_status.status.clone(),
+            sub_status: track_info.latest_status.sub_status.clone(),
+            sub_status_descr: track_info.latest_status.sub_status_descr.clone(),
+            estimated_delivery_date: track_info.time_metrics.estimated_delivery_date.clone(),
+            latest_event_time: track_info.latest_event.time_iso.clone(),
+            latest_event_description: track_info.latest_event.description.clone(),
+            latest_event_location: track_info.latest_event.location.clone(),
+        }
+    }
+
+    /// same status, ETA and latest event, the time and the source don't count
+    fn same_state(&self, other: &tracking_snapshot) -> bool {
+        let eta = &self.estimated_delivery_date;
+        let other_eta = &other.estimated_delivery_date;
+        self.carrier == other.carrier
+            && self.status == other.status
+            && self.sub_status == other.sub_status
+            && self.sub_status_descr == other.sub_status_descr
+            && eta.source == other_eta.source
+            && eta.from == other_eta.from
+            && eta.to == other_eta.to
+            && self.latest_event_time == other.latest_event_time
+            && self.latest_event_description == other.latest_event_description
+            && self.latest_event_location == other.latest_event_location
+    }
+
+    /// the snapshot for the client, with the time as RFC 3339
+    pub fn convert_to_client_form(&self) -> serde_json::Value {
+        serde_json::json!({
+            "recorded_at": self.recorded_at.try_to_rfc3339_string().ok(),
+            "source": self.source,
+            "carrier": self.carrier,
+            "status": self.status,
+            "sub_status": self.sub_status,
+            "sub_status_descr": self.sub_status_descr,
+            "estimated_delivery_date": self.estimated_delivery_date,
+            "latest_event_time": self.latest_event_time,
+            "latest_event_description": self.latest_event_description,
+            "latest_event_location": self.latest_event_location,
+        })
+    }
+}
+
+/// Save the tracking data of a parcel over the old one in one step, so there's never a moment without it, and add a
+/// snapshot to the history when something changed, the first change of a parcel saved before the history existed also
+/// records the state it had, the history is best effort and its errors are only printed, returns the tracking data that
+/// was replaced
+pub async fn save_tracking_data(
+    client: &Client,
+    tracking_data: &TrackingData_DBF,
+    source: snapshot_source,
+) -> Result<Option<TrackingData_DBF>, mongodb::error::Error> {
+    let filter = doc! {"data.number": &tracking_data.data.number};
+    let previous = replace_tracking_data(client, tracking_data, filter, true).await?;
+    record_snapshot(client, tracking_data, previous.as_ref(), source).await;
+    Ok(previous)
+}
+
+/// Same as @save_tracking_data but only over the tracking data that was last saved at the time it was read, for the
+/// changes made from what the user saw, false when it was saved again in the meantime and nothing was changed
+pub async fn save_tracking_data_if_unchanged(
+    client: &Client,
+    tracking_data: &TrackingData_DBF,
+    read_updated_at: Option<bson::DateTime>,
+    source: snapshot_source,
+) -> Result<bool, mongodb::error::Error> {
+    let filter = doc! {"data.number": &tracking_data.data.number, "updated_at": read_updated_at};
+    match replace_tracking_data(client, tracking_data, filter, false).await? {
+        Some(previous) => {
+            record_snapshot(client, tracking_data, Some(&previous), source).await;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// replace the current state and get the one before it
+async fn replace_tracking_data(
+    client: &Client,
+    tracking_data: &TrackingData_DBF,
+    filter: Document,
+    upsert: bool,
+) -> Result<Option<TrackingData_DBF>, mongodb::error::Error> {
+    let db = client.database("teletrack");
+    let collection_tracking_data: mongodb::Collection<Document> = db.collection("tracking_data");
+    let options = FindOneAndReplaceOptions::builder()
+        .upsert(upsert)
+        .return_document(ReturnDocument::Before)
+        .build();
+    Ok(collection_tracking_data
+        .find_one_and_replace(filter, bson::to_document(tracking_data)?, options)
+        .await?
+        .and_then(|previous| bson::from_document::<TrackingData_DBF>(previous).ok()))
+}
+
+// add the snapshot if something changed
+async fn record_snapshot(
+    client: &Client,
+    tracking_data: &TrackingData_DBF,
+    previous: Option<&TrackingData_DBF>,
+    source: snapshot_source,
+) {
+    let db = client.database("teletrack");
+    let collection_history: mongodb::Collection<tracking_snapshot> =
+        db.collection("tracking_data_history");
+
+    let snapshot =
+        tracking_snapshot::from_tracking_data(tracking_data, source, bson::DateTime::now());
+    let previous_snapshot = previous.map(|previous| {
+        let recorded_at = previous.updated_at.unwrap_or_else(bson::DateTime::now);
+        tracking_snapshot::from_tracking_data(previous, snapshot_source::Backfill, recorded_at)
+    });
+    if previous_snapshot
+        .as_ref()
+        .is_some_and(|previous_snapshot| previous_snapshot.same_state(&snapshot))
+    {
+        return;
+    }
+    let mut snapshots = vec![snapshot];
+    if let Some(previous_snapshot) = previous_snapshot {
+        let filter = doc! {"tracking_number": &tracking_data.data.number};
+        match collection_history.count_documents(filter, None).await {
+            Ok(0) => snapshots.insert(0, previous_snapshot),
+            Ok(_) => (),
+            Err(e) => println!("@SAVE_TRACKING_DATA: error counting the history: {}", e),
+        }
+    }
+    if let Err(e) = collection_history.insert_many(snapshots, None).await {
+        println!("@SAVE_TRACKING_DATA: error adding to the history: {}", e);
+    }
+}
+
+/// Delete the history of a parcel together with its tracking data
+pub async fn delete_tracking_history(
+    client: &Client,
+    tracking_number: &str,
+) -> Result<(), mongodb::error::Error> {
+    let db = client.database("teletrack");
+    let collection_history: mongodb::Collection<Document> = db.collection("tracking_data_history");
+    collection_history
+        .delete_many(doc! {"tracking_number": tracking_number}, None)
+        .await?;
+    Ok(())
+}
+
+/// The history of a parcel, oldest first
+pub async fn tracking_history(
+    client: &Client,
+    tracking_number: &str,
+) -> Result<Vec<tracking_snapshot>, mongodb::error::Error> {
+    let db = client.database("teletrack");
+    let collection_history: mongodb::Collection<tracking_snapshot> =
+        db.collection("tracking_data_history");
+    let options = FindOptions::builder()
+        .sort(doc! {"recorded_at": -1})
+        .limit(MAX_HISTORY_SNAPSHOTS)
+        .build();
+    let mut snapshots: Vec<tracking_snapshot> = collection_history
+        .find(doc! {"tracking_number": tracking_number}, options)
+        .await?
+        .try_collect()
+        .await?;
+    snapshots.reverse();
+    Ok(snapshots)
+}
@@ -4,7 +4,12 @@
 
 // TODO: mind which format is imported
 use crate::{
+    my_structs::tracking_data_formats::change_carrier_response::ChangeCarrierResponse as change_carrier_response,
+    my_structs::tracking_data_formats::change_info_response::ChangeInfoResponse as change_info_response,
     my_structs::tracking_data_formats::delete_tracking_number_response::DeleteTrackingResponseNumber as delete_tracking_number_response,
+    my_structs::tracking_data_formats::quota_response::{
+        QuotaData as quota_data, QuotaResponse as quota_response,
+    },
     my_structs::tracking_data_formats::register_tracking_number_response::RegisterResponse as register_tracking_number_response,
     my_structs::tracking_data_formats::retrack_stopped_number_response::RetrackStoppedNumberResponse as retrack_stopped_number_response,
     my_structs::tracking_data_formats::stop_tracking_response::StopTrackingResponse as stop_tracking_response,
@@ -13,7 +18,21 @@ use crate::{
 };
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/*
+    Constants
+*/
+
+// how long the account quota from the API is kept before asking again, registrations made in between are counted locally
+const QUOTA_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+// 17TRACK rejection codes for a carrier that needs the extra parameter (postal code, phone digits, ship date) and for a
+// parameter it can't use, see the error codes in https://api.17track.net/en/doc?version=v2.2
+const CARRIER_PARAM_REQUIRED_CODES: [i32; 2] = [-18010018, -18010019];
 
 /*
     Structs
@@ -51,6 +70,14 @@ pub enum tracking_error {
     NumberNotFound,
     #[error("the number you are trying to register is already registered")]
     TrackingAlreadyRegistered,
+    #[error(
+        "the API didn't change the carrier, it's already set or it was changed too many times"
+    )]
+    CarrierChangeRejected,
+    #[error("the carrier needs an extra parameter to track the number: {0}")]
+    CarrierParamRequired(String),
+    #[error("the API didn't change the info of the number")]
+    ChangeInfoRejected,
     #[error("Request error: {0}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("Serde error: {0}")]
@@ -66,17 +93,39 @@ pub struct tracking_client {
     client: Client,
     api_key: String,
     base_url: String,
+    // the last quota from the API and when it was fetched
+    quota_cache: Mutex<Option<(Instant, quota_data)>>,
 }
 
-// struct for getting a tracking number + carrier (optional) from client
+// struct for getting a tracking number + carrier (optional) from client, the param is for the carriers that need extra
+// data to track, it's sent to the API as just the value
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct tracking_number_carrier {
+    #[serde(deserialize_with = "crate::number_detection::deserialize_tracking_number")]
     pub number: String,
     pub carrier: Option<i32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "carrier_param::serialize_value"
+    )]
+    pub param: Option<carrier_param>,
+}
+// extra data some carriers need to track a number, from the client as {"type": "postal_code", "value": "12345"}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum carrier_param {
+    // destination postal code
+    PostalCode(String),
+    // last 4 digits of the recipient's or sender's phone number
+    PhoneDigits(String),
+    // YYYY-MM-DD
+    ShipDate(String),
 }
 // just the tracking number
 #[derive(Serialize, Deserialize, Debug)]
 pub struct just_the_tracking_number {
+    #[serde(deserialize_with = "crate::number_detection::deserialize_tracking_number")]
     pub number: String,
 }
 
@@ -86,6 +135,58 @@ pub struct just_the_tracking_number {
 
 */
 
+impl carrier_param {
+    /// the value the API gets
+    pub fn value(&self) -> &str {
+        match self {
+            carrier_param::PostalCode(value)
+            | carrier_param::PhoneDigits(value)
+            | carrier_param::ShipDate(value) => value,
+        }
+    }
+
+    /// Check the value fits the type, returns the reason if it doesn't
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            carrier_param::PostalCode(value) => {
+                if value.is_empty()
+                    || value.len() > 10
+                    || !value.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ')
+                {
+                    return Err("postal code has to be up to 10 letters and digits");
+                }
+            }
+            carrier_param::PhoneDigits(value) => {
+                if value.len() != 4 || !value.chars().all(|c| c.is_ascii_digit()) {
+                    return Err("phone digits have to be the last 4 digits of the phone number");
+                }
+            }
+            carrier_param::ShipDate(value) => {
+                if chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+                    return Err("ship date has to be YYYY-MM-DD");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// For #[serde(serialize_with)], the API takes the param as a plain string
+    fn serialize_value<S>(param: &Option<carrier_param>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match param {
+            Some(param) => serializer.serialize_some(param.value()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// the rejection is about the extra parameter of the carrier
+fn carrier_param_required(code: i32) -> bool {
+    CARRIER_PARAM_REQUIRED_CODES.contains(&code)
+}
+
 impl tracking_client {
     /// initializer
     pub fn new() -> Self {
@@ -94,6 +195,7 @@ impl tracking_client {
             api_key: env::var("TRACK17_API_KEY")
                 .expect("TRACK17_API_KEY must be set in environment"),
             base_url: "https://api.17track.net/track/v2.2".to_string(),
+            quota_cache: Mutex::new(None),
         }
     }
 
@@ -144,6 +246,7 @@ impl tracking_client {
                         "number register success: {:?}",
                         response_data.data.accepted[0]
                     );
+                    self.count_registration();
                     Ok(response_data)
                 } else if Some(response_data.data.rejected.len()) == Some(1) {
                     // tracking rejected, limit reached or already registered
@@ -166,6 +269,16 @@ impl tracking_client {
                             println!("invalid data format sent to the API");
                             return Err(tracking_error::InvalidRegisterDataFormat);
                         }
+                        // the carrier needs a postal code, phone digits or similar
+                        code if carrier_param_required(code) => {
+                            println!(
+                                "number register error: carrier parameter required {:?}",
+                                response_data.data.rejected[0]
+                            );
+                            Err(tracking_error::CarrierParamRequired(
+                                response_data.data.rejected[0].error.message.clone(),
+                            ))
+                        }
                         _ => {
                             println!(
                                 "number register error: {:?}",
@@ -507,6 +620,239 @@ impl tracking_client {
         }
     }
 
+    /// Change the carrier parameter of a registered number, for carriers that need a postal code, phone digits or the like
+    pub async fn change_info(
+        &self,
+        tracking_number: &str,
+        carrier: Option<i32>,
+        param: &carrier_param,
+    ) -> Result<change_info_response, tracking_error> {
+        // Create the body for the HTTP request since the api doesn't use a web endpoint
+        // load the url, @ROUTE, api key and parameters into the URL and send it
+        let url = format!("{}/changeinfo", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("17token", &self.api_key)
+            .json(&serde_json::json!([{
+                "number": tracking_number,
+                "carrier": carrier,
+                "items": {"param": param.value()}
+            }]))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            println!("Error: {}", response.status());
+            return Err(tracking_error::UnexpectedError);
+        }
+
+        let body_bytes = &response.bytes().await?;
+
+        // Parse the json of the response into the structures created with the 17track api docs
+        // and return the @change_info_response instance
+        let response_data = serde_json::from_slice::<change_info_response>(body_bytes)?;
+        match response_data.code {
+            // success
+            0 => {
+                // Even though it's an array treat it always like only one tracking number has been passed,
+                // the array is just an API thing, it takes up to 40 numbers at once but here only one is always passed (in parameters)
+                if response_data.data.accepted.len() == 1 {
+                    println!("change info success: {:?}", response_data.data.accepted[0]);
+                    Ok(response_data)
+                } else if response_data.data.rejected.len() == 1 {
+                    let rejected = &response_data.data.rejected[0];
+                    println!("change info rejected: {:?}", rejected);
+                    match rejected.error.code {
+                        -18019902 => Err(tracking_error::NumberNotFound),
+                        code if carrier_param_required(code) => Err(
+                            tracking_error::CarrierParamRequired(rejected.error.message.clone()),
+                        ),
+                        _ => Err(tracking_error::ChangeInfoRejected),
+                    }
+                } else {
+                    Err(tracking_error::UnexpectedError)
+                }
+            }
+            // error
+            1 => {
+                println!("{}: {:?}", response_data.code, response_data);
+                Err(tracking_error::UnexpectedError)
+            }
+            // unexpected error
+            _ => Err(tracking_error::UnexpectedError),
+        }
+    }
+
+    /// Get the quota of the 17TRACK account, cached for a few minutes since every registration checks it
+    pub async fn get_quota(&self) -> Result<quota_data, tracking_error> {
+        if let Some((fetched_at, quota)) =
+            &*self.quota_cache.lock().unwrap_or_else(|e| e.into_inner())
+        {
+            if fetched_at.elapsed() < QUOTA_CACHE_TTL {
+                return Ok(quota.clone());
+            }
+        }
+
+        // load the url, @ROUTE, api key and parameters into the URL and send it
+        let url = format!("{}/getquota", self.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("17token", &self.api_key)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            println!("Error: {}", response.status());
+            return Err(tracking_error::UnexpectedError);
+        }
+
+        let body_bytes = &response.bytes().await?;
+        let response_data = serde_json::from_slice::<quota_response>(body_bytes)?;
+        match response_data.code {
+            0 => {
+                *self.quota_cache.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some((Instant::now(), response_data.data.clone()));
+                Ok(response_data.data)
+            }
+            _ => {
+                println!("{}: {:?}", response_data.code, response_data);
+                Err(tracking_error::UnexpectedError)
+            }
+        }
+    }
+
+    /// Take a registration off the cached quota so the cache stays close to the API until it's fetched again
+    fn count_registration(&self) {
+        if let Some((_, quota)) = &mut *self.quota_cache.lock().unwrap_or_else(|e| e.into_inner()) {
+            quota.quota_used = quota.quota_used.map(|used| used + 1);
+            quota.quota_remain = quota.quota_remain.map(|remain| remain - 1);
+            quota.today_used = quota.today_used.map(|used| used + 1);
+        }
+    }
+
+    /// Get one page of every number registered on the API, page_no starts at 1 and the response says how many pages there
+    /// are, for comparing the API with the database (see jobs.rs)
+    pub async fn get_tracklist_page(
+        &self,
+        page_no: i32,
+    ) -> Result<number_status_check, tracking_error> {
+        // Create the body for the HTTP request since the api doesn't use a web endpoint
+        // load the url, @ROUTE, api key and parameters into the URL and send it
+        let url = format!("{}/gettracklist", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("17token", &self.api_key)
+            .json(&serde_json::json!({
+                "page_no": page_no
+            }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            println!("Error: {}", response.status());
+            return Err(tracking_error::UnexpectedError);
+        }
+
+        let body_bytes = &response.bytes().await?;
+
+        // Parse the json of the response into the structures created with the 17track api docs
+        // and return the @number_status_check instance, an empty page is fine here
+        let response_data = serde_json::from_slice::<number_status_check>(body_bytes)?;
+        match response_data.code {
+            0 => Ok(response_data),
+            _ => {
+                println!("{}: {:?}", response_data.code, response_data);
+                Err(tracking_error::UnexpectedError)
+            }
+        }
+    }
+
+    /// Change the carrier of a registered number when the API detected the wrong one, the old carrier is only needed when the
+    /// number is registered with more than one carrier
+    pub async fn change_carrier(
+        &self,
+        tracking_number: &str,
+        carrier_old: Option<i32>,
+        carrier_new: i32,
+    ) -> Result<change_carrier_response, tracking_error> {
+        // Create the body for the HTTP request since the api doesn't use a web endpoint
+        // load the url, @ROUTE, api key and parameters into the URL and send it
+        let url = format!("{}/changecarrier", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("17token", &self.api_key)
+            .json(&serde_json::json!([{
+                "number": tracking_number,
+                "carrier_old": carrier_old,
+                "carrier_new": carrier_new
+            }]))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            println!("Error: {}", response.status());
+            return Err(tracking_error::UnexpectedError);
+        }
+
+        let body_bytes = &response.bytes().await?;
+
+        // Parse the json of the response into the structures created with the 17track api docs
+        // and return the @change_carrier_response instance
+        let response_data = serde_json::from_slice::<change_carrier_response>(body_bytes)?;
+        match response_data.code {
+            // success
+            0 => {
+                // Even though it's an array treat it always like only one tracking number has been passed,
+                // the array is just an API thing, it takes up to 40 numbers at once but here only one is always passed (in parameters)
+                if response_data.data.accepted.len() == 1 {
+                    println!(
+                        "change carrier success: {:?}",
+                        response_data.data.accepted[0]
+                    );
+                    Ok(response_data)
+                } else if response_data.data.rejected.len() == 1 {
+                    match response_data.data.rejected[0].error.code {
+                        -18019902 => {
+                            println!(
+                                "change carrier error: number not registered {:?}",
+                                response_data.data.rejected[0]
+                            );
+                            Err(tracking_error::NumberNotFound)
+                        }
+                        // same carrier as before or the change limit of the number was reached
+                        _ => {
+                            println!(
+                                "change carrier rejected: {:?}",
+                                response_data.data.rejected[0]
+                            );
+                            Err(tracking_error::CarrierChangeRejected)
+                        }
+                    }
+                } else {
+                    Err(tracking_error::UnexpectedError)
+                }
+            }
+            // error
+            1 => {
+                println!("{}: {:?}", response_data.code, response_data);
+                Err(tracking_error::UnexpectedError)
+            }
+            // unexpected error
+            _ => Err(tracking_error::UnexpectedError),
+        }
+    }
+
     /// Get info about a tracking number (meta data)
     pub async fn get_number_metadata(
         &self,
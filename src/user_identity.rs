@@ -0,0 +1,160 @@
+/*
+    Cargo stuff
+*/
+
+use hex::encode;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/*
+    Constants
+*/
+
+// how long the init data the mini app gets from telegram is accepted after telegram signed it
+const MAX_INIT_DATA_AGE_SECONDS: i64 = 24 * 60 * 60;
+
+/*
+    Structs
+*/
+
+/// keyed hasher for turning telegram user IDs into the identifiers saved in the database, the identifier is also what the
+/// client sends back in the X-User-ID-Hash header so it has to be impossible to compute without the server secret
+pub struct user_id_hasher {
+    secret: Vec<u8>,
+    key_id: String,
+}
+
+#[derive(Error, Debug)]
+pub enum user_identity_error {
+    #[error("the user ID hash secret is missing or too short (at least 32 bytes)")]
+    InvalidSecret,
+    #[error("the telegram init data is malformed or its signature doesn't match")]
+    InvalidInitData,
+    #[error("the telegram init data is too old")]
+    ExpiredInitData,
+}
+
+/*
+    Functions
+*/
+
+impl user_id_hasher {
+    /// initializer, the key ID is a short fingerprint of the secret so the documents hashed with an older secret can be
+    /// found and re-hashed after the secret is rotated
+    pub fn new(secret: String) -> Result<Self, user_identity_error> {
+        if secret.len() < 32 {
+            return Err(user_identity_error::InvalidSecret);
+        }
+
+        Ok(Self {
+            key_id: encode(&Sha256::digest(secret.as_bytes())[..4]),
+            secret: secret.into_bytes(),
+        })
+    }
+
+    /// fingerprint of the secret currently in use
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// HMAC-SHA256 of the absolute user ID as hex string, the absolute value is kept from the old unkeyed format
+    pub fn hash_user_id(&self, user_id: i64) -> String {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(user_id.abs().to_string().as_bytes());
+        encode(mac.finalize().into_bytes())
+    }
+
+    /// sign a payload so it can be handed to a client and trusted when it comes back, the token is the payload followed by
+    /// the signature after the last dot
+    pub fn sign_token(&self, payload: &str) -> String {
+        format!(
+            "{}.{}",
+            payload,
+            encode(self.token_mac(payload).finalize().into_bytes())
+        )
+    }
+
+    /// check the signature of a token made with @sign_token and return the payload if it's valid
+    pub fn verify_token<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        self.token_mac(payload)
+            .verify_slice(&signature)
+            .ok()
+            .map(|_| payload)
+    }
+
+    /// the tokens use the same secret as the user IDs, the prefix keeps a token signature from ever matching a user ID hash
+    fn token_mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(b"token:");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Check the init data telegram hands the mini app (Telegram.WebApp.initData) was signed with the bot token and is recent,
+/// and return the ID of the user it was made for, this is the only proof of who the user is that the client can't make up
+/// https://core.telegram.org/bots/webapps#validating-data-received-via-the-mini-app
+pub fn verify_telegram_init_data(
+    init_data: &str,
+    bot_token: &str,
+) -> Result<i64, user_identity_error> {
+    // split the query string, the hash is left out of the checked fields
+    let mut hash = None;
+    let mut fields = Vec::new();
+    for pair in init_data.split('&') {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or(user_identity_error::InvalidInitData)?;
+        let value = urlencoding::decode(value)
+            .map_err(|_| user_identity_error::InvalidInitData)?
+            .into_owned();
+        match key {
+            "hash" => hash = Some(value),
+            _ => fields.push((key.to_string(), value)),
+        }
+    }
+    let hash = hex::decode(hash.ok_or(user_identity_error::InvalidInitData)?)
+        .map_err(|_| user_identity_error::InvalidInitData)?;
+    fields.sort();
+    //
+
+    // the key is the HMAC of the bot token keyed with "WebAppData", the signature is over the sorted "key=value" lines
+    let data_check_string = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut secret_key =
+        HmacSha256::new_from_slice(b"WebAppData").expect("HMAC accepts keys of any length");
+    secret_key.update(bot_token.as_bytes());
+    let mut mac = HmacSha256::new_from_slice(&secret_key.finalize().into_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(data_check_string.as_bytes());
+    mac.verify_slice(&hash)
+        .map_err(|_| user_identity_error::InvalidInitData)?;
+    //
+
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let auth_date = field("auth_date")
+        .and_then(|auth_date| auth_date.parse::<i64>().ok())
+        .ok_or(user_identity_error::InvalidInitData)?;
+    if chrono::Utc::now().timestamp() - auth_date > MAX_INIT_DATA_AGE_SECONDS {
+        return Err(user_identity_error::ExpiredInitData);
+    }
+    field("user")
+        .and_then(|user| serde_json::from_str::<serde_json::Value>(user).ok())
+        .and_then(|user| user.get("id").and_then(|id| id.as_i64()))
+        .ok_or(user_identity_error::InvalidInitData)
+}
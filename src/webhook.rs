@@ -1,17 +1,22 @@
 use crate::{
-    main,
-    my_structs::tracking_data_formats::{
-        tracking_data_database_form::TrackingData_DBF as tracking_data_database_form,
-        tracking_data_webhook_update::{
-            PackageDataWebhook, TrackingData, TrackingResponse as webhook_update,
-        },
+    database_pipelines, main,
+    my_structs::tracking_data_formats::tracking_data_base::Status,
+    my_structs::tracking_data_formats::tracking_data_webhook_update::{
+        PackageDataWebhook, TrackingData, TrackingResponse as webhook_update,
     },
+    notifications::{escape_html, notification_priority},
+    number_detection,
+    tracking_alerts::{carrier_contact, tracking_alert},
+    tracking_history::{self, snapshot_source},
     AppState,
 };
 use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
 use futures::{StreamExt, TryStreamExt};
 use hex::encode;
-use mongodb::{bson::doc, Client};
+use mongodb::{
+    bson::{doc, Document},
+    Client,
+};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
@@ -35,23 +40,12 @@ pub enum webhook_error {
     ErrorConvertingWebhookUpdate,
 }
 
-// struct for saving tracking number + carrier (optional) + user id hash as a relation record in the database
-// this also holds a bool that decides if the user is getting updates for the number or not
-// TODO: redundant with main
-#[derive(Serialize, Deserialize, Debug)]
-pub struct tracking_number_user_relation {
-    tracking_number: String,
-    carrier: Option<i32>,
-    user_id_hash: String,
-    is_subscribed: bool,
-}
-/// User structure
-// TODO: redundant with main
+/// chat to notify about an update of a tracking number and the label the follower gave it, result of the followers
+/// aggregation (see database_pipelines.rs)
 #[derive(Debug, Deserialize, Serialize)]
-struct user {
-    user_id: i64,
-    user_id_hash: String,
-    user_name: String,
+struct tracking_number_follower {
+    chat_id: i64,
+    label: Option<String>,
 }
 
 /*
@@ -68,12 +62,13 @@ pub async fn notify_of_tracking_event_update(
     user_id: i64,
     message: &str,
     tracking_number_that_was_updated: &str,
+    priority: notification_priority,
 ) -> Result<(), HttpResponse> {
     // access the service and deal with validation checks from the errors
     match &*data.notification_service {
         Ok(service) => {
             match service
-                .send_ma_notification(user_id, message, tracking_number_that_was_updated)
+                .send_ma_notification(user_id, message, tracking_number_that_was_updated, priority)
                 .await
             {
                 Ok(_) => Ok(()),
@@ -91,6 +86,27 @@ pub async fn notify_of_tracking_event_update(
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
 */
 
+/// build the message that will be displayed in the chat window and notification banner, the user's own label for the
+/// number goes in front of it if they set one
+pub fn tracking_update_message(
+    tracking_number: &str,
+    label: Option<&str>,
+    description: &str,
+) -> String {
+    "Update on your order tracking: ".to_string()
+        + &parcel_name(tracking_number, label)
+        + "\n"
+        + &escape_html(description)
+}
+
+/// the parcel as the messages name it, the user's label with the number or just the number
+fn parcel_name(tracking_number: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("<b>{}</b> ({})", escape_html(label), tracking_number),
+        None => tracking_number.to_string(),
+    }
+}
+
 /// consume the body and return whatever the text is as a @String, skip the extractor for consistency with the API sign
 async fn get_raw_body_as_string(body: web::Bytes) -> Result<String, webhook_error> {
     // get a string from the bytes
@@ -158,11 +174,12 @@ async fn verify_origin_body(
     }
 }
 
-/// Function used by webhook, takes the webhook update format of tracking update, converts to database form and refreshed the entry in the database
+/// Function used by webhook, takes the webhook update format of tracking update, converts to database form and refreshed the entry in the database,
+/// returns the status the number had before the update, none if it wasn't saved yet
 async fn refresh_tracking_info_from_webhook_update(
     client: web::Data<Client>,
     tracking_info_update: PackageDataWebhook,
-) -> Result<(), HttpResponse> {
+) -> Result<Option<Status>, HttpResponse> {
     // convert the webhook_update_accepted_package to tracking_data_database_form
     let tracking_data_database_form = match tracking_info_update.convert_to_tracking_data_dbf() {
         Some(data) => Ok(data),
@@ -171,42 +188,21 @@ async fn refresh_tracking_info_from_webhook_update(
     .unwrap();
     //
 
-    // set database
-    let db = client.database("teletrack");
-    // set collection
-    let collection_tracking_data: mongodb::Collection<tracking_data_database_form> =
-        db.collection("tracking_data");
-    // set filter to search for old data
-    let filter = doc! {"data.number": &tracking_data_database_form.data.number};
-    // delete any previous info with that tracking number
-    let _ = match collection_tracking_data.delete_many(filter, None).await {
-        Ok(delete_result) => {
-            println!("deleted {} tracking data docs", delete_result.deleted_count);
-            Ok(())
-        }
-        Err(e) => {
-            println!(
-                "@REFRESH_TRACKING_DATA: non fatal error deleting old tracking data: {}",
-                e
-            );
-            Err(e)
-        }
-    };
-    //
-
-    // insert the fresh tracking info into the database
-    match collection_tracking_data
-        .insert_one(tracking_data_database_form.clone(), None)
-        .await
+    // replace the previous info with the fresh one in one step and keep the history
+    match tracking_history::save_tracking_data(
+        &client,
+        &tracking_data_database_form,
+        snapshot_source::Webhook,
+    )
+    .await
     {
-        Ok(_) => {
-            // returning the fresh tracking info to the user
-            println!("tracking data inserted");
-            Ok(())
+        Ok(previous) => {
+            println!("tracking data saved");
+            Ok(previous.map(|previous| previous.data.track_info.latest_status))
         }
         Err(e) => {
             println!(
-                "@WEBHOOK_UPDATE_DATABASE: error inserting tracking data: {}",
+                "@WEBHOOK_UPDATE_DATABASE: error saving tracking data: {}",
                 e
             );
             Err(HttpResponse::InternalServerError().body(e.to_string()))
@@ -214,77 +210,114 @@ async fn refresh_tracking_info_from_webhook_update(
     }
 }
 
-/// Function to get all users related to the tracking number from the database
-// TODO: @$lookup doc joint search actual SQL
-async fn get_user_ids_related_to_tracking_number(
+/// Function to get all users related to the tracking number from the database, together with the label each user gave it,
+/// the IDs are chat IDs, for users that's the same as the user ID and the group chats linked to the number are included
+pub async fn get_user_ids_related_to_tracking_number(
     client: web::Data<Client>,
     tracking_number: String,
-) -> Result<Vec<i64>, HttpResponse> {
-    // set database, collection and filter
+) -> Result<Vec<(i64, Option<String>)>, HttpResponse> {
+    // set database and collection
     let db = client.database("teletrack");
-    let collection_relations: mongodb::Collection<tracking_number_user_relation> =
+    let collection_relations: mongodb::Collection<Document> =
         db.collection("tracking_number_user_relation");
-    let filter_find_hash = doc! {"tracking_number": &tracking_number, "is_subscribed": true};
 
-    // get the result of the search
-    let relation_cursor = match collection_relations.find(filter_find_hash, None).await {
-        Ok(cursor) => Ok(cursor),
+    // join the subscribed relation records with the users
+    let pipeline = database_pipelines::tracking_number_followers_pipeline(&tracking_number);
+    let followers_cursor = match collection_relations.aggregate(pipeline, None).await {
+        Ok(cursor) => cursor,
         Err(e) => {
-            println!("database error 1: {}", e);
-            Err(HttpResponse::InternalServerError().body(e.to_string()))
+            println!("@GET_USER_IDS_RELATED_TO_TRACKING_NUMBER: {}", e);
+            return Err(HttpResponse::InternalServerError().body(e.to_string()));
         }
-    }
-    .unwrap();
-    //
-
-    // convert the result of the search into a vector of user id hashes
-    let user_id_hashes: Vec<String> = match relation_cursor
-        .try_collect::<Vec<tracking_number_user_relation>>()
-        .await
-    {
-        Ok(relations) => Ok(relations.into_iter().map(|r| r.user_id_hash).collect()),
+    };
+    let followers: Vec<Document> = match followers_cursor.try_collect().await {
+        Ok(followers) => followers,
         Err(e) => {
-            println!("database error 2: {}", e);
-            Err(HttpResponse::InternalServerError().body(e.to_string()))
+            println!("@GET_USER_IDS_RELATED_TO_TRACKING_NUMBER: {}", e);
+            return Err(HttpResponse::InternalServerError().body(e.to_string()));
         }
-    }
-    .unwrap();
+    };
     //
 
-    // set new collection and filter to look for true user IDs
-    let collection_users: mongodb::Collection<user> = db.collection("users");
-    let filter_find_id = doc! { "user_id_hash": { "$in": &user_id_hashes } };
+    // convert the result into a vector of chat IDs and labels
+    followers
+        .into_iter()
+        .map(
+            |follower| match mongodb::bson::from_document::<tracking_number_follower>(follower) {
+                Ok(follower) => Ok((follower.chat_id, follower.label)),
+                Err(e) => {
+                    println!("@GET_USER_IDS_RELATED_TO_TRACKING_NUMBER: {}", e);
+                    Err(HttpResponse::InternalServerError().body(e.to_string()))
+                }
+            },
+        )
+        .collect()
+    //
+}
+
+/// Function to send the summary of the updated number to the open mini apps of every user tracking it, failures are only
+/// logged since the telegram notification goes out anyway
+async fn publish_live_update(
+    data: web::Data<AppState>,
+    client: web::Data<Client>,
+    package_update: &PackageDataWebhook,
+) {
+    // nobody has the mini app open
+    if !data.live_updates.has_subscribers() {
+        return;
+    }
+
+    // set database, collection and filter
+    let db = client.database("teletrack");
+    let collection_relations: mongodb::Collection<Document> =
+        db.collection("tracking_number_user_relation");
+    let filter = doc! {"tracking_number": &package_update.number, "chat_id": null};
 
-    // get the result of the search
-    let user_cursor = match collection_users.find(filter_find_id, None).await {
-        Ok(cursor) => Ok(cursor),
+    // get the users tracking the number
+    let relations = match collection_relations.find(filter, None).await {
+        Ok(cursor) => cursor.try_collect::<Vec<Document>>().await,
+        Err(e) => Err(e),
+    };
+    let relations = match relations {
+        Ok(relations) => relations,
         Err(e) => {
-            println!("database error 3: {}", e);
-            Err(HttpResponse::InternalServerError().body(e.to_string()))
+            println!("@PUBLISH_LIVE_UPDATE: {}", e);
+            return;
         }
-    }
-    .unwrap();
+    };
     //
 
-    // convert the result of the search into a vector of user IDs and return
-    match user_cursor.try_collect::<Vec<user>>().await {
-        Ok(users) => Ok(users.into_iter().map(|u| u.user_id).collect::<Vec<i64>>()),
-        Err(e) => {
-            println!("database error 4: {}", e);
-            Err(HttpResponse::InternalServerError().body(e.to_string()))
+    let summary = match package_update.convert_to_tracking_data_dbf() {
+        Some(tracking_data) => {
+            let mut summary = tracking_data.convert_to_summary_form();
+            if summary.carrier_name.is_none() {
+                summary.carrier_name = data
+                    .carrier_directory
+                    .get(tracking_data.data.carrier)
+                    .map(|carrier| carrier.name.clone());
+            }
+            summary
+        }
+        None => return,
+    };
+    let event = serde_json::json!({"tracking_number": &package_update.number, "summary": summary});
+    for relation in relations {
+        if let Ok(user_id_hash) = relation.get_str("user_id_hash") {
+            data.live_updates
+                .publish(user_id_hash, "tracking_update", &event);
         }
     }
-    //
 }
 
-/// Function to send notifications to all users from a vector of user ids
-async fn send_notifications_to_users(
+/// Function to send notifications to all users and group chats from a vector of chat ids and the message built for each of them
+pub async fn send_notifications_to_users(
     data: web::Data<AppState>,
-    user_ids: Vec<i64>,
-    message: &str,
+    user_messages: Vec<(i64, String)>,
     tracking_number_that_was_updated: &str,
+    priority: notification_priority,
 ) -> Vec<(i64, Result<(), HttpResponse>)> {
-    futures::stream::iter(user_ids.clone().into_iter().map(|user_id| {
+    let concurrency = user_messages.len();
+    futures::stream::iter(user_messages.into_iter().map(|(user_id, message)| {
         // one for each C:
         let data = data.clone();
         async move {
@@ -292,15 +325,16 @@ async fn send_notifications_to_users(
             let response = notify_of_tracking_event_update(
                 data,
                 user_id,
-                message,
+                &message,
                 tracking_number_that_was_updated,
+                priority,
             )
             .await;
             (user_id, response)
         }
     }))
     // run them all in parallel (None = unlimited concurrency)
-    .buffer_unordered(user_ids.len())
+    .buffer_unordered(concurrency)
     .collect()
     .await
 }
@@ -310,7 +344,6 @@ async fn send_notifications_to_users(
     WEBHOOK
 
     TODO: handle stopped update
-    TODO: modify the message to make the bot pull the telegram cloud stored tag and inject it in the message somewhere
 
 -------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------
 */
@@ -345,19 +378,26 @@ pub async fn handle_webhook(
     /*
         Split to two paths based on the enum value of PackageData
     */
-    if let TrackingData::PackageData(package_update) = payload.data {
+    if let TrackingData::PackageData(mut package_update) = payload.data {
+        // the same form as the numbers the clients send, so the lookups match the stored relations
+        package_update.number = number_detection::normalize_tracking_number(&package_update.number);
+
         // save the update in database in format
-        match refresh_tracking_info_from_webhook_update(client.clone(), package_update.clone())
-            .await
-        {
-            Ok(_) => {}
-            Err(response) => {
-                println!("unknown error trying to refresh database tracking info from update");
-                return response;
-            }
-        };
+        let previous_status =
+            match refresh_tracking_info_from_webhook_update(client.clone(), package_update.clone())
+                .await
+            {
+                Ok(previous_status) => previous_status,
+                Err(response) => {
+                    println!("unknown error trying to refresh database tracking info from update");
+                    return response;
+                }
+            };
         //
 
+        // update the mini apps that are open right now
+        publish_live_update(data.clone(), client.clone(), &package_update).await;
+
         // get list of users to notify of the update
         let user_ids_to_notify = match get_user_ids_related_to_tracking_number(
             client.clone(),
@@ -379,24 +419,54 @@ pub async fn handle_webhook(
             return HttpResponse::Ok().finish();
         }
 
-        // build the message that will be displayed in the chat window and notification banner
-        // dump the description
-        let message = "Update on your order tracking: ".to_string()
-            + package_update.number.as_str()
-            + "\n"
-            + package_update
-                .track_info
-                .latest_event
-                .description
-                .unwrap()
-                .as_str();
+        // failed deliveries, customs holds, returns and the like get the alert instead of the plain update
+        let alert = tracking_alert::from_transition(
+            previous_status.as_ref(),
+            &package_update.track_info.latest_status,
+        );
+        let contact = carrier_contact::from_track_info(
+            &package_update.track_info,
+            package_update.carrier,
+            &data.carrier_directory,
+        );
+        let priority = match alert {
+            Some(_) => notification_priority::High,
+            None => notification_priority::Normal,
+        };
+        //
+
+        // build the message for each user, dump the description
+        let description = package_update
+            .track_info
+            .latest_event
+            .description
+            .clone()
+            .unwrap_or_default();
+        let user_messages = user_ids_to_notify
+            .into_iter()
+            .map(|(user_id, label)| {
+                let message = match &alert {
+                    Some(alert) => alert.message(
+                        &parcel_name(&package_update.number, label.as_deref()),
+                        &description,
+                        &contact,
+                    ),
+                    None => tracking_update_message(
+                        &package_update.number,
+                        label.as_deref(),
+                        &description,
+                    ),
+                };
+                (user_id, message)
+            })
+            .collect();
 
         // call the update function on all IDs from the vector
         let notifications_results = send_notifications_to_users(
             data.clone(),
-            user_ids_to_notify,
-            &message,
+            user_messages,
             &package_update.number,
+            priority,
         )
         .await;
 